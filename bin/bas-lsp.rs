@@ -0,0 +1,11 @@
+use bas::lsp::LanguageServer;
+
+
+/// Bas language server, speak LSP over stdio
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut server = LanguageServer::new()?;
+
+    let code = server.run(std::io::stdin().lock(), std::io::stdout())?;
+
+    std::process::exit(code)
+}
@@ -1,39 +1,238 @@
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
+use bas::config::*;
+use bas::diagnostics::{report, ErrorFormat};
 use bas::driver::RunCompiler;
+use bas::doc::{doc_file, DocFormat};
+use bas::env::core_src_path;
+use bas::fmt::format_file;
+use bas::lint::{LintLevel, LintSel};
+use bas::repl::run_repl;
 use bas::shell::gen_completions;
-use clap::{IntoApp, Parser};
+use clap::{Args, ErrorKind, IntoApp, Parser, Subcommand};
 use clap_complete::Shell;
-use inkwellkit::config::*;
 
 
 /// Bas Lang Compiler
 #[derive(Parser)]
-#[clap()]
+#[clap(args_conflicts_with_subcommands = true)]
 struct Cli {
     /// Genrerate completion for bin
     #[clap(long = "generate", arg_enum)]
     generator: Option<Shell>,
 
-    // #[clap(subcommand)]
-    // command: Option<SubCommand>,
+    #[clap(subcommand)]
+    command: Option<SubCommand>,
+
     #[clap(short = 'O', arg_enum)]
     opt: Option<OptLv>,
 
     #[clap(short = 't', long = "target_type", arg_enum, default_value_t = TargetType::default())]
     target_type: TargetType,
 
-    #[clap(short = 'e', long = "emit_type", arg_enum, default_value_t = EmitType::default())]
-    emit_type: EmitType,
+    /// Emit several artifacts like `-e obj -e llvm-ir` or `-e obj,asm`,
+    /// `tokens`, `tt` and `mir` dump the front stages
+    #[clap(short = 'e', long = "emit_type", alias = "emit", arg_enum, multiple_occurrences = true, use_value_delimiter = true)]
+    emit_types: Vec<EmitType>,
+
+    /// Emit LLVM IR before or after optimization
+    #[clap(long = "ir-stage", arg_enum, default_value_t = IRStage::default())]
+    ir_stage: IRStage,
+
+    /// Run these LLVM passes in order instead of the `-O` pipeline, like
+    /// `--passes mem2reg,instcombine,inline`
+    #[clap(long = "passes", arg_enum, multiple_occurrences = true, use_value_delimiter = true)]
+    passes: Option<Vec<Pass>>,
+
+    /// Merge core lib bitcode into the module (link time optimization)
+    #[clap(long = "lto")]
+    lto: bool,
+
+    /// Target triple for cross compilation, like `aarch64-linux-gnu`
+    #[clap(long = "target")]
+    target: Option<String>,
+
+    /// Target cpu, `native` for host cpu
+    #[clap(long = "target-cpu")]
+    target_cpu: Option<String>,
+
+    /// Target feature, like `+avx2`
+    #[clap(long = "target-feature", multiple_occurrences = true)]
+    target_feature: Vec<String>,
+
+    /// Linker driver like `cc`, `clang` or `ld.lld` (default `$CC`)
+    #[clap(long = "linker")]
+    linker: Option<String>,
+
+    /// Link library
+    #[clap(short = 'l', multiple_occurrences = true)]
+    libs: Vec<String>,
+
+    /// Library search path
+    #[clap(short = 'L', multiple_occurrences = true)]
+    lib_paths: Vec<PathBuf>,
+
+    #[clap(long = "static")]
+    static_link: bool,
+
+    #[clap(long = "pie")]
+    pie: bool,
+
+    /// Generate debug info
+    #[clap(short = 'g')]
+    debug: bool,
+
+    /// Strip symbols of output
+    #[clap(long = "strip")]
+    strip: bool,
+
+    #[clap(long = "error-format", arg_enum, default_value_t = ErrorFormat::default())]
+    error_format: ErrorFormat,
+
+    #[clap(flatten)]
+    lints: LintArgs,
+
+    src: Option<PathBuf>,
+
+    output: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum SubCommand {
+    /// Run source file in process through JIT
+    Run {
+        #[clap(short = 'O', arg_enum)]
+        opt: Option<OptLv>,
+
+        #[clap(long = "error-format", arg_enum, default_value_t = ErrorFormat::default())]
+        error_format: ErrorFormat,
+
+        #[clap(flatten)]
+        lints: LintArgs,
+
+        src: PathBuf,
+    },
+    /// Interactive REPL
+    Repl {
+        #[clap(short = 'O', arg_enum)]
+        opt: Option<OptLv>,
+    },
+    /// Format source files in place
+    Fmt {
+        /// Don't write files, exit with 1 if any file is not formatted
+        #[clap(long = "check")]
+        check: bool,
+
+        #[clap(required = true)]
+        srcs: Vec<PathBuf>,
+    },
+    /// Write documentation pages of exported functions
+    Doc {
+        #[clap(long = "format", arg_enum, default_value_t = DocFormat::default())]
+        format: DocFormat,
+
+        /// Directory of pages
+        #[clap(short = 'o', long = "out-dir", default_value = "doc")]
+        out_dir: PathBuf,
+
+        /// Document the core lib too
+        #[clap(long = "core")]
+        core: bool,
+
+        #[clap(required_unless_present = "core")]
+        srcs: Vec<PathBuf>,
+    },
+    /// Build and run `@test` functions of source file
+    Test {
+        #[clap(short = 'O', arg_enum)]
+        opt: Option<OptLv>,
+
+        /// Run only tests whose name exactly matches a filter
+        #[clap(long = "exact")]
+        exact: bool,
+
+        #[clap(long = "error-format", arg_enum, default_value_t = ErrorFormat::default())]
+        error_format: ErrorFormat,
+
+        #[clap(flatten)]
+        lints: LintArgs,
+
+        src: PathBuf,
+
+        /// Run only tests whose name contains any filter
+        filters: Vec<String>,
+    },
+    /// Build (`-O3` by default) and measure `@bench` functions of source
+    /// file
+    Bench {
+        #[clap(short = 'O', arg_enum)]
+        opt: Option<OptLv>,
+
+        /// Run only benchmarks whose name exactly matches a filter
+        #[clap(long = "exact")]
+        exact: bool,
+
+        /// Compare with the baseline file
+        #[clap(long = "baseline")]
+        baseline: Option<PathBuf>,
+
+        /// Save results as the baseline file
+        #[clap(long = "save-baseline")]
+        save_baseline: Option<PathBuf>,
+
+        #[clap(long = "error-format", arg_enum, default_value_t = ErrorFormat::default())]
+        error_format: ErrorFormat,
+
+        #[clap(flatten)]
+        lints: LintArgs,
+
+        src: PathBuf,
+
+        /// Run only benchmarks whose name contains any filter
+        filters: Vec<String>,
+    },
+}
+
+/// Lint levels, `warnings` selects all lints
+///
+/// When a lint is selected several times, `-D` wins over `-W` over `-A`
+#[derive(Args)]
+struct LintArgs {
+    /// Allow lint
+    #[clap(short = 'A', multiple_occurrences = true)]
+    allow: Vec<LintSel>,
 
-    src: PathBuf,
+    /// Warn lint
+    #[clap(short = 'W', multiple_occurrences = true)]
+    warn: Vec<LintSel>,
 
-    output: PathBuf,
+    /// Deny lint
+    #[clap(short = 'D', multiple_occurrences = true)]
+    deny: Vec<LintSel>,
 }
 
-// #[derive(Subcommand)]
-// enum SubCommand {
-// }
+
+impl LintArgs {
+    fn diag_config(self, error_format: ErrorFormat) -> DiagConfig {
+        let levels = [
+            (self.allow, LintLevel::Allow),
+            (self.warn, LintLevel::Warn),
+            (self.deny, LintLevel::Deny),
+        ];
+
+        DiagConfig {
+            error_format,
+            lint_levels: levels
+                .into_iter()
+                .flat_map(|(sels, level)| {
+                    sels.into_iter().map(move |sel| (sel, level))
+                })
+                .collect(),
+        }
+    }
+}
 
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -45,23 +244,254 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
+    if let Some(command) = cli.command {
+        match command {
+            SubCommand::Run {
+                opt,
+                error_format,
+                lints,
+                src,
+            } => {
+                let diag = lints.diag_config(error_format);
+
+                match RunCompiler::jit(
+                    &src,
+                    opt.unwrap_or(OptLv::Debug),
+                    &diag,
+                ) {
+                    Ok(code) => std::process::exit(code),
+                    Err(err) => {
+                        report(&*err, error_format);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            SubCommand::Repl { opt } => {
+                return run_repl(opt.unwrap_or(OptLv::Debug));
+            }
+            SubCommand::Fmt { check, srcs } => {
+                std::process::exit(run_fmt(check, &srcs));
+            }
+            SubCommand::Doc {
+                format,
+                out_dir,
+                core,
+                mut srcs,
+            } => {
+                if core {
+                    srcs.insert(0, core_src_path()?);
+                }
+
+                std::process::exit(run_doc(format, &out_dir, &srcs));
+            }
+            SubCommand::Test {
+                opt,
+                exact,
+                error_format,
+                lints,
+                src,
+                filters,
+            } => {
+                let config = CompilerConfig {
+                    optlv: opt.unwrap_or(OptLv::Debug),
+                    harness: Some(Harness::Test),
+                    diag: lints.diag_config(error_format),
+                    ..Default::default()
+                };
+                let mut args = vec![];
+
+                if exact {
+                    args.push("--exact".into());
+                }
+                args.extend(filters.into_iter().map(Into::into));
+
+                match run_harness(&src, config, &args) {
+                    Ok(code) => std::process::exit(code),
+                    Err(err) => {
+                        report(&*err, error_format);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            SubCommand::Bench {
+                opt,
+                exact,
+                baseline,
+                save_baseline,
+                error_format,
+                lints,
+                src,
+                filters,
+            } => {
+                let config = CompilerConfig {
+                    optlv: opt.unwrap_or(OptLv::Opt3),
+                    harness: Some(Harness::Bench),
+                    diag: lints.diag_config(error_format),
+                    ..Default::default()
+                };
+                let mut args = vec![];
+
+                if exact {
+                    args.push("--exact".into());
+                }
+                if let Some(baseline) = baseline {
+                    args.push("--baseline".into());
+                    args.push(baseline.into());
+                }
+                if let Some(save_baseline) = save_baseline {
+                    args.push("--save-baseline".into());
+                    args.push(save_baseline.into());
+                }
+                args.extend(filters.into_iter().map(Into::into));
+
+                match run_harness(&src, config, &args) {
+                    Ok(code) => std::process::exit(code),
+                    Err(err) => {
+                        report(&*err, error_format);
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+    }
+
+    let (src, output) = match (cli.src, cli.output) {
+        (Some(src), Some(output)) => (src, output),
+        _ => Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "<SRC> and <OUTPUT> are required",
+            )
+            .exit(),
+    };
+
     let optlv = cli.opt.unwrap_or(OptLv::Debug);
     let target_type = cli.target_type;
-    let emit_type = cli.emit_type;
-    let print_type = if cli.output == PathBuf::from("stderr") {
+    let emit_types = if cli.emit_types.is_empty() {
+        vec![EmitType::default()]
+    } else {
+        cli.emit_types
+    };
+    let print_type = if output == PathBuf::from("stderr") {
         PrintTy::StdErr
     } else {
-        PrintTy::File(cli.output)
+        PrintTy::File(output)
     };
 
     let config = CompilerConfig {
         optlv,
         target_type,
-        emit_type,
+        emit_types,
+        ir_stage: cli.ir_stage,
+        passes: PassConfig {
+            custom: cli.passes,
+            lto: cli.lto,
+        },
         print_type,
+        target: TargetSpec {
+            triple: cli.target,
+            cpu: cli.target_cpu,
+            features: cli.target_feature,
+        },
+        linker: LinkerConfig {
+            linker: cli.linker,
+            libs: cli.libs,
+            lib_paths: cli.lib_paths,
+            static_link: cli.static_link,
+            pie: cli.pie,
+            strip: cli.strip,
+        },
+        debug: cli.debug,
+        harness: None,
+        diag: cli.lints.diag_config(cli.error_format),
     };
 
-    RunCompiler::new(&cli.src, config)?;
+    if let Err(msg) = config.check_emit_paths() {
+        Cli::command().error(ErrorKind::ArgumentConflict, msg).exit()
+    }
+
+    if let Err(err) = RunCompiler::new(&src, config) {
+        report(&*err, cli.error_format);
+        std::process::exit(1);
+    }
 
     Ok(())
 }
+
+
+/// Exit code: 1 for errors or unformatted files in check mode
+fn run_fmt(check: bool, srcs: &[PathBuf]) -> i32 {
+    let mut code = 0;
+
+    for path in srcs.iter() {
+        let formatted = match format_file(path) {
+            Ok(Some(formatted)) => formatted,
+            Ok(None) => continue,
+            Err(err) => {
+                report(&*err, ErrorFormat::Human);
+                code = 1;
+                continue;
+            }
+        };
+
+        if check {
+            println!("{} is not formatted", path.display());
+            code = 1;
+        } else if let Err(err) = std::fs::write(path, formatted) {
+            eprintln!("error: write {}: {err}", path.display());
+            code = 1;
+        }
+    }
+
+    code
+}
+
+
+/// Write `{out_dir}/{stem}.{md|html}` for each source, exit code: 1 for
+/// errors
+fn run_doc(format: DocFormat, out_dir: &Path, srcs: &[PathBuf]) -> i32 {
+    if let Err(err) = std::fs::create_dir_all(out_dir) {
+        eprintln!("error: create {}: {err}", out_dir.display());
+        return 1;
+    }
+
+    let mut code = 0;
+
+    for path in srcs.iter() {
+        let page = match doc_file(path, format) {
+            Ok(page) => page,
+            Err(err) => {
+                report(&*err, ErrorFormat::Human);
+                code = 1;
+                continue;
+            }
+        };
+
+        let out = out_dir
+            .join(path.file_stem().unwrap_or_default())
+            .with_extension(format.extension());
+
+        if let Err(err) = std::fs::write(&out, page) {
+            eprintln!("error: write {}: {err}", out.display());
+            code = 1;
+        } else {
+            println!("{}", out.display());
+        }
+    }
+
+    code
+}
+
+
+/// Build harness of `src` into temp dir and run it with `args`
+fn run_harness(
+    src: &Path,
+    config: CompilerConfig,
+    args: &[OsString],
+) -> Result<i32, Box<dyn std::error::Error>> {
+    RunCompiler::with_exe(&src, config, |exe| {
+        let status = Command::new(exe).args(args).status()?;
+
+        Ok(status.code().unwrap_or(101))
+    })
+}
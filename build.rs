@@ -0,0 +1,54 @@
+//! Build runtime (clib) so that it can be embedded into the compiler
+
+use std::{env, fs, io, path::Path, path::PathBuf, process::Command};
+
+
+fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    // build a copy in OUT_DIR, keep source tree clean
+    let build_dir = out_dir.join("clib");
+
+    for sub in ["src", "include"] {
+        copy_dir(&Path::new("clib").join(sub), &build_dir.join(sub))
+            .expect("Copy clib failed");
+    }
+    fs::copy("clib/Makefile", build_dir.join("Makefile"))
+        .expect("Copy clib failed");
+
+    let status = Command::new("make")
+        .arg("-C")
+        .arg(&build_dir)
+        .arg("libbas.a")
+        .arg("libbas.so")
+        .status()
+        .expect("Run make for clib failed");
+
+    if !status.success() {
+        panic!("Build clib failed: {status}");
+    }
+
+    for name in ["libbas.a", "libbas.so"] {
+        fs::copy(build_dir.join(name), out_dir.join(name)).unwrap();
+    }
+
+    println!("cargo:rerun-if-changed=clib/src");
+    println!("cargo:rerun-if-changed=clib/include");
+    println!("cargo:rerun-if-changed=clib/Makefile");
+    println!("cargo:rerun-if-changed=boostrap/core.bath");
+}
+
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+
+        if entry.file_type()?.is_file() {
+            fs::copy(entry.path(), to.join(entry.file_name()))?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,13 @@
+#ifndef BENCH_H
+
+#define BENCH_H
+
+#include <stdint.h>
+
+int32_t black_box(int32_t x);
+
+int bas_bench_main(
+  int argc, char** argv, int n, const char** names, void** fns
+);
+
+#endif
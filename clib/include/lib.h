@@ -9,6 +9,22 @@ char* stringify_i32(int32_t src);
 char* stringify_f64(double src);
 
 char* cmd_symbols_replace(char* src, Vec syms, Vec strs);
-char* exec(const char* cmd);
+/*
+* Result of a command: captured stdout, stderr (empty if it isn't captured)
+* and exit status
+*/
+typedef struct CmdRes {
+  char* out;
+  char* err;
+  int32_t status;
+} *CmdRes;
+
+CmdRes exec(const char* cmd, int32_t capture_err);
+int32_t exec_stream(const char* cmd);
+
+char* cmd_stdout(CmdRes res);
+char* cmd_stderr(CmdRes res);
+int32_t cmd_status(CmdRes res);
+void cmd_drop(CmdRes res);
 
 #endif
@@ -0,0 +1,25 @@
+#ifndef TEST_H
+
+#define TEST_H
+
+#include <stdint.h>
+#include <stdbool.h>
+
+void bas_assert(int32_t cond, const char* msg);
+void bas_assert_eq_i32(int32_t left, int32_t right, const char* msg);
+void bas_assert_eq_f64(double left, double right, const char* msg);
+void bas_assert_eq_str(const char* left, const char* right, const char* msg);
+
+/*
+* Name is selected if it contains (or equals in exact mode) any filter,
+* all are selected without filters
+*/
+bool bas_harness_selected(
+  const char* name, int nfilters, char** filters, bool exact
+);
+
+int bas_test_main(
+  int argc, char** argv, int n, const char** names, void** fns
+);
+
+#endif
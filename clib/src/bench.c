@@ -0,0 +1,274 @@
+#define _POSIX_C_SOURCE 200809L
+
+#include <stdlib.h>
+#include <stdio.h>
+#include <stdint.h>
+#include <stdbool.h>
+#include <string.h>
+#include <time.h>
+
+#include "../include/bench.h"
+#include "../include/test.h"
+
+
+typedef void (*BenchFn)(int32_t);
+
+/* each sample runs at least such long */
+#define SAMPLE_NS 5e6
+#define NSAMPLES 20
+#define MAX_ITERS (1 << 30)
+#define NAME_LEN 256
+
+
+typedef struct {
+  char name[NAME_LEN];
+  double mean;
+  double median;
+  double dev;
+  /* calibrated iterations of each sample, not saved in baseline */
+  int32_t iters;
+} BenchStat;
+
+
+/*
+* Return `x` unchanged, the empty asm hides it from optimizer so that the
+* benchmarked code producing it is kept
+*/
+int32_t black_box(int32_t x) {
+  __asm__ volatile("" : : "r"(x) : "memory");
+
+  return x;
+}
+
+
+static double _now_ns() {
+  struct timespec ts;
+  clock_gettime(CLOCK_MONOTONIC, &ts);
+
+  return ts.tv_sec * 1e9 + ts.tv_nsec;
+}
+
+static double _run(BenchFn fn, int32_t iters) {
+  double start = _now_ns();
+  fn(iters);
+
+  return _now_ns() - start;
+}
+
+/* Newton's method, to not depend on libm */
+static double _sqrt(double x) {
+  if (x <= 0) return 0;
+
+  double r = x;
+  for (int i = 0; i < 64; i++) r = (r + x / r) / 2;
+
+  return r;
+}
+
+static int _cmp_f64(const void* a, const void* b) {
+  double x = *(const double*)a;
+  double y = *(const double*)b;
+
+  return (x > y) - (x < y);
+}
+
+/*
+* Double the iteration count until a run lasts SAMPLE_NS (it warms up too),
+* then take NSAMPLES samples of ns per iteration
+*/
+static void _bench(BenchFn fn, BenchStat* stat) {
+  int32_t iters = 1;
+
+  while (_run(fn, iters) < SAMPLE_NS && iters < MAX_ITERS) iters *= 2;
+
+  stat->iters = iters;
+
+  double samples[NSAMPLES];
+  double sum = 0;
+
+  for (int i = 0; i < NSAMPLES; i++) {
+    samples[i] = _run(fn, iters) / iters;
+    sum += samples[i];
+  }
+
+  stat->mean = sum / NSAMPLES;
+
+  double var = 0;
+  for (int i = 0; i < NSAMPLES; i++) {
+    var += (samples[i] - stat->mean) * (samples[i] - stat->mean);
+  }
+  stat->dev = _sqrt(var / (NSAMPLES - 1));
+
+  qsort(samples, NSAMPLES, sizeof(double), _cmp_f64);
+  stat->median = (samples[NSAMPLES / 2 - 1] + samples[NSAMPLES / 2]) / 2;
+}
+
+/*
+* Baseline file: one `name mean median dev` per line,
+* return count of loaded entries or -1 if it can't be opened
+*/
+static int _load_baseline(const char* path, BenchStat** out) {
+  FILE* f = fopen(path, "r");
+  if (f == NULL) return -1;
+
+  int cap = 16;
+  int n = 0;
+  BenchStat* stats = malloc(sizeof(BenchStat) * cap);
+  BenchStat stat;
+
+  while (fscanf(
+    f, "%255s %lf %lf %lf", stat.name, &stat.mean, &stat.median, &stat.dev
+  ) == 4) {
+    if (n == cap) {
+      cap *= 2;
+      stats = realloc(stats, sizeof(BenchStat) * cap);
+    }
+    stats[n++] = stat;
+  }
+
+  fclose(f);
+  *out = stats;
+
+  return n;
+}
+
+static const BenchStat* _find_stat(
+  const BenchStat* stats, int n, const char* name
+) {
+  for (int i = 0; i < n; i++) {
+    if (strcmp(stats[i].name, name) == 0) return &stats[i];
+  }
+
+  return NULL;
+}
+
+
+/*
+* Entry of bench harness, `main` generated by `basc bench` calls it.
+*
+* Usage: <harness> [--exact] [--baseline FILE] [--save-baseline FILE]
+*                  [FILTER]...
+*
+* Report mean, median and standard deviation of ns per iteration, compare
+* them with the baseline and save them as a new baseline.
+*/
+int bas_bench_main(
+  int argc, char** argv, int n, const char** names, void** fns
+) {
+  bool exact = false;
+  const char* baseline_path = NULL;
+  const char* save_path = NULL;
+  char** filters = malloc(sizeof(char*) * argc);
+  int nfilters = 0;
+
+  for (int i = 1; i < argc; i++) {
+    if (strcmp(argv[i], "--exact") == 0) exact = true;
+    else if (strcmp(argv[i], "--baseline") == 0 && i + 1 < argc) {
+      baseline_path = argv[++i];
+    }
+    else if (strcmp(argv[i], "--save-baseline") == 0 && i + 1 < argc) {
+      save_path = argv[++i];
+    }
+    else filters[nfilters++] = argv[i];
+  }
+
+  BenchStat* baseline = NULL;
+  int nbaseline = 0;
+
+  if (baseline_path != NULL) {
+    nbaseline = _load_baseline(baseline_path, &baseline);
+
+    if (nbaseline < 0) {
+      fprintf(stderr, "can't open baseline %s\n", baseline_path);
+      free(filters);
+      return 1;
+    }
+  }
+
+  BenchStat* stats = malloc(sizeof(BenchStat) * (n > 0 ? n : 1));
+  int nstats = 0;
+
+  int nselected = 0;
+  for (int i = 0; i < n; i++) {
+    if (bas_harness_selected(names[i], nfilters, filters, exact)) {
+      nselected++;
+    }
+  }
+
+  printf("\nrunning %d benchmark%s\n", nselected, nselected == 1 ? "" : "s");
+
+  for (int i = 0; i < n; i++) {
+    if (!bas_harness_selected(names[i], nfilters, filters, exact)) continue;
+
+    BenchStat* stat = &stats[nstats++];
+    snprintf(stat->name, NAME_LEN, "%s", names[i]);
+
+    printf("bench %s ... ", names[i]);
+    fflush(stdout);
+
+    _bench((BenchFn)fns[i], stat);
+
+    printf(
+      "%.2f ns/iter (median %.2f, +/- %.2f, %d iters)",
+      stat->mean,
+      stat->median,
+      stat->dev,
+      stat->iters
+    );
+
+    if (baseline != NULL) {
+      const BenchStat* base = _find_stat(baseline, nbaseline, names[i]);
+
+      if (base == NULL) {
+        printf(" [no baseline]");
+      }
+      else {
+        printf(
+          " [%+.2f%% from %.2f]",
+          (stat->mean - base->mean) / base->mean * 100,
+          base->mean
+        );
+      }
+    }
+
+    printf("\n");
+  }
+
+  int code = 0;
+
+  if (save_path != NULL) {
+    FILE* f = fopen(save_path, "w");
+
+    if (f == NULL) {
+      fprintf(stderr, "can't write baseline %s\n", save_path);
+      code = 1;
+    }
+    else {
+      for (int i = 0; i < nstats; i++) {
+        fprintf(
+          f,
+          "%s %lf %lf %lf\n",
+          stats[i].name,
+          stats[i].mean,
+          stats[i].median,
+          stats[i].dev
+        );
+      }
+      fclose(f);
+
+      printf("\nbaseline saved to %s\n", save_path);
+    }
+  }
+
+  printf(
+    "\nbench result: %d measured; %d filtered out\n\n",
+    nstats,
+    n - nselected
+  );
+
+  free(filters);
+  free(stats);
+  free(baseline);
+
+  return code;
+}
@@ -3,9 +3,12 @@
 #include <stdint.h>
 #include <string.h>
 #include <assert.h>
+#include <unistd.h>
+#include <sys/wait.h>
 
 #include "../include/vec.h"
 #include "../include/cstr.h"
+#include "../include/lib.h"
 
 
 char* stringify_i32(int32_t src) {
@@ -94,16 +97,121 @@ char* cmd_symbols_replace(char* src, Vec syms, Vec strs) {
 }
 
 
-char* exec(const char* cmd) {
-  char buffer[128];
+/*
+* Exit status like shell, 128 + signal number if it's killed
+*/
+static int32_t _wait_status(int wstatus) {
+  if (wstatus == -1) return -1;
+  if (WIFEXITED(wstatus)) return WEXITSTATUS(wstatus);
+  if (WIFSIGNALED(wstatus)) return 128 + WTERMSIG(wstatus);
 
+  return wstatus;
+}
+
+static char* _read_all(FILE* f) {
+  char buffer[128];
   CStr result = cstr_empty();
-  FILE* pipe = popen(cmd, "r");
 
-  while (fgets(buffer, sizeof buffer, pipe) != NULL) {
+  while (fgets(buffer, sizeof buffer, f) != NULL) {
     cstr_push(result, buffer);
   }
 
-  pclose(pipe);
   return cstr_into(result);
 }
+
+/*
+* Run `cmd` by shell, capture its stdout (and stderr into a temporary file
+* under `TMPDIR` if `capture_err`, or else it goes to terminal)
+*
+* return Heap Alloc result (need to drop it by `cmd_drop`)
+*/
+CmdRes exec(const char* cmd, int32_t capture_err) {
+  CmdRes res = malloc(sizeof(struct CmdRes));
+  assert(res != NULL);
+
+  char* errpath = NULL;
+  int errfd = -1;
+  char* shcmd;
+
+  if (capture_err) {
+    const char* tmpdir = getenv("TMPDIR");
+    if (tmpdir == NULL || *tmpdir == '\0') tmpdir = "/tmp";
+
+    errpath = malloc(strlen(tmpdir) + 32);
+    assert(errpath != NULL);
+    sprintf(errpath, "%s/bas-stderr-XXXXXX", tmpdir);
+
+    errfd = mkstemp(errpath);
+    assert(errfd >= 0);
+
+    // newline ends a trailing comment of cmd
+    shcmd = malloc(strlen(cmd) + strlen(errpath) + 16);
+    assert(shcmd != NULL);
+    sprintf(shcmd, "{ %s\n} 2>'%s'", cmd, errpath);
+  }
+  else {
+    shcmd = strdup(cmd);
+  }
+
+  fflush(stdout);
+  FILE* pipe = popen(shcmd, "r");
+  assert(pipe != NULL);
+
+  res->out = _read_all(pipe);
+  res->status = _wait_status(pclose(pipe));
+
+  if (capture_err) {
+    FILE* errf = fdopen(errfd, "r");
+    res->err = _read_all(errf);
+    fclose(errf);
+    unlink(errpath);
+    free(errpath);
+  }
+  else {
+    res->err = strdup("");
+  }
+
+  free(shcmd);
+
+  return res;
+}
+
+/*
+* Run `cmd` by shell with output streaming to terminal, return exit status
+*/
+int32_t exec_stream(const char* cmd) {
+  fflush(stdout);
+
+  return _wait_status(system(cmd));
+}
+
+/*
+* Output is borrowed from `res`, it's freed by `cmd_drop`
+*/
+char* cmd_stdout(CmdRes res) {
+  return res->out;
+}
+
+char* cmd_stderr(CmdRes res) {
+  return res->err;
+}
+
+int32_t cmd_status(CmdRes res) {
+  return res->status;
+}
+
+void cmd_drop(CmdRes res) {
+  free(res->out);
+  free(res->err);
+  free(res);
+}
+
+
+/*
+* Abort on failed runtime check (generated in debug mode)
+*/
+void bas_panic(const char* msg) {
+  fflush(stdout);
+  fprintf(stderr, "panic: %s\n", msg);
+  abort();
+}
@@ -0,0 +1,179 @@
+#define _POSIX_C_SOURCE 200809L
+
+#include <stdlib.h>
+#include <stdio.h>
+#include <stdint.h>
+#include <stdbool.h>
+#include <string.h>
+#include <time.h>
+#include <unistd.h>
+#include <sys/wait.h>
+
+#include "../include/test.h"
+
+
+typedef void (*TestFn)(void);
+
+
+static void _assert_failed(const char* msg) {
+  fflush(stdout);
+  fprintf(stderr, "assertion failed: %s\n", msg);
+}
+
+void bas_assert(int32_t cond, const char* msg) {
+  if (!cond) {
+    _assert_failed(msg);
+    abort();
+  }
+}
+
+void bas_assert_eq_i32(int32_t left, int32_t right, const char* msg) {
+  if (left != right) {
+    _assert_failed(msg);
+    fprintf(stderr, "  left: %d\n right: %d\n", left, right);
+    abort();
+  }
+}
+
+void bas_assert_eq_f64(double left, double right, const char* msg) {
+  if (left != right) {
+    _assert_failed(msg);
+    fprintf(stderr, "  left: %lf\n right: %lf\n", left, right);
+    abort();
+  }
+}
+
+void bas_assert_eq_str(const char* left, const char* right, const char* msg) {
+  if (left == right) return;
+
+  if (left == NULL || right == NULL || strcmp(left, right) != 0) {
+    _assert_failed(msg);
+    fprintf(
+      stderr,
+      "  left: \"%s\"\n right: \"%s\"\n",
+      left ? left : "(null)",
+      right ? right : "(null)"
+    );
+    abort();
+  }
+}
+
+
+static double _now_ms() {
+  struct timespec ts;
+  clock_gettime(CLOCK_MONOTONIC, &ts);
+
+  return ts.tv_sec * 1e3 + ts.tv_nsec / 1e6;
+}
+
+bool bas_harness_selected(
+  const char* name, int nfilters, char** filters, bool exact
+) {
+  if (nfilters == 0) return true;
+
+  for (int i = 0; i < nfilters; i++) {
+    bool matched = exact
+      ? strcmp(name, filters[i]) == 0
+      : strstr(name, filters[i]) != NULL;
+
+    if (matched) return true;
+  }
+
+  return false;
+}
+
+/*
+* Entry of test harness, `main` generated by `basc test` calls it.
+*
+* Usage: <harness> [--exact] [FILTER]...
+*
+* Run each selected test in a forked child so that a crash (failed assertion
+* aborts) only fails that test, return 1 if any test failed.
+*/
+int bas_test_main(
+  int argc, char** argv, int n, const char** names, void** fns
+) {
+  bool exact = false;
+  char** filters = malloc(sizeof(char*) * argc);
+  int nfilters = 0;
+
+  for (int i = 1; i < argc; i++) {
+    if (strcmp(argv[i], "--exact") == 0) exact = true;
+    else filters[nfilters++] = argv[i];
+  }
+
+  int nselected = 0;
+  for (int i = 0; i < n; i++) {
+    if (bas_harness_selected(names[i], nfilters, filters, exact)) nselected++;
+  }
+
+  printf("\nrunning %d test%s\n", nselected, nselected == 1 ? "" : "s");
+
+  const char** failures = malloc(sizeof(char*) * (n > 0 ? n : 1));
+  int passed = 0;
+  int failed = 0;
+  double start = _now_ms();
+
+  for (int i = 0; i < n; i++) {
+    if (!bas_harness_selected(names[i], nfilters, filters, exact)) continue;
+
+    // don't duplicate buffered output in child
+    fflush(NULL);
+
+    double test_start = _now_ms();
+    pid_t pid = fork();
+
+    if (pid == 0) {
+      ((TestFn)fns[i])();
+      fflush(NULL);
+      _exit(0);
+    }
+
+    int status = 0;
+    bool ok = pid > 0
+      && waitpid(pid, &status, 0) == pid
+      && WIFEXITED(status)
+      && WEXITSTATUS(status) == 0;
+    double elapsed = _now_ms() - test_start;
+
+    if (ok) {
+      printf("test %s ... ok (%.2f ms)\n", names[i], elapsed);
+      passed++;
+      continue;
+    }
+
+    printf("test %s ... FAILED (%.2f ms)\n", names[i], elapsed);
+    failures[failed++] = names[i];
+
+    if (pid < 0) {
+      perror("  fork");
+    }
+    else if (WIFSIGNALED(status)) {
+      printf("  killed by signal %d (%s)\n",
+        WTERMSIG(status), strsignal(WTERMSIG(status)));
+    }
+    else if (WIFEXITED(status)) {
+      printf("  exited with %d\n", WEXITSTATUS(status));
+    }
+  }
+
+  if (failed > 0) {
+    printf("\nfailures:\n");
+    for (int i = 0; i < failed; i++) printf("    %s\n", failures[i]);
+  }
+
+  printf(
+    "\ntest result: %s. %d passed; %d failed; %d filtered out; "
+    "finished in %.2f ms\n\n",
+    failed > 0 ? "FAILED" : "ok",
+    passed,
+    failed,
+    n - nselected,
+    _now_ms() - start
+  );
+
+  free(filters);
+  free(failures);
+
+  return failed > 0 ? 1 : 0;
+}
@@ -1,5 +1,6 @@
 #include <stdio.h>
 #include <string.h>
+#include <assert.h>
 #include "../include/vec.h"
 #include "../include/aa.h"
 #include "../include/cstr.h"
@@ -47,10 +48,20 @@ void test_sym_replace() {
 
 
 void test_sh_call() {
-  char* res;
-  // res = exec("sh -c ls -a");
-  res = exec("echo -n Count :");
-  // ps(res)
+  CmdRes res;
+  // res = exec("sh -c ls -a", 0);
+  res = exec("echo -n Count :", 0);
+  assert(strcmp(cmd_stdout(res), "Count :") == 0);
+  assert(cmd_status(res) == 0);
+  cmd_drop(res);
+
+  res = exec("echo oops >&2; exit 3", 1);
+  assert(strcmp(cmd_stdout(res), "") == 0);
+  assert(strcmp(cmd_stderr(res), "oops\n") == 0);
+  assert(cmd_status(res) == 3);
+  cmd_drop(res);
+
+  assert(exec_stream("exit 2") == 2);
 }
 
 void test_cstr() {
@@ -6,10 +6,10 @@ use inkwellkit::{
     types::{FloatType, IntType},
 };
 use m6entry::KVEntry as Entry;
-use m6lexerkit::{str2sym, sym2str, Symbol, Token};
+use m6lexerkit::{str2sym, sym2str, Span, Symbol, Token};
 
 use super::MIR;
-use crate::parser::SyntaxType as ST;
+use crate::{lint::Lint, parser::SyntaxType as ST};
 
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -22,17 +22,21 @@ use crate::parser::SyntaxType as ST;
 #[derive(Debug, Clone, Copy)]
 pub enum ATag {
     RAW,
+    /// `err#` of command expression, capture stderr
+    ERR,
     PH
 }
 
 
 /// Exported Symbol Set
+#[derive(Clone)]
 pub struct ExtSymSet {
     pub mods: Vec<AModExp>,
 }
 
 
 /// An Exported Mod
+#[derive(Clone)]
 pub struct AModExp {
     pub afns: IndexMap<Symbol, AnExtFnDec>,
 }
@@ -45,17 +49,20 @@ pub struct AnExtFnDec {
     pub params: Vec<AParamPat>,
     pub ret: AType,
     pub symbol_name: Symbol,
+    /// `##` comments above the item
+    pub doc: Option<String>,
 }
 
 
 pub struct AFnDec {
-    #[allow(unused)]
     pub idt: Token, // Identifier Token
     pub attrs: A3ttrs,
     // body_idx: Option<usize>,
     pub name: Symbol,
     pub params: Vec<AParamPat>,
     pub ret: AType,
+    /// `##` comments above the item
+    pub doc: Option<String>,
 }
 
 
@@ -68,12 +75,21 @@ pub struct A3ttrs(pub IndexMap<A3ttrName, A3ttrVal>);
 pub enum A3ttrName {
     NoMangle,
     VarArg,
+    /// Unit test run by `basc test`
+    Test,
+    /// Benchmark run by `basc bench`, takes the iteration count
+    Bench,
+    Allow,
+    Warn,
+    Deny,
 }
 
 
 #[derive(Debug, Clone)]
 pub enum A3ttrVal {
     Empty,
+    /// `@allow(..)`, `@warn(..)`, `@deny(..)`
+    Lints(Vec<Lint>),
 }
 
 
@@ -81,9 +97,17 @@ pub struct AMod {
     pub(crate) name: Symbol,
     /// External Declare
     pub(crate) efns: IndexMap<Symbol, AnExtFnDec>,
+    /// External Declare - identifier span
+    pub(crate) efn_spans: IndexMap<Symbol, Span>,
     /// Local Definition
     pub(crate) afns: IndexMap<Symbol, AFnDec>,
     pub(crate) allocs: IndexMap<Symbol, AFnAlloc>,
+    /// Module level variables (used by REPL session)
+    pub(crate) globals: AFnAlloc,
+    /// External module level variables
+    pub(crate) eglobals: AFnAlloc,
+    /// fn name - (sym, tagid) - declaration span
+    pub(crate) alloc_spans: IndexMap<Symbol, IndexMap<(Symbol, usize), Span>>,
     pub(crate) scopes: Vec<AScope>, // Start from Root Scope
 }
 
@@ -100,9 +124,13 @@ pub(crate) struct AScope {
     /// 语法结构中间值
     pub(crate) implicit_bindings: IndexMap<Symbol, usize>,
     pub(crate) mirs: Vec<MIR>,
+    /// implicit value - drop fn (full name)
+    ///
+    /// Heap memory allocated by runtime, dropped in reverse order when the
+    /// scope exits
+    pub(crate) owned: Vec<(Symbol, Symbol)>,
 
     pub(crate) tail: AVar,
-    pub(crate) break_var: Option<AVar>,
     pub(crate) ret_var: Option<AVar>
 }
 
@@ -172,6 +200,8 @@ pub(crate) enum AVal {
     },
     TypeCast {
         name: Symbol,
+        /// type of `name`
+        from: AType,
         ty: AType,
     },
     ConstAlias(ConstVal),
@@ -195,10 +225,25 @@ pub(crate) struct AVar {
 }
 
 
+/// Name in source resolved by pass2, used by language server
+#[derive(Debug, Clone)]
+pub(crate) enum ARef {
+    /// Read or write of local variable or param
+    Binding(AVar),
+    /// Call of function (full name)
+    Fn(Symbol),
+}
+
+
+/// (name span, enclosing fn, ref)
+pub(crate) type RefLists = Vec<(Span, Symbol, ARef)>;
+
+
 #[derive(Debug, Clone)]
 pub struct AParamPat {
     pub formal: Symbol,
     pub ty: AType,
+    pub span: Span,
 }
 
 
@@ -267,6 +312,40 @@ impl A3ttrs {
     pub fn has(&self, name: A3ttrName) -> bool {
         self.get_attr(name).is_some()
     }
+
+    /// Source form like `@no_mangle`, `@allow(shadowing)`
+    pub fn to_src(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .map(|(name, val)| match val {
+                A3ttrVal::Empty => format!("@{}", name.name()),
+                A3ttrVal::Lints(lints) => format!(
+                    "@{}({})",
+                    name.name(),
+                    lints
+                        .iter()
+                        .map(|lint| lint.name())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            })
+            .collect()
+    }
+}
+
+
+impl A3ttrName {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::NoMangle => "no_mangle",
+            Self::VarArg => "vararg",
+            Self::Test => "test",
+            Self::Bench => "bench",
+            Self::Allow => "allow",
+            Self::Warn => "warn",
+            Self::Deny => "deny",
+        }
+    }
 }
 
 
@@ -340,6 +419,7 @@ impl std::fmt::Debug for AMod {
             .field("name", &self.name)
             .field("afns", &self.afns)
             .field("allocs", &self.allocs)
+            .field("globals", &self.globals)
             .field("scopes", &AScopeVec(&self.scopes))
             .finish()
     }
@@ -353,6 +433,10 @@ impl AMod {
             efns: indexmap! {},
             afns: indexmap! {},
             allocs: indexmap! {},
+            globals: indexmap! {},
+            eglobals: indexmap! {},
+            alloc_spans: indexmap! {},
+            efn_spans: indexmap! {},
             scopes: vec![AScope::default()], // push Root Scope
         }
     }
@@ -373,6 +457,40 @@ impl AMod {
 
         AModExp { afns }
     }
+
+    /// Body scope of analyzed fn `name` (full name)
+    pub(crate) fn fn_scope_idx(&self, name: Symbol) -> Option<usize> {
+        self.scopes[0].mirs.iter().find_map(|mir| match mir.val {
+            AVal::DefFn {
+                name: fn_name,
+                scope_idx,
+            } if fn_name == name => Some(scope_idx),
+            _ => None,
+        })
+    }
+
+    /// Type of explicit binding `val` (param or local variable) of fn `name`
+    pub(crate) fn binding_ty(
+        &self,
+        name: Symbol,
+        val: &AVal,
+    ) -> Option<AType> {
+        let scope_idx = self.fn_scope_idx(name)?;
+
+        // scopes of a fn are pushed continuously
+        self.scopes[scope_idx..]
+            .iter()
+            .flat_map(|scope| scope.explicit_bindings.iter())
+            .map(|Entry(_sym, (_tagid, var))| var)
+            .find(|var| match (&var.val, val) {
+                (AVal::Var(sym1, tagid1), AVal::Var(sym2, tagid2)) => {
+                    sym1 == sym2 && tagid1 == tagid2
+                }
+                (AVal::FnParam(idx1), AVal::FnParam(idx2)) => idx1 == idx2,
+                _ => false,
+            })
+            .map(|var| var.ty.clone())
+    }
 }
 
 
@@ -382,12 +500,7 @@ impl AScope {
     }
 
     pub(crate) fn as_var(&self) -> AVar {
-        if let Some(ref break_var) = self.break_var {
-            break_var.clone()
-        }
-        else {
-            self.tail.clone()
-        }
+        self.tail.clone()
     }
 
     pub(crate) fn in_scope_find_sym(
@@ -410,9 +523,9 @@ impl std::fmt::Debug for AScope {
             .field("explicit_bindings", &self.explicit_bindings)
             // .field("implicit_bindings", &self.implicit_bindings)
             .field("mirs", &self.mirs)
+            .field("owned", &self.owned)
             .field("tail", &self.tail)
             .field("ret_var", &self.ret_var)
-            .field("break_var", &self.break_var)
             .finish()
     }
 }
@@ -436,6 +549,7 @@ impl AFnDec {
             params: self.params.clone(),
             ret: self.ret.clone(),
             symbol_name: self.name,
+            doc: self.doc.clone(),
         }
     }
 }
@@ -560,6 +674,23 @@ impl AType {
             _ => Err(()),
         }
     }
+
+    /// Cost of passing `self` as argument of type `ty`: exact match,
+    /// promotion like `lift_tys`, then the narrowing of `try_cast`
+    pub(crate) fn conv_cost(&self, ty: &Self) -> Option<usize> {
+        if self == ty {
+            Some(0)
+        } else if *self != Self::PH
+            && Self::lift_tys(ST::add, self.clone(), ty.clone()).as_ref()
+                == Ok(ty)
+        {
+            Some(1)
+        } else if self.try_cast(ty).is_ok() {
+            Some(2)
+        } else {
+            None
+        }
+    }
 }
 
 
@@ -661,10 +792,13 @@ pub(crate) const fn aty_u8() -> AType {
 pub(crate) const fn aty_f64() -> AType {
     AType::Pri(APriType::Float(8))
 }
-#[allow(unused)]
 pub(crate) fn aty_opaque_struct(s: &str) -> AType {
     AType::Pri(APriType::OpaqueStruct(str2sym(s)))
 }
+/// Result of command expression (`CmdRes` of runtime)
+pub(crate) fn aty_cmd() -> AType {
+    aty_opaque_struct("CmdRes")
+}
 #[allow(unused)]
 pub(crate) const fn aty_arr_int() -> AType {
     AType::Arr(APriType::Int(-4), 1)
@@ -1,6 +1,7 @@
 pub mod data;
 pub mod pass1;
 pub mod pass2;
+pub mod opt;
 
 
 use std::fmt::Debug;
@@ -9,9 +10,10 @@ use itertools::Itertools;
 use m6lexerkit::{sym2str, Span, SrcFileInfo, Symbol, Token};
 
 use crate::{
+    diagnostics::Diagnostic,
+    lint::Lint,
     name_mangling::mangling,
-    parser::{ST, TT},
-    ref_source,
+    parser::{SN, ST, TT},
 };
 pub use pass1::*;
 pub use pass2::*;
@@ -39,6 +41,8 @@ pub(crate) struct MIR {
     pub(crate) mirty: MIRTy,
     pub(crate) ty: AType,
     pub(crate) val: AVal,
+    /// Originating source span (statement level), used by debug info
+    pub(crate) span: Span,
 }
 
 
@@ -52,6 +56,8 @@ pub(crate) enum MIRTy {
 pub enum SemanticErrorReason {
     DupItemDef {
         name: Symbol,
+        /// Definition in the same module
+        prev: Option<Span>,
     },
     IncompatOpType {
         op1: AType,
@@ -68,9 +74,30 @@ pub enum SemanticErrorReason {
     UnkonwnType,
     UnkonwTag,
     NoMatchedFunc(Symbol, Vec<AType>), // basename, tys
+    /// Overloads of the same least conversion cost
+    AmbiguousCall {
+        name: Symbol,
+        tys: Vec<AType>,
+        /// signatures of the candidates
+        cands: Vec<String>,
+    },
     DuplicateAttr(Symbol, A3ttrVal),
     UnknownAttr(Symbol),
-    AssignRequireLV
+    UnknownLint(Symbol),
+    AssignRequireLV,
+    /// `@test` function with parameters, without body or returning other
+    /// than nothing and `int`
+    InvalidTestFn(Symbol),
+    /// `@bench` function without body or not taking only the iteration
+    /// count
+    InvalidBenchFn(Symbol),
+    UseBeforeAssign {
+        name: Symbol,
+        /// `let` without initializer
+        decl: Option<Span>,
+    },
+    /// Returned `cmd` isn't a new one owned by the fn, e.g. a parameter
+    ReturnBorrowedCmd,
 }
 use SemanticErrorReason as R;
 
@@ -84,23 +111,25 @@ pub struct SemanticError {
 
 
 impl MIR {
-    fn bind_value(name: Symbol, var: AVar) -> Self {
+    fn bind_value(name: Symbol, var: AVar, span: Span) -> Self {
         Self {
             name,
             tagid: None,
             mirty: MIRTy::ValBind,
             ty: var.ty,
             val: var.val,
+            span,
         }
     }
 
-    fn assign_var(name: Symbol, tagid: usize, var: AVar) -> Self {
+    fn assign_var(name: Symbol, tagid: usize, var: AVar, span: Span) -> Self {
         Self {
             name,
             tagid: Some(tagid),
             mirty: MIRTy::VarAssign,
             ty: var.ty,
             val: var.val,
+            span,
         }
     }
 }
@@ -112,87 +141,176 @@ impl MIR {
 impl std::fmt::Debug for SemanticError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f)?;
-        writeln!(f)?;
 
-        for (i, (cause, span)) in self.cause_lists.iter().enumerate() {
-            writeln!(
-                f,
-                "{} cause-{:003} {}",
-                "-".to_string().repeat(34),
-                i + 1,
-                "-".to_string().repeat(36),
-            )?;
-            writeln!(f)?;
-
-            // writeln!(f, "{}, {:#?}", item.loc, item.dtype)?;
-            match cause {
-                R::DupItemDef { name } => {
-                    writeln!(
-                        f,
-                        "Duplicate item `{}` definition:\n",
-                        sym2str(*name)
-                    )?;
-                    /* frontwards reference */
-                    // ref_source!(prev, "=", f, self.src);
-                    Ok(())
-                }
-                R::IncompatOpType { op1, op2 } => {
-                    writeln!(f, "No compatiable operator between {op1:?} and {op2:?}:\n")
-                }
-                R::IncompatIfExprs { if1, oths } => {
-                    writeln!(f, "If block type {if1:?} diffs in {oths:#?}:\n",)
-                }
-                R::UnknownSymBinding(arg0) => {
-                    writeln!(f, "Unkonwn symbol {}:\n", sym2str(*arg0))
-                }
-                // R::UnsupportedStringifyType(arg0) => {
-                //     writeln!(f, "Can't stringify {arg0:?}:\n")
-                // }
-                R::CantCastType(from, to) => {
-                    writeln!(f, "Can't cast {:?} into {:?}:\n", from, to)
-                }
-                R::UnmatchedType(expect, found, four) => writeln!(
-                    f,
-                    "Expect Type {:?}, however found {:?} for {}:\n",
-                    expect, found, four
-                ),
-                R::UnkonwnType => writeln!(f, "Unknown Type:\n"),
-                R::NoMatchedFunc(basename, tys) => {
-                    let fullname_sym = mangling(*basename, tys);
+        for diag in self.diagnostics() {
+            writeln!(f, "{}", diag.render_human(&self.src, false))?;
+        }
 
-                    writeln!(f, "No def for {}", sym2str(fullname_sym))
-                }
-                R::DuplicateAttr(attrsym, attrval) => {
-                    writeln!(
-                        f,
-                        "Duplicate Attr annotation {}, prev: {attrval:?}",
-                        sym2str(*attrsym)
-                    )
+        Ok(())
+    }
+}
+
+
+impl SemanticError {
+    pub(crate) fn src(&self) -> &SrcFileInfo {
+        &self.src
+    }
+
+    pub(crate) fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.cause_lists
+            .iter()
+            .map(|(cause, span)| cause.to_diagnostic(*span))
+            .collect()
+    }
+}
+
+
+impl SemanticErrorReason {
+    pub(crate) fn to_diagnostic(&self, span: Span) -> Diagnostic {
+        match self {
+            R::DupItemDef { name, prev } => {
+                let diag = Diagnostic::error(
+                    "E0201",
+                    format!("duplicate item `{}` definition", sym2str(*name)),
+                )
+                .with_primary(span, "redefined here");
+
+                /* frontwards reference */
+                if let Some(prev) = prev {
+                    diag.with_label(*prev, "previous definition here")
+                } else {
+                    diag.with_note("previous definition is in another module")
                 }
-                R::UnknownAttr(attrsym) => {
-                    writeln!(
-                        f,
-                        "Unknown Attr annotation {}",
-                        sym2str(*attrsym)
-                    )
-                },
-                R::UnkonwTag => {
-                    writeln!(
-                        f,
-                        "Unknown Tag"
-                    )
-                },
-                R::AssignRequireLV => {
-                    writeln!(f, "Assign require Left Value")
+            }
+            R::IncompatOpType { op1, op2 } => Diagnostic::error(
+                "E0202",
+                format!(
+                    "no compatiable operator between {op1:?} and {op2:?}"
+                ),
+            )
+            .with_primary(span, "incompatible operands"),
+            R::IncompatIfExprs { if1, oths } => Diagnostic::error(
+                "E0203",
+                format!("if block type {if1:?} diffs in {oths:?}"),
+            )
+            .with_primary(span, "branches have different types"),
+            R::UnknownSymBinding(sym) => Diagnostic::error(
+                "E0204",
+                format!("unknown symbol `{}`", sym2str(*sym)),
+            )
+            .with_primary(span, "not found in this scope"),
+            R::CantCastType(from, to) => Diagnostic::error(
+                "E0205",
+                format!("can't cast {from:?} into {to:?}"),
+            )
+            .with_primary(span, ""),
+            R::UnmatchedType(expect, found, four) => Diagnostic::error(
+                "E0206",
+                format!("mismatched types for {four}"),
+            )
+            .with_primary(
+                span,
+                format!("expected {expect:?}, found {found:?}"),
+            ),
+            R::UnkonwnType => Diagnostic::error("E0207", "unknown type")
+                .with_primary(span, ""),
+            R::UnkonwTag => Diagnostic::error("E0208", "unknown tag")
+                .with_primary(span, ""),
+            R::NoMatchedFunc(basename, tys) => Diagnostic::error(
+                "E0209",
+                format!(
+                    "no function `{}` matches the arguments",
+                    sym2str(*basename)
+                ),
+            )
+            .with_primary(span, format!("arguments are {tys:?}"))
+            .with_note(format!(
+                "looked up `{}`",
+                sym2str(mangling(*basename, tys))
+            )),
+            R::AmbiguousCall { name, tys, cands } => cands.iter().fold(
+                Diagnostic::error(
+                    "E0217",
+                    format!("call of `{}` is ambiguous", sym2str(*name)),
+                )
+                .with_primary(
+                    span,
+                    format!(
+                        "arguments are ({})",
+                        tys.iter().map(|ty| ty.ident_name()).join(", ")
+                    ),
+                ),
+                |diag, cand| diag.with_note(format!("candidate `{cand}`")),
+            )
+            .with_help("cast the arguments to pick one of the candidates"),
+            R::DuplicateAttr(attrsym, attrval) => Diagnostic::error(
+                "E0210",
+                format!("duplicate attribute `{}`", sym2str(*attrsym)),
+            )
+            .with_primary(span, "")
+            .with_note(format!("previous value: {attrval:?}")),
+            R::UnknownAttr(attrsym) => Diagnostic::error(
+                "E0211",
+                format!("unknown attribute `{}`", sym2str(*attrsym)),
+            )
+            .with_primary(span, ""),
+            R::UnknownLint(lint) => Diagnostic::error(
+                "E0213",
+                format!("unknown lint `{}`", sym2str(*lint)),
+            )
+            .with_primary(span, ""),
+            R::AssignRequireLV => Diagnostic::error(
+                "E0212",
+                "assignment requires a left value",
+            )
+            .with_primary(span, "can't assign to this expression"),
+            R::InvalidTestFn(name) => Diagnostic::error(
+                "E0215",
+                format!(
+                    "test function `{}` must have a body, take no \
+                     parameters and return nothing or `int`",
+                    sym2str(*name)
+                ),
+            )
+            .with_primary(span, "")
+            .with_help("declare it like `fn name()`"),
+            R::InvalidBenchFn(name) => Diagnostic::error(
+                "E0216",
+                format!(
+                    "benchmark function `{}` must have a body and take only \
+                     the iteration count",
+                    sym2str(*name)
+                ),
+            )
+            .with_primary(span, "")
+            .with_help("declare it like `fn name(iters: int)`"),
+            R::UseBeforeAssign { name, decl } => {
+                let diag = Diagnostic::error(
+                    "E0214",
+                    format!(
+                        "used binding `{}` is possibly unassigned",
+                        sym2str(*name)
+                    ),
+                )
+                .with_primary(span, "used here before assignment")
+                .with_help(format!(
+                    "assign `{}` on every path before reading it",
+                    sym2str(*name)
+                ));
+
+                if let Some(decl) = decl {
+                    diag.with_label(*decl, "binding declared here")
+                } else {
+                    diag
                 }
-            }?;
-            writeln!(f)?;
-            ref_source!(span, "^", f, self.src);
-            writeln!(f)?;
-            writeln!(f)?;
+            }
+            R::ReturnBorrowedCmd => Diagnostic::error(
+                "E0218",
+                "returned command result isn't owned by the function",
+            )
+            .with_primary(span, "borrowed from the caller or elsewhere")
+            .with_help("return a command expression or a call result"),
         }
-
-        Ok(())
     }
 }
 
@@ -262,6 +380,9 @@ pub(crate) fn analyze_ty_(tt: &TT) -> Result<AType, Span> {
     if tok_id.check_value("ptr") {
         return Ok(AType::Pri(APriType::Ptr));
     }
+    if tok_id.check_value("cmd") {
+        return Ok(aty_cmd());
+    }
     if tok_id.check_value("[") {
         if tt.len() < 2 {
             return Err(tok_id.span);
@@ -297,15 +418,28 @@ pub(crate) fn analyze_attrs(
     tt: &TT,
 ) -> A3ttrs {
     let mut attrs = A3ttrs::new();
+    let mut p = 0;
 
-    for (st, sn) in tt.iter() {
-        debug_assert_eq!(*st, ST::attr);
+    while p < tt.len() {
+        debug_assert_eq!(tt[p].0, ST::attr);
 
-        let idt = sn.as_tok();
+        let idt = tt[p].1.as_tok();
+        p += 1;
+
+        let mut args = None;
+        if p < tt.len() && tt[p].0 == ST::AttrArgs {
+            args = Some(tt[p].1.as_tt());
+            p += 1;
+        }
 
         let attr_name = match idt.value_string().as_str() {
             "no_mangle" => A3ttrName::NoMangle,
             "vararg" => A3ttrName::VarArg,
+            "test" => A3ttrName::Test,
+            "bench" => A3ttrName::Bench,
+            "allow" => A3ttrName::Allow,
+            "warn" => A3ttrName::Warn,
+            "deny" => A3ttrName::Deny,
             _ => {
                 write_diagnosis(
                     cause_lists,
@@ -316,6 +450,40 @@ pub(crate) fn analyze_attrs(
             }
         };
 
+        if matches!(
+            attr_name,
+            A3ttrName::Allow | A3ttrName::Warn | A3ttrName::Deny
+        ) {
+            let mut lints = vec![];
+
+            for (_st, sn) in args.iter().flat_map(|args| args.iter()) {
+                let SN::E(tok) = sn else { continue };
+
+                if !tok.check_name("id") {
+                    continue;
+                }
+
+                if let Some(lint) = Lint::from_name(&tok.value_string()) {
+                    lints.push(lint);
+                } else {
+                    write_diagnosis(
+                        cause_lists,
+                        R::UnknownLint(tok.value),
+                        tok.span,
+                    );
+                }
+            }
+
+            // lint attributes accumulate
+            if let Some(A3ttrVal::Lints(prev)) = attrs.0.get_mut(&attr_name) {
+                prev.extend(lints);
+            } else {
+                attrs.0.insert(attr_name, A3ttrVal::Lints(lints));
+            }
+
+            continue;
+        }
+
         if let Some(oldval) = attrs.0.insert(attr_name, A3ttrVal::Empty) {
             write_diagnosis(
                 cause_lists,
@@ -350,3 +518,40 @@ pub(crate) fn toks_to_span(toks: &[Token]) -> Span {
         }
     }
 }
+
+
+/// Front phases (lexer, parser, pass1 and pass2) of `path` for tests, with
+/// core lib if `core`
+#[cfg(test)]
+pub(crate) fn lower_src(
+    path: &str,
+    core: bool,
+) -> Result<Pass2Export, Box<dyn std::error::Error>> {
+    let mods = if core {
+        vec![crate::driver::RunCompiler::boot(&Default::default())?]
+    } else {
+        vec![]
+    };
+
+    let src = SrcFileInfo::new(&path)?;
+    let tokens = crate::lexer::tokenize(&src)?;
+    let tt = crate::parser::parse(tokens, &src)?;
+
+    let Pass1Export {
+        src,
+        tt2,
+        amod,
+        ess,
+    } = SemanticAnalyzerPass1::run(src, tt, ExtSymSet { mods })?;
+
+    Ok(SemanticAnalyzerPass2::run(src, tt2, amod, ess)?)
+}
+
+
+/// Semantic errors of `path` reported by pass1 or pass2, see `lower_src`
+#[cfg(test)]
+pub(crate) fn lower_src_err(path: &str, core: bool) -> SemanticError {
+    let err = lower_src(path, core).err().expect("no semantic error");
+
+    *err.downcast::<SemanticError>().unwrap()
+}
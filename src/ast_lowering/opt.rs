@@ -0,0 +1,441 @@
+//! MIR optimizations, run between pass2 and codegen
+//!
+//! Every scope is optimized on its own straight-line list of MIRs:
+//!
+//! 1. constant folding of `BOpExpr` and `TypeCast` (integer overflow and
+//!    division by zero are left to the runtime)
+//!
+//! 1. copy propagation: a load of a variable (or param) whose value is
+//!    already held by a temp of the scope is replaced by that temp, and a
+//!    load after a constant store becomes the constant
+//!
+//! 1. dead binding elimination of unused pure temps
+//!
+//! Temps are named per scope (`!__tmp_N`), a nested scope may read the
+//! temps of its ancestors by name, so those temps are pinned: never
+//! substituted nor removed.
+//!
+//! It should be run after pass2 is done with the module, since removed
+//! bindings are dropped from `implicit_bindings` too.
+
+use std::collections::{HashMap, HashSet};
+
+use m6lexerkit::Symbol;
+
+use super::{aty_bool, aty_f64, aty_i32, AMod, AScope, AType, AVal, ConstVal,
+    MIRTy, MIR};
+use crate::{codegen::is_implicit_sym, parser::SyntaxType as ST};
+
+
+/// Facts of the straight-line MIRs of one scope
+#[derive(Default)]
+struct Facts {
+    /// temp - constant it's bound to
+    consts: HashMap<Symbol, ConstVal>,
+    /// temp - temp of the same value replacing it
+    copies: HashMap<Symbol, Symbol>,
+    /// temp - type
+    tys: HashMap<Symbol, AType>,
+    /// var - constant of its current value
+    var_consts: HashMap<(Symbol, usize), ConstVal>,
+    /// var - temp holding its current value
+    var_temps: HashMap<(Symbol, usize), Symbol>,
+    /// param index - temp holding it
+    param_temps: HashMap<u32, Symbol>,
+}
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Function
+
+pub(crate) fn optimize(amod: &mut AMod) {
+    let pinned = pinned_temps(&amod.scopes);
+
+    for (scope, pinned) in amod.scopes.iter_mut().zip(pinned.iter()) {
+        propagate(scope, pinned);
+        eliminate_dead_bindings(scope, pinned);
+    }
+}
+
+
+/// Temps of each scope read by its nested scopes
+fn pinned_temps(scopes: &[AScope]) -> Vec<HashSet<Symbol>> {
+    let defs = scopes
+        .iter()
+        .map(|scope| {
+            scope
+                .mirs
+                .iter()
+                .filter(|mir| matches!(mir.mirty, MIRTy::ValBind))
+                .map(|mir| mir.name)
+                .collect::<HashSet<Symbol>>()
+        })
+        .collect::<Vec<_>>();
+    let mut pinned = vec![HashSet::new(); scopes.len()];
+
+    for scope in scopes.iter() {
+        let mut local = HashSet::new();
+        let mut outer = vec![];
+
+        for mir in scope.mirs.iter() {
+            outer.extend(
+                val_refs(&mir.val).filter(|sym| !local.contains(sym)),
+            );
+
+            if matches!(mir.mirty, MIRTy::ValBind) {
+                local.insert(mir.name);
+            }
+        }
+        outer.extend(end_refs(scope).filter(|sym| !local.contains(sym)));
+
+        for sym in outer {
+            let mut paren = scope.paren;
+
+            while let Some(idx) = paren {
+                if defs[idx].contains(&sym) {
+                    pinned[idx].insert(sym);
+                }
+                paren = scopes[idx].paren;
+            }
+        }
+    }
+
+    pinned
+}
+
+
+fn propagate(scope: &mut AScope, pinned: &HashSet<Symbol>) {
+    let mut facts = Facts::default();
+
+    for mir in scope.mirs.iter_mut() {
+        for sym in val_refs_mut(&mut mir.val) {
+            facts.subst(sym);
+        }
+
+        if let Some(val) = facts.simplify(&mir.val, &mir.ty) {
+            mir.val = val;
+        }
+
+        if clobbers_vars(&mir.val) {
+            facts.var_consts.clear();
+            facts.var_temps.clear();
+        }
+
+        match mir.mirty {
+            MIRTy::ValBind if is_implicit_sym(mir.name) => {
+                facts.bind(mir, pinned)
+            }
+            MIRTy::ValBind => (),
+            MIRTy::VarAssign => {
+                let key = (mir.name, mir.tagid.unwrap_or_default());
+
+                facts.var_temps.remove(&key);
+
+                if let AVal::ConstAlias(ref val) = mir.val {
+                    facts.var_consts.insert(key, val.clone());
+                } else {
+                    facts.var_consts.remove(&key);
+                }
+            }
+        }
+    }
+
+    for var in [Some(&mut scope.tail), scope.ret_var.as_mut()]
+        .into_iter()
+        .flatten()
+    {
+        for sym in val_refs_mut(&mut var.val) {
+            facts.subst(sym);
+        }
+    }
+}
+
+
+fn eliminate_dead_bindings(scope: &mut AScope, pinned: &HashSet<Symbol>) {
+    let mut uses: HashMap<Symbol, usize> = HashMap::new();
+
+    for sym in scope
+        .mirs
+        .iter()
+        .flat_map(|mir| val_refs(&mir.val))
+        .chain(end_refs(scope))
+    {
+        *uses.entry(sym).or_default() += 1;
+    }
+
+    let mut dead = vec![false; scope.mirs.len()];
+
+    // operands are bound before, so one reversed pass reaches fixed point
+    for (i, mir) in scope.mirs.iter().enumerate().rev() {
+        if matches!(mir.mirty, MIRTy::ValBind)
+            && is_implicit_sym(mir.name)
+            && !pinned.contains(&mir.name)
+            && uses.get(&mir.name).copied().unwrap_or_default() == 0
+            && is_pure(mir)
+        {
+            dead[i] = true;
+
+            for sym in val_refs(&mir.val) {
+                if let Some(n) = uses.get_mut(&sym) {
+                    *n -= 1;
+                }
+            }
+        }
+    }
+
+    let mut dead = dead.into_iter();
+    scope.mirs.retain(|_| !dead.next().unwrap());
+
+    scope.implicit_bindings = scope
+        .mirs
+        .iter()
+        .enumerate()
+        .filter(|(_, mir)| {
+            matches!(mir.mirty, MIRTy::ValBind) && is_implicit_sym(mir.name)
+        })
+        .map(|(i, mir)| (mir.name, i))
+        .collect();
+}
+
+
+/// Temps read by value
+fn val_refs(val: &AVal) -> impl Iterator<Item = Symbol> + '_ {
+    let refs: Vec<Symbol> = match val {
+        AVal::IfBlock { if_exprs, .. } => {
+            if_exprs.iter().map(|(cond, _)| *cond).collect()
+        }
+        AVal::FnCall { args, .. } => args.clone(),
+        AVal::BOpExpr { operands, .. } => vec![operands.0, operands.1],
+        AVal::TypeCast { name, .. } => vec![*name],
+        AVal::Assign(_, _, valsym) => vec![*valsym],
+        AVal::Return(Some(sym)) => vec![*sym],
+        _ => vec![],
+    };
+
+    refs.into_iter().filter(|sym| is_implicit_sym(*sym))
+}
+
+
+fn val_refs_mut(val: &mut AVal) -> Vec<&mut Symbol> {
+    match val {
+        AVal::IfBlock { if_exprs, .. } => {
+            if_exprs.iter_mut().map(|(cond, _)| cond).collect()
+        }
+        AVal::FnCall { args, .. } => args.iter_mut().collect(),
+        AVal::BOpExpr { operands, .. } => {
+            vec![&mut operands.0, &mut operands.1]
+        }
+        AVal::TypeCast { name, .. } => vec![name],
+        AVal::Assign(_, _, valsym) => vec![valsym],
+        AVal::Return(Some(sym)) => vec![sym],
+        _ => vec![],
+    }
+}
+
+
+/// Temps read at the end of scope (tail and ret value)
+fn end_refs(scope: &AScope) -> impl Iterator<Item = Symbol> + '_ {
+    [Some(&scope.tail), scope.ret_var.as_ref()]
+        .into_iter()
+        .flatten()
+        .flat_map(|var| val_refs(&var.val))
+}
+
+
+/// Nested scopes and calls (module variables) may assign any variable
+fn clobbers_vars(val: &AVal) -> bool {
+    matches!(
+        val,
+        AVal::IfBlock { .. }
+            | AVal::InfiLoopExpr(_)
+            | AVal::BlockExpr(_)
+            | AVal::DefFn { .. }
+            | AVal::FnCall { .. }
+    )
+}
+
+
+/// No side effect, runtime checks included
+fn is_pure(mir: &MIR) -> bool {
+    match &mir.val {
+        AVal::ConstAlias(_)
+        | AVal::Var(..)
+        | AVal::TypeCast { .. }
+        | AVal::FnParam(_)
+        | AVal::PH => true,
+        AVal::BOpExpr { op, .. } => match op {
+            ST::gt | ST::ge | ST::lt | ST::le => true,
+            // int ones may trap on overflow
+            ST::add | ST::sub | ST::mul => mir.ty == aty_f64(),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+
+fn fold_bop(op: ST, val1: &ConstVal, val2: &ConstVal) -> Option<ConstVal> {
+    Some(match (val1, val2) {
+        (ConstVal::Int(a), ConstVal::Int(b)) => match op {
+            ST::add => ConstVal::Int(a.checked_add(*b)?),
+            ST::sub => ConstVal::Int(a.checked_sub(*b)?),
+            ST::mul => ConstVal::Int(a.checked_mul(*b)?),
+            ST::div => ConstVal::Int(a.checked_div(*b)?),
+            ST::gt => ConstVal::Bool(a > b),
+            ST::ge => ConstVal::Bool(a >= b),
+            ST::lt => ConstVal::Bool(a < b),
+            ST::le => ConstVal::Bool(a <= b),
+            _ => return None,
+        },
+        (ConstVal::Float(a), ConstVal::Float(b)) => match op {
+            ST::add => ConstVal::Float(a + b),
+            ST::sub => ConstVal::Float(a - b),
+            ST::mul => ConstVal::Float(a * b),
+            ST::div if *b != 0.0 => ConstVal::Float(a / b),
+            ST::gt => ConstVal::Bool(a > b),
+            ST::ge => ConstVal::Bool(a >= b),
+            // unordered as codegen
+            ST::lt => ConstVal::Bool(!(a >= b)),
+            ST::le => ConstVal::Bool(!(a > b)),
+            _ => return None,
+        },
+        _ => return None,
+    })
+}
+
+
+fn fold_cast(val: &ConstVal, ty: &AType) -> Option<ConstVal> {
+    Some(match val {
+        ConstVal::Int(int) if *ty == aty_i32() => ConstVal::Int(*int),
+        ConstVal::Int(int) if *ty == aty_f64() => ConstVal::Float(*int as f64),
+        ConstVal::Float(float) if *ty == aty_f64() => ConstVal::Float(*float),
+        ConstVal::Float(float)
+            if *ty == aty_i32()
+                && float.trunc() >= i32::MIN as f64
+                && float.trunc() <= i32::MAX as f64 =>
+        {
+            ConstVal::Int(*float as i32)
+        }
+        ConstVal::Bool(bool) if *ty == aty_bool() => ConstVal::Bool(*bool),
+        _ => return None,
+    })
+}
+
+
+
+impl Facts {
+    fn subst(&self, sym: &mut Symbol) {
+        if let Some(to) = self.copies.get(sym) {
+            *sym = *to;
+        }
+    }
+
+    fn simplify(&self, val: &AVal, ty: &AType) -> Option<AVal> {
+        let folded = match val {
+            AVal::BOpExpr { op, operands } => fold_bop(
+                *op,
+                self.consts.get(&operands.0)?,
+                self.consts.get(&operands.1)?,
+            )?,
+            AVal::TypeCast { name, ty: to, .. } => {
+                fold_cast(self.consts.get(name)?, to)?
+            }
+            AVal::Var(sym, tagid) => {
+                self.var_consts.get(&(*sym, *tagid))?.clone()
+            }
+            _ => return None,
+        };
+
+        // e.g. int to bool cast
+        if matches!(folded, ConstVal::Bool(_)) != (*ty == aty_bool()) {
+            return None;
+        }
+
+        Some(AVal::ConstAlias(folded))
+    }
+
+    /// Record the implicit binding of `mir`
+    fn bind(&mut self, mir: &MIR, pinned: &HashSet<Symbol>) {
+        let name = mir.name;
+        let copy = !pinned.contains(&name);
+
+        match mir.val {
+            AVal::ConstAlias(ref val) => {
+                self.consts.insert(name, val.clone());
+            }
+            AVal::Var(sym, tagid) => {
+                match self.var_temps.get(&(sym, tagid)) {
+                    Some(tmp) if copy => {
+                        self.copies.insert(name, *tmp);
+                    }
+                    _ => {
+                        self.var_temps.insert((sym, tagid), name);
+                    }
+                }
+            }
+            AVal::FnParam(idx) => match self.param_temps.get(&idx) {
+                Some(tmp) if copy => {
+                    self.copies.insert(name, *tmp);
+                }
+                _ => {
+                    self.param_temps.insert(idx, name);
+                }
+            },
+            AVal::TypeCast { name: from, ref ty, .. }
+                if copy && self.tys.get(&from) == Some(ty) =>
+            {
+                self.copies.insert(name, from);
+            }
+            AVal::Assign(sym, tagid, valsym) => {
+                let key = (sym, tagid);
+
+                self.var_consts.remove(&key);
+                self.var_temps.remove(&key);
+
+                // only temps of the scope can be named later
+                if let Some(val) = self.consts.get(&valsym) {
+                    self.var_consts.insert(key, val.clone());
+                } else if self.tys.contains_key(&valsym) {
+                    self.var_temps.insert(key, valsym);
+                }
+            }
+            _ => (),
+        }
+
+        self.tys.insert(name, mir.ty.clone());
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::optimize;
+    use crate::{
+        ast_lowering::{lower_src, Pass2Export},
+        dump::dump_mir,
+    };
+
+    #[test]
+    fn test_optimize() -> Result<(), Box<dyn std::error::Error>> {
+        let Pass2Export { mut amod, .. } =
+            lower_src("./examples/opt.bath", false)?;
+
+        optimize(&mut amod);
+        let mir_dump = dump_mir(&amod);
+
+        // folded through the cast of `2`
+        assert!(mir_dump.contains("a#0: i32 <- const 7\n"));
+        assert!(mir_dump.contains("f#0: f64 <- const 3.5\n"));
+        // overflow is left to the runtime check
+        assert!(mir_dump.contains("big#0: i32 <- add %"));
+        // load of `a` after the constant store
+        assert!(!mir_dump.contains("= a#0\n"));
+        // second load of `x` is a copy of the first one
+        assert!(mir_dump.contains("mul %0, %0\n"));
+        assert_eq!(mir_dump.matches(": i32 = param 0\n").count(), 2);
+
+        Ok(())
+    }
+}
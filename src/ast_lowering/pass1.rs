@@ -7,8 +7,9 @@ use crate::{
         analyze_attrs, analyze_pat_no_top, analyze_ty,
         calc_fullname, write_diagnosis, A3ttrName, A3ttrs, AFnDec,
         AMod, AParamPat, AType, SemanticError,
-        SemanticErrorReason as R, AnExtFnDec, A3ttrVal,
+        SemanticErrorReason as R, AnExtFnDec, A3ttrVal, aty_i32,
     },
+    lexer::doc_comment,
     opt_osstr_to_str,
     parser::{SyntaxType as ST, TT},
 };
@@ -77,6 +78,8 @@ impl SemanticAnalyzerPass1 {
     pub(crate) fn do_analyze_item(&mut self, mut tt: TT) -> Option<AnItem> {
         let mut p = 0;
 
+        let doc = doc_comment(&self.src, tt[p].1.span().from);
+
         let attrs;
         if tt[p].0 == ST::Attrs {
             attrs = self.analyze_attrs(&tt[p].1.as_tt());
@@ -87,7 +90,7 @@ impl SemanticAnalyzerPass1 {
         }
 
         if tt[p].0 == ST::Function {
-            self.do_analyze_fn(attrs, tt.move_elem(p).1.into_tt())
+            self.do_analyze_fn(attrs, doc, tt.move_elem(p).1.into_tt())
         }
         else {
             unreachable!()
@@ -95,7 +98,12 @@ impl SemanticAnalyzerPass1 {
     }
 
     /// Function Definition or Exrernal Function Declare
-    pub(crate) fn do_analyze_fn(&mut self, mut attrs: A3ttrs, tt: TT) -> Option<AnItem> {
+    pub(crate) fn do_analyze_fn(
+        &mut self,
+        mut attrs: A3ttrs,
+        doc: Option<String>,
+        tt: TT,
+    ) -> Option<AnItem> {
         let mut p = 0;
 
         /* skip <fn> */
@@ -152,6 +160,30 @@ impl SemanticAnalyzerPass1 {
             attrs.push_attr(A3ttrName::VarArg, A3ttrVal::Empty);
         }
 
+        if attrs.has(A3ttrName::Test)
+            && (!params.is_empty()
+                || !(ret == AType::Void || ret == aty_i32())
+                || tt[p].0 == ST::semi)
+        {
+            write_diagnosis(
+                &mut self.cause_lists,
+                R::InvalidTestFn(fn_base_name),
+                idt.span(),
+            );
+        }
+
+        if attrs.has(A3ttrName::Bench)
+            && (params.len() != 1
+                || params[0].ty != aty_i32()
+                || tt[p].0 == ST::semi)
+        {
+            write_diagnosis(
+                &mut self.cause_lists,
+                R::InvalidBenchFn(fn_base_name),
+                idt.span(),
+            );
+        }
+
         if attrs.has(A3ttrName::NoMangle) {
             full_name = fn_base_name
         } else {
@@ -159,10 +191,14 @@ impl SemanticAnalyzerPass1 {
         }
 
         if let Some(_afn) = self.find_func_by_name(full_name) {
+            let prev =
+                self.amod.afns.get(&full_name).map(|afn| afn.idt.span());
+
             write_diagnosis(
                 &mut self.cause_lists,
                 R::DupItemDef {
                     name: full_name,
+                    prev,
                 },
                 idt.span(),
             );
@@ -175,10 +211,12 @@ impl SemanticAnalyzerPass1 {
                 full_name,
                 params,
                 ret,
-                symbol_name: full_name
+                symbol_name: full_name,
+                doc,
             };
 
             self.amod.efns.insert(full_name, afn);
+            self.amod.efn_spans.insert(full_name, idt.span());
 
             None
         }
@@ -192,6 +230,7 @@ impl SemanticAnalyzerPass1 {
                 name: full_name,
                 params,
                 ret,
+                doc,
             };
 
             self.amod.afns.insert(full_name, afn);
@@ -233,6 +272,7 @@ impl SemanticAnalyzerPass1 {
 
         /* get pat_no_top */
 
+        let span = tt[p].1.span();
         let formal;
         if tt[p].0 == ST::PatNoTop {
             formal = self.analyze_pat_no_top(tt[p].1.as_tt());
@@ -248,7 +288,7 @@ impl SemanticAnalyzerPass1 {
 
         let ty = self.analyze_ty(&tt[p].1.as_tt());
 
-        AParamPat { formal, ty }
+        AParamPat { formal, ty, span }
     }
 
     pub(crate) fn analyze_pat_no_top(&mut self, tt: &TT) -> Symbol {
@@ -1,15 +1,20 @@
-use m6lexerkit::{lazy_static::lazy_static, str2sym, sym2str, Symbol};
+use std::collections::HashSet;
+
+use m6lexerkit::{
+    lazy_static::lazy_static, str2sym, sym2str, Span, Symbol,
+};
 use m6parserkit::Cursor;
 use regex::Regex;
 
 use super::SemanticAnalyzerPass2;
 use crate::ast_lowering::ATag;
 use crate::{
+    lint::LintCause,
     ast_lowering::{
-        aty_bool, aty_f64, aty_i32, APriType, ASymDef, AType, AVal, AVar,
-        ConstVal, SemanticErrorReason as R,
+        aty_bool, aty_f64, aty_i32, APriType, ARef, ASymDef, AType, AVal,
+        AVar, ConstVal, SemanticErrorReason as R,
     },
-    name_mangling::mangling,
+    name_mangling::{signature, unmangling},
     parser::{SyntaxType as ST, TT},
 };
 
@@ -32,7 +37,7 @@ impl SemanticAnalyzerPass2 {
             ST::IfExpr => self.analyze_if_expr(tt),
             ST::InfiLoopExpr => self.analyze_infi_loop_expr(tt),
             ST::BlockExpr => self.analyze_block_expr(tt),
-            ST::GroupedExpr => self.analyze_expr(&tt[0].1.as_tt()),
+            ST::GroupedExpr => self.analyze_expr(&tt[1].1.as_tt()),
 
 
             /* ExprSpan */
@@ -52,10 +57,10 @@ impl SemanticAnalyzerPass2 {
 
     pub(crate) fn analyze_funcall_expr(&mut self, tt: &TT) -> AVar {
         debug_assert_eq!(tt[0].0, ST::PathExpr);
-        debug_assert_eq!(tt[1].0, ST::GroupedExpr);
+        debug_assert_eq!(tt[1].0, ST::CallArgs);
 
         let path = tt[0].1.as_tt();
-        let grouped = tt[1].1.as_tt();
+        let args = tt[1].1.as_tt();
 
         /* get fn path name */
         let mut p = 0;
@@ -71,14 +76,16 @@ impl SemanticAnalyzerPass2 {
         let name_tok = seg0[0].1.as_tok();
         let base_name = name_tok.value;
 
-        let fn_params_tt = grouped[1].1.as_tt();
         let mut param_syms = vec![];
         let mut param_tys = vec![];
 
-        for (ty, sn) in fn_params_tt.subs.iter() {
-            debug_assert_eq!(*ty, ST::PathExpr);
-            let param_var = self.analyze_path_expr(sn.as_tt());
-            let param_sym = self.bind_value(param_var.clone());
+        for (ty, sn) in args.subs.iter() {
+            if *ty != ST::Expr {
+                continue;
+            }
+
+            let param_var = self.analyze_expr(sn.as_tt());
+            let param_sym = self.bind_value_at(param_var.clone(), sn.span());
 
             param_syms.push(param_sym);
             param_tys.push(param_var.ty);
@@ -89,24 +96,160 @@ impl SemanticAnalyzerPass2 {
             use_raw = matches!(atag, ATag::RAW);
         }
 
-        let fullname;
-        if use_raw {
-            fullname = base_name;
+        let found = if use_raw {
+            self.find_func_by_name(base_name).ok_or(vec![])
         } else {
-            /* name mangling */
-            fullname = mangling(base_name, &param_tys);
+            self.resolve_overload(base_name, &param_tys)
+        };
+
+        match found {
+            Ok(afndec) => {
+                /* implicit conversion of arguments */
+                for (i, param) in afndec.params.iter().enumerate() {
+                    if !use_raw && param_tys[i] != param.ty {
+                        param_syms[i] = self.cast_val(
+                            param_syms[i],
+                            param_tys[i].clone(),
+                            param.ty.clone(),
+                        );
+                    }
+                }
+
+                let fullname = afndec.full_name;
+
+                self.used_fns.insert(fullname);
+                self.write_ref(name_tok.span, ARef::Fn(fullname));
+
+                AVar::efn_call(afndec, param_syms)
+            }
+            Err(cands) if cands.len() > 1 => {
+                let cands = cands
+                    .iter()
+                    .map(|cand| signature(base_name, &cand.params, &cand.ret))
+                    .collect();
+
+                self.write_dialogsis(
+                    R::AmbiguousCall {
+                        name: base_name,
+                        tys: param_tys,
+                        cands,
+                    },
+                    name_tok.span,
+                );
+
+                AVar::undefined()
+            }
+            Err(_)
+                if !use_raw
+                    && matches!(
+                        sym2str(base_name).as_str(),
+                        "assert" | "assert_eq"
+                    ) =>
+            {
+                let span = Span {
+                    from: tt[0].1.span().from,
+                    end: tt[1].1.span().end,
+                };
+
+                self.analyze_assert(base_name, param_syms, param_tys, span)
+            }
+            Err(_) => {
+                self.write_dialogsis(
+                    R::NoMatchedFunc(base_name, param_tys),
+                    name_tok.span,
+                );
+
+                AVar::undefined()
+            }
+        }
+    }
+
+    /// Builtin `assert(cond)` and `assert_eq(left, right)`, lowered into
+    /// runtime calls with the source text and location of `span`
+    fn analyze_assert(
+        &mut self,
+        base_name: Symbol,
+        mut syms: Vec<Symbol>,
+        tys: Vec<AType>,
+        span: Span,
+    ) -> AVar {
+        let is_eq = sym2str(base_name) == "assert_eq";
+        let arity = if is_eq { 2 } else { 1 };
+
+        if tys.len() != arity {
+            self.write_dialogsis(R::NoMatchedFunc(base_name, tys), span);
+            return AVar::undefined();
         }
 
-        if let Some(afndef) = self.find_func_by_name(fullname) {
-            AVar::efn_call(afndef, param_syms)
+        let rt_fn = if !is_eq {
+            if tys[0] == aty_bool() {
+                syms[0] = self.cast_val(syms[0], aty_bool(), aty_i32());
+            } else if tys[0] != aty_i32() {
+                self.write_dialogsis(
+                    R::UnmatchedType(
+                        aty_bool(),
+                        tys[0].clone(),
+                        "assert".to_owned(),
+                    ),
+                    span,
+                );
+                return AVar::undefined();
+            }
+
+            "bas_assert"
         } else {
-            self.write_dialogsis(
-                R::NoMatchedFunc(base_name, param_tys),
-                name_tok.span,
-            );
+            if tys[1] != tys[0] {
+                if tys[1].try_cast(&tys[0]).is_ok() {
+                    syms[1] = self.cast_val(
+                        syms[1],
+                        tys[1].clone(),
+                        tys[0].clone(),
+                    );
+                } else {
+                    self.write_dialogsis(
+                        R::UnmatchedType(
+                            tys[0].clone(),
+                            tys[1].clone(),
+                            "assert_eq".to_owned(),
+                        ),
+                        span,
+                    );
+                    return AVar::undefined();
+                }
+            }
 
-            AVar::undefined()
-        }
+            match &tys[0] {
+                ty if *ty == aty_i32() => "bas_assert_eq_i32",
+                ty if *ty == aty_f64() => "bas_assert_eq_f64",
+                AType::Pri(APriType::Ptr) => "bas_assert_eq_str",
+                _ => {
+                    self.write_dialogsis(
+                        R::NoMatchedFunc(base_name, tys.clone()),
+                        span,
+                    );
+                    return AVar::undefined();
+                }
+            }
+        };
+
+        let loc = self.src.boffset2srcloc(span.from);
+        let msg = format!(
+            "`{}` at {}:{}:{}",
+            &self.src.get_srcstr()[span.from..span.end],
+            self.src.get_path().to_string_lossy(),
+            loc.ln,
+            loc.col
+        );
+        syms.push(self.build_const_str(str2sym(&msg)));
+
+        // declared in core lib
+        let rt_fn = str2sym(rt_fn);
+        let Some(afndec) = self.find_func_by_name(rt_fn) else {
+            self.write_dialogsis(R::NoMatchedFunc(rt_fn, tys), span);
+            return AVar::undefined();
+        };
+
+        AVar::efn_call(afndec, syms)
     }
 
     pub(crate) fn analyze_bop_expr(&mut self, tt: &TT) -> AVar {
@@ -135,16 +278,17 @@ impl SemanticAnalyzerPass2 {
                 return AVar::undefined();
             }
             else {
-                var = self.analyze_path_expr(tt1[0].1.as_tt());
+                // write only, not a read
+                var = self.analyze_place_expr(tt1[0].1.as_tt());
             }
 
             let value = self.analyze_expr(tt2);
             let valty = value.ty.clone();
-            let mut valsym = self.bind_value(value);
+            let mut valsym = self.bind_value_at(value, tt[2].1.span());
 
             if var.ty != valty {
                 if let Ok(_) = valty.try_cast(&var.ty) {
-                    valsym = self.cast_val(valsym, var.ty);
+                    valsym = self.cast_val(valsym, valty, var.ty);
                 } else {
                     self.write_dialogsis(
                         R::CantCastType(valty.clone(), var.ty),
@@ -154,6 +298,8 @@ impl SemanticAnalyzerPass2 {
             }
 
             let (name, tagid) = var.val.as_var();
+            self.unassigned.remove(&(name, tagid));
+            self.move_owned(valsym, Some((name, tagid)));
 
             return AVar {
                 ty: valty,
@@ -162,13 +308,16 @@ impl SemanticAnalyzerPass2 {
         }
 
         let var1 = self.analyze_expr(tt1);
-        let sym1 = self.bind_value(var1.clone());
+        let sym1 = self.bind_value_at(var1.clone(), tt[0].1.span());
         let symdef1 = ASymDef::new(sym1, var1.ty.clone());
 
         /* EXCLUDE SHORT CIRCUIT EVALUATION CASE */
 
         if *bopty == ST::and {
+            let unassigned = self.unassigned.clone();
             let var2 = self.analyze_expr(tt2);
+            // rhs may be skipped
+            self.unassigned.extend(unassigned);
             let ifblk_idx = self.push_single_value_scope(var2);
 
             let elseblk_idx = self.push_single_value_scope(AVar {
@@ -188,7 +337,10 @@ impl SemanticAnalyzerPass2 {
         }
 
         if *bopty == ST::or {
+            let unassigned = self.unassigned.clone();
             let var2 = self.analyze_expr(tt2);
+            // rhs may be skipped
+            self.unassigned.extend(unassigned);
             let elseblk_idx = self.push_single_value_scope(var2);
 
             let ifblk_idx = self.push_single_value_scope(AVar {
@@ -208,7 +360,7 @@ impl SemanticAnalyzerPass2 {
         }
 
         let var2 = self.analyze_expr(tt2);
-        let sym2 = self.bind_value(var2.clone());
+        let sym2 = self.bind_value_at(var2.clone(), tt[2].1.span());
         let symdef2 = ASymDef::new(sym2, var2.ty.clone());
 
         let (res_symdef1, res_symdef2) =
@@ -310,6 +462,15 @@ impl SemanticAnalyzerPass2 {
     // }
 
     pub(crate) fn analyze_path_expr(&mut self, tt: &TT) -> AVar {
+        let var = self.analyze_place_expr(tt);
+        self.mark_used(&var);
+        self.check_assigned(&var, tt[0].1.span());
+
+        var
+    }
+
+    /// Path expr as left value
+    pub(crate) fn analyze_place_expr(&mut self, tt: &TT) -> AVar {
         let p = 0;
 
         debug_assert_eq!(tt[p].0, ST::PathExprSeg);
@@ -320,7 +481,10 @@ impl SemanticAnalyzerPass2 {
         let idtok = seg0[0].1.as_tok();
         let id = idtok.value;
 
-        self.find_explicit_sym_or_diagnose(id, idtok.span)
+        let var = self.find_explicit_sym_or_diagnose(id, idtok.span);
+        self.write_ref(idtok.span, ARef::Binding(var.clone()));
+
+        var
     }
 
     pub(crate) fn analyze_side_effect_expr(&mut self, tt: &TT) -> AVar {
@@ -347,6 +511,9 @@ impl SemanticAnalyzerPass2 {
         let op = if op == ST::inc { ST::add } else { ST::sub };
         let var_id = idt.value;
         let var = self.find_explicit_sym_or_diagnose(var_id, idt.span());
+        self.write_ref(idt.span(), ARef::Binding(var.clone()));
+        self.mark_used(&var);
+        self.check_assigned(&var, idt.span());
         let var_ty = var.ty.clone();
         let id = self.bind_value(var.clone());
 
@@ -374,7 +541,7 @@ impl SemanticAnalyzerPass2 {
             ty: symdef1.ty.clone(),
             val,
         };
-        self.assign_var(var_id, nxt_var.clone());
+        self.assign_var(var_id, nxt_var.clone(), idt.span());
 
         if fst_get {
             var
@@ -397,14 +564,59 @@ impl SemanticAnalyzerPass2 {
         }
     }
 
+    /// Capture stdout, exit status (and stderr of `err#`) of the command,
+    /// printing is up to user
     pub(crate) fn analyze_cmd_expr(&mut self, tt: &TT) -> AVar {
-        let mut sns = tt.subs.iter();
+        let (capture_err, cmd_sym) = match self.build_cmd(tt) {
+            Ok(res) => res,
+            Err(var) => return var,
+        };
+
+        let capture_err = self.bind_value(AVar {
+            ty: aty_i32(),
+            val: AVal::ConstAlias(ConstVal::Int(capture_err as i32)),
+        });
+
+        let exec_fndec = self.find_func_by_name(str2sym("exec")).unwrap();
 
-        let (_st, sn) = sns.next().unwrap();
-        let idt = *sn.as_tok();
+        // owned by the scope binding it, see `bind_value_at`
+        exec_fndec.fn_call_val(&[cmd_sym, capture_err])
+    }
+
+    /// Command in statement position streams its output to terminal
+    pub(crate) fn analyze_cmd_stmt(&mut self, tt: &TT) -> AVar {
+        let cmd_sym = match self.build_cmd(tt) {
+            Ok((_capture_err, cmd_sym)) => cmd_sym,
+            Err(var) => return var,
+        };
+
+        let exec_fndec =
+            self.find_func_by_name(str2sym("exec_stream")).unwrap();
+
+        exec_fndec.fn_call_val(&[cmd_sym])
+    }
+
+    /// If stderr is captured and the command string with `$name` replaced,
+    /// or else the undefined var
+    fn build_cmd(&mut self, tt: &TT) -> Result<(bool, Symbol), AVar> {
+        let mut p = 0;
+        let mut capture_err = false;
+
+        if tt[p].0 == ST::tag {
+            let tok = tt[p].1.as_tok();
+
+            match self.analyze_tag(tok) {
+                ATag::ERR => capture_err = true,
+                ATag::RAW => self.write_dialogsis(R::UnkonwTag, tok.span),
+                ATag::PH => (),
+            }
+            p += 1;
+        }
+
+        let idt = *tt[p].1.as_tok();
 
         // extract symbol from tokv
-        let syms = extract_symbol(sn.as_tok().value);
+        let syms = extract_symbol(idt.value);
         let mut sym_syms = Vec::with_capacity(syms.len());
         let mut string_syms = Vec::with_capacity(syms.len());
 
@@ -412,14 +624,16 @@ impl SemanticAnalyzerPass2 {
         for sym in syms.iter() {
             let var = self.find_explicit_sym_or_diagnose(*sym, idt.span());
             if var.ty == AType::PH {
-                return var;
+                return Err(var);
             }
+            self.mark_used(&var);
+            self.check_assigned(&var, idt.span());
             string_syms.push(self.build_strinify_var(var, idt.span()));
             sym_syms.push(self.build_const_str(*sym));
         }
 
         // string replace
-        let arg0 = self.build_const_str(sn.as_tok().value);
+        let arg0 = self.build_const_str(idt.value);
         let arg1 = self.build_const_vec_str(sym_syms);
         let arg2 = self.build_const_vec_str(string_syms);
 
@@ -427,18 +641,10 @@ impl SemanticAnalyzerPass2 {
             .find_func_by_name(str2sym("cmd_symbols_replace"))
             .unwrap();
 
-        let cmd_sym =
-            self.bind_value(cmd_fndec.fn_call_val(&[arg0, arg1, arg2]));
-
-        let exec_fndec = self.find_func_by_name(str2sym("exec")).unwrap();
-
-        let exec_res = self.bind_value(exec_fndec.fn_call_val(&[cmd_sym]));
-        let ctlstr = self.build_const_str(str2sym("%s\n"));
+        let cmd_sym = self
+            .bind_owned(cmd_fndec.fn_call_val(&[arg0, arg1, arg2]), "free");
 
-        // print stdout
-        let printf_fndec = self.find_func_by_name(str2sym("printf")).unwrap();
-
-        printf_fndec.fn_call_val(&[ctlstr, exec_res])
+        Ok((capture_err, cmd_sym))
     }
 
     pub(crate) fn analyze_if_expr(&mut self, tt: &TT) -> AVar {
@@ -451,17 +657,22 @@ impl SemanticAnalyzerPass2 {
 
         let mut if_exprs = vec![];
         let mut else_blk = None;
+        // unassigned ones at the end of each branch
+        let mut branch_unassigned = vec![];
 
         while !p.reach_end() {
             let cond_var = self.analyze_expr(tt[*p].1.as_tt());
-            let cond_sym = self.bind_value(cond_var);
+            let cond_sym = self.bind_value_at(cond_var, tt[*p].1.span());
             p.inc();
 
+            let unassigned = self.unassigned.clone();
             let if_expr_var =
                 self.analyze_block_expr(tt[*p].1.as_tt());
             let if_expr_scope_idx = if_expr_var.val.as_block_expr_idx();
             p.inc();
             if_exprs.push((cond_sym, if_expr_scope_idx));
+            branch_unassigned
+                .push(std::mem::replace(&mut self.unassigned, unassigned));
 
             if !p.reach_end() {
                 if tt[*p].0 == ST::r#else {
@@ -479,6 +690,11 @@ impl SemanticAnalyzerPass2 {
             }
         }
 
+        // without else, it falls through when all conds are false
+        for unassigned in branch_unassigned {
+            self.unassigned.extend(unassigned);
+        }
+
         // Check if_exprs and else ret type
         let if_ty = &self.amod.scopes[if_exprs[0].1].as_var().ty;
         let mut conds = if_exprs.iter().skip(1);
@@ -527,19 +743,22 @@ impl SemanticAnalyzerPass2 {
     }
 
     pub(crate) fn analyze_infi_loop_expr(&mut self, tt: &TT) -> AVar {
+        self.break_unassigned.push(None);
         let var = self.analyze_block_expr(tt[1].1.as_tt());
         let scope_id = var.val.as_block_expr_idx();
 
-        self.sc.push(scope_id);
-
-        if let Some(ref avar) = self.cur_scope().break_var {
-            self.cur_scope_mut().tail.ty = avar.ty.clone();
-        } else {
-            self.cur_scope_mut().tail.ty = AType::Never;
-        }
-        let ty = self.cur_scope().as_var().ty;
-
-        self.sc.pop();
+        // loop is left only through `break`
+        let ty = match self.break_unassigned.pop().unwrap() {
+            Some(unassigned) => {
+                self.unassigned = unassigned;
+                AType::Void
+            }
+            None => {
+                self.diverge();
+                AType::Never
+            }
+        };
+        self.amod.scopes[scope_id].tail.ty = ty.clone();
 
         let val = AVal::InfiLoopExpr(scope_id);
 
@@ -552,12 +771,19 @@ impl SemanticAnalyzerPass2 {
             val: AVal::Break,
         };
 
-        self.cur_scope_mut().break_var = Some(var.clone());
+        if let Some(broken) = self.break_unassigned.last_mut() {
+            broken
+                .get_or_insert_default()
+                .extend(self.unassigned.iter().cloned());
+        }
+        self.diverge();
 
         var
     }
 
     pub(crate) fn analyze_continue_expr(&mut self, _tt: &TT) -> AVar {
+        self.diverge();
+
         AVar {
             ty: AType::Void,
             val: AVal::Continue,
@@ -570,25 +796,67 @@ impl SemanticAnalyzerPass2 {
         tt: &TT,
     ) {
         self.sc.push(scope_idx);
+        let outer_span = self.cur_span;
+        // span of the first `ret` stmt
+        let mut ret_span: Option<Span> = None;
+        let mut unreachable_reported = false;
 
         for (ty, sn) in tt.iter() {
+            self.cur_span = sn.span();
+
+            // report only the first unreachable one
+            if let Some(span) = ret_span
+                && !unreachable_reported
+            {
+                unreachable_reported = true;
+                self.write_lint(LintCause::UnreachableCode(span), sn.span());
+            }
+
             if *ty == ST::Stmt {
                 self.do_analyze_stmt(sn.as_tt());
+
+                if ret_span.is_none()
+                    && let Some(mir) = self.cur_scope().mirs.last()
+                    && matches!(mir.val, AVal::Return(..))
+                {
+                    ret_span = Some(sn.span());
+                }
             } else if *ty == ST::Expr {
                 // Stmts ret value
                 self.cur_scope_mut().tail = self.analyze_expr(sn.as_tt());
+                self.move_tail_owned();
                 break;
             } else {
                 unreachable!("{:#?}", ty)
             }
         }
 
+        self.cur_span = outer_span;
         self.sc.pop();
     }
 
     /// Side Effect Exec
     pub(crate) fn do_analyze_expr(&mut self, tt: &TT) {
-        let avar = self.analyze_expr(tt);
+        let avar = if tt[0].0 == ST::CmdExpr {
+            self.analyze_cmd_stmt(tt[0].1.as_tt())
+        } else {
+            self.analyze_expr(tt)
+        };
+
+        // only the call written by user, not the one of desugared cmd
+        if tt[0].0 == ST::FunCallExpr
+            && let AVal::FnCall { call_fn, .. } = &avar.val
+            && !matches!(avar.ty, AType::Void | AType::Never | AType::PH)
+        {
+            let name = self
+                .amod
+                .afns
+                .get(call_fn)
+                .map(|afn| afn.idt.value)
+                .or_else(|| unmangling(*call_fn).map(|(base, _)| base))
+                .unwrap_or(*call_fn);
+            self.write_lint(LintCause::UnusedResult(name), self.cur_span);
+        }
 
         self.bind_value(avar);
     }
@@ -25,6 +25,10 @@ impl SemanticAnalyzerPass2 {
     pub(crate) fn do_analyze_fn(&mut self, name: Symbol, body: TT) {
         // Set current fn name
         self.cur_fn = Some(name);
+        self.cur_span = body[0].1.as_tok().span;
+        self.used_bindings.clear();
+        self.unassigned.clear();
+        self.owners.clear();
 
         /* Unpack Param (into body) */
 
@@ -44,12 +48,15 @@ impl SemanticAnalyzerPass2 {
                         (0, AVar { ty: param_pat.ty.clone(), val: aval })
                     ));
 
+                let span = self.cur_span;
+
                 self.cur_scope_mut().mirs.push(MIR::bind_value(
                     param_pat.formal,
                     AVar {
                         ty: param_pat.ty.clone(),
                         val: AVal::FnParam(i as u32),
                     },
+                    span,
                 ))
             }
         }
@@ -91,7 +98,8 @@ impl SemanticAnalyzerPass2 {
             );
             let tail_return_mir = MIR::bind_value(
                 self.cur_scope().tmp_name(),
-                tail_return_avar
+                tail_return_avar,
+                span
             );
 
             self.cur_scope_mut().mirs.push(tail_return_mir);
@@ -111,6 +119,8 @@ impl SemanticAnalyzerPass2 {
             val,
         });
 
+        self.lint_unused_bindings(name, scope_idx);
+
         // Unset current fn name
         self.cur_fn = None;
     }
@@ -1,16 +1,19 @@
+use std::collections::{HashMap, HashSet};
+
 use indexmap::indexmap;
 use m6entry::KVEntry as Entry;
 use m6lexerkit::{str2sym, sym2str, Span, SrcFileInfo, Symbol, Token};
 
 use super::{
      analyze_pat_no_top, analyze_ty,
-    aty_int, aty_str, write_diagnosis, AMod, AScope, ASymDef, AType,
-    AVal, AVar, AnExtFnDec, ConstVal, ExtSymSet, SemanticError,
-    SemanticErrorReason as R, MIR, TokenTree2, APriType, ATag,
+    aty_cmd, aty_int, aty_str, write_diagnosis, AMod, AScope, ASymDef, AType,
+    A3ttrName, ARef, AVal, AVar, AnExtFnDec, ConstVal, ExtSymSet, SemanticError,
+    SemanticErrorReason as R, MIR, RefLists, TokenTree2, APriType, ATag,
 };
 use crate::{
     codegen::is_implicit_sym,
-    name_mangling::mangling,
+    lint::{LintCause, LintLists},
+    name_mangling::{base_name, mangling},
     parser::{SyntaxType as ST, TT},
 };
 
@@ -35,8 +38,50 @@ pub struct SemanticAnalyzerPass2 {
 
     sc: Vec<usize>, // Scope Counter,
     cur_fn: Option<Symbol>,
+    /// Span of the statement being analyzed
+    cur_span: Span,
 
     cause_lists: Vec<(R, Span)>,
+    ref_lists: RefLists,
+
+    /* Lint */
+
+    lint_lists: LintLists,
+    /// Bindings read in current fn
+    used_bindings: HashSet<BindingKey>,
+    /// Functions of current module called
+    used_fns: HashSet<Symbol>,
+
+    /* Definite Assignment */
+
+    /// Local variables possibly unassigned at current point
+    unassigned: HashSet<(Symbol, usize)>,
+    /// Unassigned ones at `break` of each enclosing loop (None if no `break`)
+    break_unassigned: Vec<Option<HashSet<(Symbol, usize)>>>,
+
+    /* Ownership */
+
+    /// Owner (scope, temp) of the call result each local variable refers to
+    owners: HashMap<(Symbol, usize), (usize, Symbol)>,
+}
+
+
+/// Identity of an explicit binding (param or local variable)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum BindingKey {
+    Param(u32),
+    Local(Symbol, usize),
+}
+
+
+impl BindingKey {
+    fn of(val: &AVal) -> Option<Self> {
+        match val {
+            AVal::FnParam(i) => Some(Self::Param(*i)),
+            AVal::Var(sym, tagid) => Some(Self::Local(*sym, *tagid)),
+            _ => None,
+        }
+    }
 }
 
 
@@ -44,10 +89,11 @@ pub(crate) type Pass2Result = Result<Pass2Export, SemanticError>;
 
 
 pub struct Pass2Export {
-    #[allow(unused)]
     pub src: SrcFileInfo,
     pub amod: AMod,
-    pub ess: ExtSymSet
+    pub ess: ExtSymSet,
+    pub(crate) lints: LintLists,
+    pub(crate) refs: RefLists,
 }
 
 
@@ -68,7 +114,15 @@ impl SemanticAnalyzerPass2 {
             ess,
             sc: vec![0], // 0 is root
             cur_fn: None,
+            cur_span: Span::default(),
             cause_lists: vec![],
+            ref_lists: vec![],
+            lint_lists: vec![],
+            used_bindings: HashSet::new(),
+            used_fns: HashSet::new(),
+            unassigned: HashSet::new(),
+            break_unassigned: vec![],
+            owners: HashMap::new(),
         };
 
         it.analyze(tt)
@@ -79,11 +133,15 @@ impl SemanticAnalyzerPass2 {
             self.do_analyze_item(anitem);
         }
 
+        self.lint_dead_code();
+
         if self.cause_lists.is_empty() {
             Ok(Pass2Export {
                 src: self.src,
                 amod: self.amod,
-                ess: self.ess
+                ess: self.ess,
+                lints: self.lint_lists,
+                refs: self.ref_lists,
             })
         } else {
             Err(SemanticError {
@@ -97,6 +155,10 @@ impl SemanticAnalyzerPass2 {
         write_diagnosis(&mut self.cause_lists, r, span)
     }
 
+    fn write_lint(&mut self, cause: LintCause, span: Span) {
+        self.lint_lists.push((cause, span, self.cur_fn))
+    }
+
     fn cur_scope(&self) -> &AScope {
         &self.amod.scopes[*self.sc.last().unwrap()]
     }
@@ -163,6 +225,71 @@ impl SemanticAnalyzerPass2 {
         }
     }
 
+    /// Mangled functions named `base` in module and dependencies, in the
+    /// priority of `find_func_by_name`
+    pub(crate) fn find_overloads(&self, base: Symbol) -> Vec<AnExtFnDec> {
+        let mut cands: Vec<AnExtFnDec> = vec![];
+
+        let decs = self
+            .amod
+            .afns
+            .values()
+            .map(|afn| afn.as_ext_fn_dec())
+            .chain(self.amod.efns.values().cloned())
+            .chain(self.ess.afns_iter().cloned());
+
+        for dec in decs {
+            if !dec.attrs.has(A3ttrName::NoMangle)
+                && base_name(dec.full_name) == base
+                && cands.iter().all(|cand| cand.full_name != dec.full_name)
+            {
+                cands.push(dec);
+            }
+        }
+
+        cands
+    }
+
+    /// Overload with the least total conversion cost of `tys`, or else all
+    /// the tied candidates (empty if none is viable)
+    pub(crate) fn resolve_overload(
+        &self,
+        base: Symbol,
+        tys: &[AType],
+    ) -> Result<AnExtFnDec, Vec<AnExtFnDec>> {
+        let mut best = None;
+        let mut tied = vec![];
+
+        for cand in self.find_overloads(base) {
+            if cand.params.len() != tys.len() {
+                continue;
+            }
+
+            let cost = tys
+                .iter()
+                .zip(cand.params.iter())
+                .map(|(ty, param)| ty.conv_cost(&param.ty))
+                .sum::<Option<usize>>();
+
+            let Some(cost) = cost else { continue };
+
+            match best {
+                Some(min) if cost > min => (),
+                Some(min) if cost == min => tied.push(cand),
+                _ => {
+                    best = Some(cost);
+                    tied = vec![cand];
+                }
+            }
+        }
+
+        if tied.len() == 1 {
+            Ok(tied.pop().unwrap())
+        } else {
+            Err(tied)
+        }
+    }
+
     pub(crate) fn find_explicit_sym_ty_and_tag(
         &self,
         sym: &Symbol,
@@ -195,6 +322,40 @@ impl SemanticAnalyzerPass2 {
         }
     }
 
+    fn write_ref(&mut self, span: Span, aref: ARef) {
+        if let Some(fname) = self.cur_fn {
+            self.ref_lists.push((span, fname, aref))
+        }
+    }
+
+    /// Record read of explicit binding
+    pub(crate) fn mark_used(&mut self, var: &AVar) {
+        if let Some(key) = BindingKey::of(&var.val) {
+            self.used_bindings.insert(key);
+        }
+    }
+
+    /// Read of explicit binding before any assignment is an error
+    pub(crate) fn check_assigned(&mut self, var: &AVar, span: Span) {
+        let AVal::Var(sym, tagid) = var.val else { return };
+
+        // report once for each binding
+        if self.unassigned.remove(&(sym, tagid)) {
+            let decl = self
+                .cur_fn
+                .and_then(|fname| self.amod.alloc_spans.get(&fname))
+                .and_then(|spans| spans.get(&(sym, tagid)))
+                .cloned();
+
+            self.write_dialogsis(R::UseBeforeAssign { name: sym, decl }, span);
+        }
+    }
+
+    /// Rest of the block is unreachable, so nothing is unassigned there
+    pub(crate) fn diverge(&mut self) {
+        self.unassigned.clear();
+    }
+
     pub(crate) fn lift_tys_or_diagnose(
         &mut self,
         op: ST,
@@ -215,22 +376,30 @@ impl SemanticAnalyzerPass2 {
             if ty1 != ty {
                 let val = AVal::TypeCast {
                     name: sym1,
+                    from: ty1,
                     ty: ty.clone(),
                 };
-                let res_sym1 = self.bind_value(AVar {
-                    ty: ty.clone(),
-                    val,
-                });
+                let res_sym1 = self.bind_value_at(
+                    AVar {
+                        ty: ty.clone(),
+                        val,
+                    },
+                    span,
+                );
                 res_sym_def1 = ASymDef { name: res_sym1, ty };
             } else if ty2 != ty {
                 let val = AVal::TypeCast {
                     name: sym2,
+                    from: ty2,
                     ty: ty.clone(),
                 };
-                let res_sym2 = self.bind_value(AVar {
-                    ty: ty.clone(),
-                    val,
-                });
+                let res_sym2 = self.bind_value_at(
+                    AVar {
+                        ty: ty.clone(),
+                        val,
+                    },
+                    span,
+                );
                 res_sym_def2 = ASymDef { name: res_sym2, ty };
             }
 
@@ -250,21 +419,53 @@ impl SemanticAnalyzerPass2 {
 
     /// For Implicit Symbol
     pub(crate) fn bind_value(&mut self, var: AVar) -> Symbol {
+        self.bind_value_at(var, self.cur_span)
+    }
+
+    /// Bind at the span of the expression instead of the statement, so
+    /// runtime checks of `var` report where it is
+    pub(crate) fn bind_value_at(&mut self, var: AVar, span: Span) -> Symbol {
+        // call result is a new one, the binding scope owns it
+        let drop_fn = match var.val {
+            AVal::FnCall { call_fn, .. } => self.result_drop_fn(call_fn),
+            _ => None,
+        };
         let scope = self.cur_scope_mut();
 
         let tmp = scope.tmp_name();
-        scope.mirs.push(MIR::bind_value(tmp, var));
+        scope.mirs.push(MIR::bind_value(tmp, var, span));
         scope.implicit_bindings.insert(tmp, scope.mirs.len() - 1);
 
+        if let Some(drop_fn) = drop_fn {
+            self.own(tmp, drop_fn);
+        }
+
         tmp
     }
 
     /// For Explicit Symbol
-    pub(crate) fn assign_var(&mut self, sym: Symbol, var: AVar) -> Symbol {
+    pub(crate) fn assign_var(
+        &mut self,
+        sym: Symbol,
+        var: AVar,
+        span: Span,
+    ) -> Symbol {
         if let Some((tagid, _ty)) = self.find_explicit_sym_ty_and_tag(&sym) {
-            self.cur_scope_mut()
-                .mirs
-                .push(MIR::assign_var(sym, tagid, var));
+            self.unassigned.remove(&(sym, tagid));
+
+            if var.ty == aty_cmd() || var.ty == aty_str() {
+                // bound first to track the owner of the result
+                let var_ty = var.ty.clone();
+                let valsym = self.bind_value_at(var, span);
+                self.move_owned(valsym, Some((sym, tagid)));
+
+                let val = AVal::Assign(sym, tagid, valsym);
+                self.bind_value_at(AVar { ty: var_ty, val }, span);
+            } else {
+                self.cur_scope_mut()
+                    .mirs
+                    .push(MIR::assign_var(sym, tagid, var, span));
+            }
         } else {
             unreachable!("Compiler Bug Unmatched sym {}", sym2str(sym))
         }
@@ -273,20 +474,30 @@ impl SemanticAnalyzerPass2 {
     }
 
     /// Create a local variable (alloc) without initialization
-    pub(crate) fn create_var(&mut self, sym: Symbol, ty: AType) {
+    pub(crate) fn create_var(&mut self, sym: Symbol, ty: AType, span: Span) {
         let fn_alloc =
             self.amod.allocs.get_mut(&self.cur_fn.unwrap()).unwrap();
 
-        // get last tagid
-        let mut tagid = 0;
-        for (scan_sym, scan_tagid) in fn_alloc.keys().rev() {
-            if sym == *scan_sym {
-                tagid = scan_tagid + 1;
-                break;
-            }
-        }
+        // get next tagid (module level variables share the namespace)
+        let tagid = fn_alloc
+            .keys()
+            .chain(self.amod.globals.keys())
+            .chain(self.amod.eglobals.keys())
+            .filter(|(scan_sym, _)| *scan_sym == sym)
+            .map(|(_, scan_tagid)| scan_tagid + 1)
+            .max()
+            .unwrap_or(0);
 
         fn_alloc.insert((sym, tagid), ty.clone());
+
+        self.amod
+            .alloc_spans
+            .entry(self.cur_fn.unwrap())
+            .or_default()
+            .insert((sym, tagid), span);
+
+        self.unassigned.insert((sym, tagid));
+
         let val = AVal::Var(sym, tagid);
 
         let scope = self.cur_scope_mut();
@@ -294,9 +505,15 @@ impl SemanticAnalyzerPass2 {
         scope.explicit_bindings.push(Entry(sym, (tagid, AVar { ty, val })));
     }
 
-    pub(crate) fn cast_val(&mut self, varsym: Symbol, ty: AType) -> Symbol {
+    pub(crate) fn cast_val(
+        &mut self,
+        varsym: Symbol,
+        from: AType,
+        ty: AType,
+    ) -> Symbol {
         let castval = AVal::TypeCast {
             name: varsym,
+            from,
             ty: ty.clone(),
         };
 
@@ -312,7 +529,7 @@ impl SemanticAnalyzerPass2 {
             AType::PH => str2sym(""),
             _ => {
                 let sym = self.bind_value(var.clone());
-                let arg0 = sym;
+                let mut arg0 = sym;
 
                 let fullname =
                 match var.ty {
@@ -320,6 +537,16 @@ impl SemanticAnalyzerPass2 {
                         APriType::Float(_) => "stringify_f64",
                         APriType::Int(_) => "stringify_i32",
                         APriType::Ptr => "strdup",
+                        // stdout of command, it's borrowed from the result
+                        APriType::OpaqueStruct(_) if var.ty == aty_cmd() => {
+                            let stdout_fndec = self
+                                .find_func_by_name(str2sym("cmd_stdout"))
+                                .unwrap();
+                            arg0 = self
+                                .bind_value(stdout_fndec.fn_call_val(&[sym]));
+
+                            "strdup"
+                        }
                         APriType::OpaqueStruct(_) => todo!(),
                     },
                     AType::Arr(_, _) => todo!(),
@@ -342,11 +569,140 @@ impl SemanticAnalyzerPass2 {
                     ret_var = AVar::undefined()
                 }
 
-                self.bind_value(ret_var)
+                self.bind_owned(ret_var, "free")
             }
         }
     }
 
+    /// Bind value allocated on heap by runtime, it's owned by current scope
+    /// and dropped by `drop_fn` at scope exit
+    pub(super) fn bind_owned(&mut self, var: AVar, drop_fn: &str) -> Symbol {
+        let undefined = var.ty == AType::PH;
+        let sym = self.bind_value(var);
+
+        if !undefined {
+            self.own(sym, drop_fn);
+        }
+
+        sym
+    }
+
+    /// Drop fn of the heap value returned by a call of `call_fn`, if it's a
+    /// new one. `cmd` returned by bas fns is new since `build_ret` rejects
+    /// the borrowed one, and `stdout`/`stderr` return a copy of the output.
+    fn result_drop_fn(&self, call_fn: Symbol) -> Option<&'static str> {
+        let afndec = self.find_func_by_name(call_fn)?;
+
+        if afndec.ret == aty_cmd()
+            && (sym2str(call_fn) == "exec"
+                || !afndec.attrs.has(A3ttrName::NoMangle))
+        {
+            return Some("cmd_drop");
+        }
+
+        ["stdout", "stderr"]
+            .into_iter()
+            .any(|name| mangling(str2sym(name), &[aty_cmd()]) == call_fn)
+            .then_some("free")
+    }
+
+    fn own(&mut self, sym: Symbol, drop_fn: &str) {
+        let drop_fn = str2sym(drop_fn);
+
+        if self.find_func_by_name(drop_fn).is_some() {
+            self.cur_scope_mut().owned.push((sym, drop_fn));
+        }
+    }
+
+    /// Owner of the heap value bound to `valsym` of current scope
+    fn owner_of(&self, valsym: Symbol) -> Option<(usize, Symbol)> {
+        let scope_idx = *self.sc.last().unwrap();
+        let scope = &self.amod.scopes[scope_idx];
+
+        if scope.owned.iter().any(|(sym, _)| *sym == valsym) {
+            return Some((scope_idx, valsym));
+        }
+
+        let mir = &scope.mirs[*scope.implicit_bindings.get(&valsym)?];
+
+        self.var_owner(&mir.val)
+    }
+
+    fn var_owner(&self, val: &AVal) -> Option<(usize, Symbol)> {
+        match val {
+            AVal::Var(sym, tagid) => {
+                self.owners.get(&(*sym, *tagid)).copied()
+            }
+            _ => None,
+        }
+    }
+
+    /// The owned value of `valsym` is moved into variable `var` or out of
+    /// the fn (None). It escapes, i.e. it isn't dropped any more, if the
+    /// destination may outlive its owner scope.
+    pub(super) fn move_owned(
+        &mut self,
+        valsym: Symbol,
+        var: Option<(Symbol, usize)>,
+    ) {
+        let owner = self.owner_of(valsym);
+
+        let Some(key) = var else {
+            if let Some(owner) = owner {
+                self.escape_owned(owner);
+            }
+            return;
+        };
+
+        let Some(owner) = owner else {
+            self.owners.remove(&key);
+            return;
+        };
+
+        self.owners.insert(key, owner);
+
+        // scope declaring the variable
+        let mut scope_idx = *self.sc.last().unwrap();
+
+        while self.amod.scopes[scope_idx]
+            .in_scope_find_sym(&key.0)
+            .is_none()
+            && let Some(paren) = self.amod.scopes[scope_idx].paren
+        {
+            scope_idx = paren;
+        }
+
+        // owner is it or one of its ancestors
+        let mut outlived = Some(scope_idx);
+
+        while let Some(idx) = outlived
+            && idx != owner.0
+        {
+            outlived = self.amod.scopes[idx].paren;
+        }
+
+        if outlived.is_none() {
+            self.escape_owned(owner);
+        }
+    }
+
+    /// The owned value of scope tail escapes if current scope owns it
+    pub(super) fn move_tail_owned(&mut self) {
+        let scope_idx = *self.sc.last().unwrap();
+
+        if let Some(owner) = self.var_owner(&self.cur_scope().tail.val)
+            && owner.0 == scope_idx
+        {
+            self.escape_owned(owner);
+        }
+    }
+
+    fn escape_owned(&mut self, (scope_idx, tmp): (usize, Symbol)) {
+        self.amod.scopes[scope_idx]
+            .owned
+            .retain(|(sym, _)| *sym != tmp);
+    }
+
     pub(super) fn build_const_str(&mut self, sym: Symbol) -> Symbol {
         let val = AVal::ConstAlias(ConstVal::Str(sym));
         let ty = aty_str();
@@ -368,7 +724,7 @@ impl SemanticAnalyzerPass2 {
         let fndec = self.find_func_by_name(
             str2sym("vec_new_ptr"),
         ).unwrap();
-        let sym_vec = self.bind_value(fndec.fn_call_val(&[cap]));
+        let sym_vec = self.bind_owned(fndec.fn_call_val(&[cap]), "vec_drop");
 
         for s in strs.into_iter() {
             let sym_str = if is_implicit_sym(s) {
@@ -409,15 +765,31 @@ impl SemanticAnalyzerPass2 {
         let retty = avar.ty.clone();
         let retsym;
 
+        // Placeholder return type means that it's inferred from body,
+        // e.g. the REPL snippet function
+        let infer_ret = afndec.ret == AType::PH;
+
+        if infer_ret && retty != AType::Never {
+            self.amod.afns.get_mut(&fname).unwrap().ret = retty.clone();
+        }
+
         if retty != AType::Void {
             // calc implicit symbol
-            retsym = Some(self.bind_value(avar));
+            let sym = self.bind_value_at(avar, span);
+
+            // the caller owns returned `cmd`, so it must be a new one
+            if retty == aty_cmd() && self.owner_of(sym).is_none() {
+                self.write_dialogsis(R::ReturnBorrowedCmd, span);
+            }
+
+            self.move_owned(sym, None);
+            retsym = Some(sym);
         }
         else {
             retsym = None;
         }
 
-        if retty != AType::Never && retty != afndec.ret {
+        if !infer_ret && retty != AType::Never && retty != afndec.ret {
             self.write_dialogsis(
                 R::UnmatchedType(
                     afndec.ret.clone(),
@@ -430,11 +802,122 @@ impl SemanticAnalyzerPass2 {
             return AVar::undefined();
         }
 
+        self.diverge();
+
         AVar { ty: AType::Void, val: AVal::Return(retsym) }
 
     }
 
 
+    ///////////////////////////////////////////////////////////////////////////
+    //// Lint
+
+    /// Unused params and local variables of fn `name` (after analyzed)
+    pub(crate) fn lint_unused_bindings(
+        &mut self,
+        name: Symbol,
+        scope_idx: usize,
+    ) {
+        let afn = &self.amod.afns[&name];
+        let alloc_spans = self.amod.alloc_spans.get(&name);
+        let mut unused = vec![];
+
+        // scopes of a fn are pushed continuously
+        for scope in self.amod.scopes[scope_idx..].iter() {
+            for Entry(sym, (_tagid, var)) in scope.explicit_bindings.iter() {
+                if sym2str(*sym).starts_with('_') {
+                    continue;
+                }
+
+                let Some(key) = BindingKey::of(&var.val) else { continue };
+
+                if self.used_bindings.contains(&key) {
+                    continue;
+                }
+
+                match key {
+                    BindingKey::Param(i) => unused.push((
+                        LintCause::UnusedParam(*sym),
+                        afn.params[i as usize].span,
+                    )),
+                    BindingKey::Local(sym, tagid) => {
+                        if let Some(span) = alloc_spans
+                            .and_then(|spans| spans.get(&(sym, tagid)))
+                        {
+                            unused
+                                .push((LintCause::UnusedVariable(sym), *span));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (cause, span) in unused {
+            self.write_lint(cause, span);
+        }
+    }
+
+    /// `let` rebinding `name` which is already bound in current scope
+    pub(crate) fn lint_shadowing(&mut self, name: Symbol, span: Span) {
+        if sym2str(name).starts_with('_') {
+            return;
+        }
+
+        let Some((_tagid, var)) = self.cur_scope().in_scope_find_sym(&name)
+        else {
+            return;
+        };
+
+        let prev = match BindingKey::of(&var.val) {
+            Some(BindingKey::Param(i)) => self
+                .cur_fn
+                .and_then(|fname| self.amod.afns.get(&fname))
+                .map(|afn| afn.params[i as usize].span),
+            Some(BindingKey::Local(sym, tagid)) => self
+                .cur_fn
+                .and_then(|fname| self.amod.alloc_spans.get(&fname))
+                .and_then(|spans| spans.get(&(sym, tagid)))
+                .cloned(),
+            None => None,
+        };
+
+        self.write_lint(LintCause::Shadowing(name, prev), span);
+    }
+
+    /// Functions and external declarations never called in module
+    pub(crate) fn lint_dead_code(&mut self) {
+        let mut unused = vec![];
+
+        // module without `main` is a library, functions are exported
+        let is_bin = self.amod.afns.contains_key(&str2sym("main"));
+
+        for (name, afn) in self.amod.afns.iter() {
+            // no_mangle ones (include main) are exported, tests and
+            // benchmarks are called by harness
+            if is_bin
+                && !afn.attrs.has(A3ttrName::NoMangle)
+                && !afn.attrs.has(A3ttrName::Test)
+                && !afn.attrs.has(A3ttrName::Bench)
+                && !self.used_fns.contains(name)
+            {
+                let cause = LintCause::UnusedFn(afn.idt.value);
+
+                unused.push((cause, afn.idt.span, *name));
+            }
+        }
+
+        for (name, span) in self.amod.efn_spans.iter() {
+            if !self.used_fns.contains(name) {
+                unused.push((LintCause::UnusedExtFn(*name), *span, *name));
+            }
+        }
+
+        for (cause, span, item) in unused {
+            self.lint_lists.push((cause, span, Some(item)));
+        }
+    }
+
+
     ////////////////////////////////////////////////////////////////////////////////
     //// Other Analyze method
 
@@ -453,6 +936,9 @@ impl SemanticAnalyzerPass2 {
             "raw" => {
                 ATag::RAW
             },
+            "err" => {
+                ATag::ERR
+            },
             _ => {
                 self.write_dialogsis(
                     R::UnkonwTag,
@@ -465,3 +951,261 @@ impl SemanticAnalyzerPass2 {
     }
 }
 
+
+
+
+#[cfg(test)]
+mod tests {
+    use m6lexerkit::sym2str;
+
+    use crate::ast_lowering::{
+        aty_cmd, lower_src, lower_src_err, AType, AVal, ConstVal, Pass2Export,
+        SemanticErrorReason as R,
+    };
+
+    #[test]
+    fn test_definite_assignment() -> Result<(), Box<dyn std::error::Error>> {
+        let err = lower_src_err("./examples/err_unassigned.bath", false);
+
+        let lns = err
+            .cause_lists
+            .iter()
+            .map(|(cause, span)| {
+                let R::UseBeforeAssign { name, decl } = cause else {
+                    unreachable!()
+                };
+                assert_eq!(sym2str(*name), "a");
+                assert!(decl.is_some());
+
+                err.src.boffset2srcloc(span.from).ln
+            })
+            .collect::<Vec<_>>();
+
+        // if without else, break before assign, never assigned
+        assert_eq!(lns, vec![21, 59, 65]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_test_fn() -> Result<(), Box<dyn std::error::Error>> {
+        let err = lower_src_err("./examples/err_test_fn.bath", true);
+
+        // returning nothing or `int` is fine
+        let names = err
+            .cause_lists
+            .iter()
+            .map(|(reason, _)| match reason {
+                R::InvalidTestFn(name) => sym2str(*name),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["test_param", "test_str"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_overload() -> Result<(), Box<dyn std::error::Error>> {
+        let err = lower_src_err("./examples/err_ambiguous.bath", false);
+
+        // `half(3)` is resolved, only `pick(1, 2)` is reported
+        assert_eq!(err.cause_lists.len(), 1);
+
+        let (R::AmbiguousCall { name, cands, .. }, span) =
+            &err.cause_lists[0]
+        else {
+            unreachable!()
+        };
+
+        assert_eq!(sym2str(*name), "pick");
+        assert_eq!(
+            cands,
+            &[
+                "fn pick(a: i32, b: f64) -> i32",
+                "fn pick(a: f64, b: i32) -> i32",
+            ]
+        );
+        assert_eq!(err.src.boffset2srcloc(span.from).ln, 19);
+        assert_eq!(
+            err.diagnostics()[0].labels[0].message,
+            "arguments are (i32, i32)"
+        );
+
+        // candidates of core lib are collected with the module's
+        let Pass2Export { amod, .. } =
+            lower_src("./examples/overload_core.bath", true)?;
+
+        let calls = amod
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.mirs.iter())
+            .filter_map(|mir| match mir.val {
+                AVal::FnCall { call_fn, .. } => Some(sym2str(call_fn)),
+                _ => None,
+            })
+            .filter(|name| name.starts_with("status@"))
+            .collect::<Vec<_>>();
+        assert_eq!(calls, ["status@{CmdRes}", "status@i32"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_loop_type() -> Result<(), Box<dyn std::error::Error>> {
+        let Pass2Export { amod, .. } =
+            lower_src("./examples/loop_type.bath", true)?;
+
+        let loop_tys = amod
+            .scopes
+            .iter()
+            .flat_map(|scope| {
+                scope
+                    .mirs
+                    .iter()
+                    .map(|mir| (&mir.val, &mir.ty))
+                    .chain([(&scope.tail.val, &scope.tail.ty)])
+            })
+            .filter(|(val, _)| matches!(val, AVal::InfiLoopExpr(_)))
+            .map(|(_, ty)| ty.clone())
+            .collect::<Vec<_>>();
+
+        // `break` of the nested `if` leaves the loop of `until`
+        assert_eq!(loop_tys, [AType::Void, AType::Never]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_owned() -> Result<(), Box<dyn std::error::Error>> {
+        let Pass2Export { amod, .. } =
+            lower_src("./examples/owned.bath", true)?;
+
+        // loop body is dropped on each iteration
+        let scope = amod
+            .scopes
+            .iter()
+            .find(|scope| {
+                scope.mirs.iter().any(|mir| {
+                    matches!(
+                        mir.val,
+                        AVal::FnCall { call_fn, .. }
+                        if sym2str(call_fn) == "exec_stream"
+                    )
+                })
+            })
+            .unwrap();
+        let drop_fns = scope
+            .owned
+            .iter()
+            .map(|(_, drop_fn)| sym2str(*drop_fn))
+            .collect::<Vec<_>>();
+
+        // stringified `i`, two vecs of replacement and command, the output
+        // is streamed
+        assert_eq!(drop_fns, ["free", "vec_drop", "vec_drop", "free"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmd_expr() -> Result<(), Box<dyn std::error::Error>> {
+        let Pass2Export { amod, .. } = lower_src("./examples/cmd.bath", true)?;
+
+        let mirs = amod
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.mirs.iter())
+            .collect::<Vec<_>>();
+        let calls = mirs
+            .iter()
+            .filter_map(|mir| match mir.val {
+                AVal::FnCall { call_fn, ref args } => {
+                    Some((sym2str(call_fn), args, &mir.ty))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // captured with stderr
+        let (_, args, ty) =
+            calls.iter().find(|(name, ..)| name == "exec").unwrap();
+        assert_eq!(*ty, &aty_cmd());
+        assert!(mirs.iter().any(|mir| mir.name == args[1]
+            && matches!(mir.val, AVal::ConstAlias(ConstVal::Int(1)))));
+
+        // `$out` is its stdout, the statement streams
+        for name in ["cmd_stdout", "exec_stream", "status@{CmdRes}"] {
+            assert!(calls.iter().any(|(call_fn, ..)| call_fn == name));
+        }
+
+        // `out` and `res` of the loop body, the returned one escapes
+        let owners = amod
+            .scopes
+            .iter()
+            .filter(|scope| {
+                scope
+                    .owned
+                    .iter()
+                    .any(|(_, drop_fn)| sym2str(*drop_fn) == "cmd_drop")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(owners.len(), 2);
+        assert!(owners[1].mirs.iter().any(|mir| matches!(
+            mir.val,
+            AVal::FnCall { call_fn, .. }
+            if sym2str(call_fn).starts_with("newest")
+        )));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmd_ret() -> Result<(), Box<dyn std::error::Error>> {
+        let err = lower_src_err("./examples/err_cmd_ret.bath", true);
+
+        // the parameter passed through would be dropped twice by `main`
+        assert_eq!(err.cause_lists.len(), 1);
+        let (R::ReturnBorrowedCmd, span) = &err.cause_lists[0] else {
+            unreachable!()
+        };
+        assert_eq!(err.src.boffset2srcloc(span.from).ln, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmd_str() -> Result<(), Box<dyn std::error::Error>> {
+        let Pass2Export { amod, .. } =
+            lower_src("./examples/cmd_str.bath", true)?;
+
+        let is_stdout = |val: &AVal| {
+            matches!(
+                val,
+                AVal::FnCall { call_fn, .. }
+                if sym2str(*call_fn) == "stdout@{CmdRes}"
+            )
+        };
+
+        // the loop body and `greeting`, the copies escape from them, only
+        // the command results are dropped there
+        let scopes = amod
+            .scopes
+            .iter()
+            .filter(|scope| scope.mirs.iter().any(|mir| is_stdout(&mir.val)))
+            .collect::<Vec<_>>();
+        assert_eq!(scopes.len(), 2);
+
+        for scope in scopes {
+            assert!(scope
+                .owned
+                .iter()
+                .any(|(_, drop_fn)| sym2str(*drop_fn) == "cmd_drop"));
+            assert!(scope.owned.iter().all(|(sym, _)| {
+                !is_stdout(&scope.mirs[scope.implicit_bindings[sym]].val)
+            }));
+        }
+
+        Ok(())
+    }
+}
@@ -19,9 +19,12 @@ impl SemanticAnalyzerPass2 {
             /* get pat_no_top */
 
             let name = self.analyze_pat_no_top(tt[p].1.as_tt());
+            let pat_span = tt[p].1.span();
             let mut has_type_anno = false;
             p += 1;
 
+            self.lint_shadowing(name, pat_span);
+
 
             if tt[p].0 == ST::colon {
                 /* skip colon */
@@ -30,7 +33,7 @@ impl SemanticAnalyzerPass2 {
                 let ty = self.analyze_ty(tt[p].1.as_tt());
                 p += 1;
                 // need explicit type annotation
-                self.create_var(name, ty);
+                self.create_var(name, ty, pat_span);
                 has_type_anno = true;
             }
 
@@ -41,9 +44,9 @@ impl SemanticAnalyzerPass2 {
                 let var = self.analyze_expr(tt[p].1.as_tt());
 
                 if !has_type_anno {
-                    self.create_var(name, var.ty.clone());
+                    self.create_var(name, var.ty.clone(), pat_span);
                 }
-                self.assign_var(name, var);
+                self.assign_var(name, var, tt[p].1.span());
             }
 
             return;
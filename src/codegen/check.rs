@@ -0,0 +1,278 @@
+use inkwellkit::{
+    get_ctx,
+    module::Linkage,
+    values::{BasicValueEnum, FunctionValue, IntValue, PointerValue},
+    AddressSpace, FloatPredicate, IntPredicate,
+};
+
+use super::CodeGen;
+use crate::{config::OptLv, parser::SyntaxType as ST};
+
+
+/// Runtime abort function, declared in core lib
+const PANIC_FN: &str = "bas_panic";
+
+
+
+impl<'ctx> CodeGen<'ctx> {
+    ///////////////////////////////////////////////////////////////////////////
+    //// Runtime Checks (Debug Mode)
+
+    /// Source is required to locate the failed check
+    pub(crate) fn rt_checks(&self) -> bool {
+        self.config.optlv == OptLv::Debug && self.src.is_some()
+    }
+
+    /// add/sub/mul through `llvm.{s|u}<op>.with.overflow.iN` by the sign
+    pub(crate) fn build_checked_int_arith(
+        &mut self,
+        op: ST,
+        signed: bool,
+        operand1: IntValue<'ctx>,
+        operand2: IntValue<'ctx>,
+    ) -> IntValue<'ctx> {
+        let (intrinsic, opname) = match op {
+            ST::add => ("add", "add"),
+            ST::sub => ("sub", "subtract"),
+            ST::mul => ("mul", "multiply"),
+            _ => unreachable!("{:?}", op),
+        };
+
+        let int_t = operand1.get_type();
+        let name = format!(
+            "llvm.{}{}.with.overflow.i{}",
+            if signed { "s" } else { "u" },
+            intrinsic,
+            int_t.get_bit_width()
+        );
+
+        let fn_val = self.vmmod.module.get_function(&name).unwrap_or_else(|| {
+            let ret_t = get_ctx().struct_type(
+                &[int_t.into(), get_ctx().bool_type().into()],
+                false,
+            );
+
+            self.vmmod.module.add_function(
+                &name,
+                ret_t.fn_type(&[int_t.into(), int_t.into()], false),
+                None,
+            )
+        });
+
+        let res = self
+            .builder
+            .build_call(fn_val, &[operand1.into(), operand2.into()], "")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_struct_value();
+
+        let val = self.builder.build_extract_value(res, 0, "").unwrap();
+        let overflow = self.builder.build_extract_value(res, 1, "").unwrap();
+
+        self.build_rt_check(
+            overflow.into_int_value(),
+            &format!("attempt to {opname} with overflow"),
+        );
+
+        val.into_int_value()
+    }
+
+    pub(crate) fn build_zero_divisor_check(
+        &mut self,
+        divisor: BasicValueEnum<'ctx>,
+    ) {
+        let is_zero = if divisor.is_int_value() {
+            let divisor = divisor.into_int_value();
+
+            self.builder.build_int_compare(
+                IntPredicate::EQ,
+                divisor,
+                divisor.get_type().const_zero(),
+                "",
+            )
+        } else {
+            let divisor = divisor.into_float_value();
+
+            self.builder.build_float_compare(
+                FloatPredicate::OEQ,
+                divisor,
+                divisor.get_type().const_zero(),
+                "",
+            )
+        };
+
+        self.build_rt_check(is_zero, "attempt to divide by zero");
+    }
+
+    /// Signed division of the minimum by -1 overflows
+    pub(crate) fn build_div_overflow_check(
+        &mut self,
+        dividend: IntValue<'ctx>,
+        divisor: IntValue<'ctx>,
+    ) {
+        let int_t = dividend.get_type();
+        let bits = int_t.get_bit_width();
+
+        let is_min = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            dividend,
+            int_t.const_int(1 << (bits - 1), false),
+            "",
+        );
+        let is_neg_one = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            divisor,
+            int_t.const_all_ones(),
+            "",
+        );
+        let overflow = self.builder.build_and(is_min, is_neg_one, "");
+
+        self.build_rt_check(overflow, "attempt to divide with overflow");
+    }
+
+    /// Check `idx` against `vec_len(vec)` before `vec_get_*`/`vec_set_*`
+    pub(crate) fn build_bounds_check(
+        &mut self,
+        vec: PointerValue<'ctx>,
+        idx: IntValue<'ctx>,
+    ) {
+        let vec_len = self.vmmod.module.get_function("vec_len").unwrap_or_else(
+            || {
+                let i8ptr_t =
+                    get_ctx().i8_type().ptr_type(AddressSpace::Generic);
+
+                self.vmmod.module.add_function(
+                    "vec_len",
+                    get_ctx().i32_type().fn_type(&[i8ptr_t.into()], false),
+                    Some(Linkage::External),
+                )
+            },
+        );
+
+        let len = self
+            .builder
+            .build_call(vec_len, &[vec.into()], "")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+
+        // compare at the full width of the wider one, never truncate
+        let idx_bits = idx.get_type().get_bit_width();
+        let len_bits = len.get_type().get_bit_width();
+
+        let (idx, len) = if idx_bits < len_bits {
+            let idx =
+                self.builder.build_int_s_extend(idx, len.get_type(), "");
+            (idx, len)
+        } else if idx_bits > len_bits {
+            let len =
+                self.builder.build_int_z_extend(len, idx.get_type(), "");
+            (idx, len)
+        } else {
+            (idx, len)
+        };
+
+        // negative index is also out of bounds as unsigned
+        let out_of_bounds =
+            self.builder
+                .build_int_compare(IntPredicate::UGE, idx, len, "");
+
+        self.build_rt_check(out_of_bounds, "index out of bounds");
+    }
+
+    /// Abort with `<file>:<line>:<col>: <msg>` if `failed` is true
+    pub(crate) fn build_rt_check(
+        &mut self,
+        failed: IntValue<'ctx>,
+        msg: &str,
+    ) {
+        let bb_failed = self.insert_nonterminal_bb();
+        let bb_ok = self.insert_nonterminal_bb();
+
+        self.builder
+            .build_conditional_branch(failed, bb_failed, bb_ok);
+
+        self.builder.position_at_end(bb_failed);
+
+        let (line, col) = self.srcloc(self.cur_span);
+        let msg = format!(
+            "{}:{}:{}: {}",
+            self.src.as_ref().unwrap().get_path().to_string_lossy(),
+            line,
+            col,
+            msg
+        );
+        let msg_ptr = self
+            .builder
+            .build_global_string_ptr(&msg, "")
+            .as_pointer_value();
+
+        self.builder
+            .build_call(self.panic_fn(), &[msg_ptr.into()], "");
+        self.builder.build_unreachable();
+
+        self.builder.position_at_end(bb_ok);
+    }
+
+    fn panic_fn(&self) -> FunctionValue<'ctx> {
+        self.vmmod.module.get_function(PANIC_FN).unwrap_or_else(|| {
+            let i8ptr_t = get_ctx().i8_type().ptr_type(AddressSpace::Generic);
+
+            self.vmmod.module.add_function(
+                PANIC_FN,
+                get_ctx().void_type().fn_type(&[i8ptr_t.into()], false),
+                Some(Linkage::External),
+            )
+        })
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use crate::{
+        codegen::sh_llvm_config, config::Harness, driver::RunCompiler,
+    };
+
+    #[test]
+    fn test_rt_checks() -> Result<(), Box<dyn std::error::Error>> {
+        let mut config = sh_llvm_config(true);
+        config.harness = Some(Harness::Test);
+
+        let output = RunCompiler::with_exe(
+            &"./examples/rt_checks.bath",
+            config,
+            |exe| Command::new(exe).output(),
+        )?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        assert_eq!(output.status.code(), Some(1));
+
+        for name in [
+            "test_overflow",
+            "test_zero_divisor",
+            "test_bounds",
+            "test_div_overflow",
+        ] {
+            assert!(stdout.contains(&format!("test {name} ... FAILED")));
+        }
+        assert!(stdout.contains("test test_in_range ... ok"));
+
+        for msg in [
+            "rt_checks.bath:3:9: attempt to add with overflow",
+            "rt_checks.bath:7:9: attempt to divide by zero",
+            "rt_checks.bath:23:5: index out of bounds",
+            "rt_checks.bath:7:9: attempt to divide with overflow",
+        ] {
+            assert!(stderr.contains(msg), "{msg} in {stderr}");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+use std::path::Path;
+
+use inkwellkit::{
+    debug_info::{
+        AsDIScope, DICompileUnit, DIFlags, DIFlagsConstants, DISubprogram,
+        DIType, DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder,
+    },
+    get_ctx,
+    module::FlagBehavior,
+    values::FunctionValue,
+    AddressSpace, VMMod,
+};
+use m6lexerkit::{sym2str, Span, SrcFileInfo, Symbol};
+
+use super::CodeGen;
+use crate::{
+    ast_lowering::{APriType, AType},
+    config::OptLv,
+};
+
+
+/// DWARF base type encoding
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_FLOAT: u32 = 0x04;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_SIGNED_CHAR: u32 = 0x06;
+
+const PTR_BITS: u64 = 64;
+
+
+pub(crate) struct DebugInfo<'ctx> {
+    dibuilder: DebugInfoBuilder<'ctx>,
+    cu: DICompileUnit<'ctx>,
+    /// Subprogram of the function being generated
+    sp: Option<DISubprogram<'ctx>>,
+}
+
+
+
+impl<'ctx> DebugInfo<'ctx> {
+    pub(crate) fn new(
+        vmmod: &VMMod<'ctx>,
+        src: &SrcFileInfo,
+        is_optimized: bool,
+    ) -> Self {
+        let module = &vmmod.module;
+        let ctx = get_ctx();
+
+        module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            ctx.i32_type().const_int(3, false),
+        );
+        module.add_basic_value_flag(
+            "Dwarf Version",
+            FlagBehavior::Warning,
+            ctx.i32_type().const_int(4, false),
+        );
+
+        let path = src
+            .get_path()
+            .canonicalize()
+            .unwrap_or(src.get_path().to_path_buf());
+        let filename = path.file_name().unwrap().to_string_lossy();
+        let directory = path
+            .parent()
+            .unwrap_or(Path::new("."))
+            .to_string_lossy();
+
+        let (dibuilder, cu) = module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            &filename,
+            &directory,
+            concat!("basc ", env!("CARGO_PKG_VERSION")),
+            is_optimized,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+
+        Self {
+            dibuilder,
+            cu,
+            sp: None,
+        }
+    }
+
+    fn di_type(&self, aty: &AType) -> Option<DIType<'ctx>> {
+        let basic = |name: &str, bits: u64, encoding: u32| {
+            self.dibuilder
+                .create_basic_type(name, bits, encoding, DIFlags::PUBLIC)
+                .unwrap()
+                .as_type()
+        };
+        let ptr = |name: &str| {
+            self.dibuilder
+                .create_pointer_type(
+                    name,
+                    basic("char", 8, DW_ATE_SIGNED_CHAR),
+                    PTR_BITS,
+                    0,
+                    AddressSpace::Generic,
+                )
+                .as_type()
+        };
+
+        Some(match aty {
+            AType::Pri(APriType::Float(len)) => {
+                basic("f64", *len as u64 * 8, DW_ATE_FLOAT)
+            }
+            AType::Pri(APriType::Int(1)) => basic("bool", 8, DW_ATE_BOOLEAN),
+            AType::Pri(APriType::Int(slen)) => {
+                let bits = slen.unsigned_abs() as u64 * 8;
+
+                basic(&format!("i{bits}"), bits, DW_ATE_SIGNED)
+            }
+            AType::Pri(APriType::Ptr) => ptr("ptr"),
+            AType::Pri(APriType::OpaqueStruct(name)) => ptr(&sym2str(*name)),
+            AType::Arr(..) => ptr("vec"),
+            _ => return None,
+        })
+    }
+}
+
+
+impl<'ctx> CodeGen<'ctx> {
+    ///////////////////////////////////////////////////////////////////////////
+    //// Debug Info
+
+    /// Attach subprogram to `fn_val` and set location to its definition
+    pub(crate) fn dbg_enter_fn(
+        &mut self,
+        name: Symbol,
+        fn_val: FunctionValue<'ctx>,
+    ) {
+        let Some(ref dbg) = self.dbg else { return };
+
+        let afndec = self.amod.afns.get(&name).unwrap();
+        let (line, _col) = self.srcloc(afndec.idt.span);
+
+        let params = afndec
+            .params
+            .iter()
+            .filter_map(|param| dbg.di_type(&param.ty))
+            .collect::<Vec<_>>();
+
+        let subroutine_ty = dbg.dibuilder.create_subroutine_type(
+            dbg.cu.get_file(),
+            dbg.di_type(&afndec.ret),
+            &params,
+            DIFlags::PUBLIC,
+        );
+
+        let sp = dbg.dibuilder.create_function(
+            dbg.cu.as_debug_info_scope(),
+            &sym2str(afndec.name),
+            Some(&sym2str(name)),
+            dbg.cu.get_file(),
+            line,
+            subroutine_ty,
+            false,
+            true,
+            line,
+            DIFlags::PUBLIC,
+            self.config.optlv != OptLv::Debug,
+        );
+
+        fn_val.set_subprogram(sp);
+
+        self.dbg.as_mut().unwrap().sp = Some(sp);
+        self.dbg_set_loc(afndec.idt.span);
+    }
+
+    /// Describe local variable allocas of fn `name`
+    pub(crate) fn dbg_declare_vars(&self, name: Symbol) {
+        let Some(ref dbg) = self.dbg else { return };
+        let Some(sp) = dbg.sp else { return };
+
+        let Some(alloc_spans) = self.amod.alloc_spans.get(&name) else {
+            return;
+        };
+        let fn_alloc = self.amod.allocs.get(&name).unwrap();
+        let bb = self.builder.get_insert_block().unwrap();
+
+        for ((sym, tagid), ty) in fn_alloc.iter() {
+            let Some(di_ty) = dbg.di_type(ty) else { continue };
+            let Some(span) = alloc_spans.get(&(*sym, *tagid)) else {
+                continue;
+            };
+            let (line, col) = self.srcloc(*span);
+
+            let var = dbg.dibuilder.create_auto_variable(
+                sp.as_debug_info_scope(),
+                &sym2str(*sym),
+                dbg.cu.get_file(),
+                line,
+                di_ty,
+                true,
+                DIFlags::ZERO,
+                0,
+            );
+
+            let loc = dbg.dibuilder.create_debug_location(
+                get_ctx(),
+                line,
+                col,
+                sp.as_debug_info_scope(),
+                None,
+            );
+
+            dbg.dibuilder.insert_declare_at_end(
+                self.fn_alloc[&(*sym, *tagid)],
+                Some(var),
+                None,
+                loc,
+                bb,
+            );
+        }
+    }
+
+    /// (line, column)
+    pub(crate) fn srcloc(&self, span: Span) -> (u32, u32) {
+        let loc = self.src.as_ref().unwrap().boffset2srcloc(span.from);
+
+        (loc.ln as u32, loc.col as u32)
+    }
+
+    pub(crate) fn dbg_set_loc(&self, span: Span) {
+        let Some(ref dbg) = self.dbg else { return };
+        let Some(sp) = dbg.sp else { return };
+
+        // implicit MIR without source, keep previous location
+        if span.from == 0 && span.end == 0 {
+            return;
+        }
+
+        let (line, col) = self.srcloc(span);
+        let loc = dbg.dibuilder.create_debug_location(
+            get_ctx(),
+            line,
+            col,
+            sp.as_debug_info_scope(),
+            None,
+        );
+
+        self.builder.set_current_debug_location(get_ctx(), loc);
+    }
+
+    pub(crate) fn dbg_leave_fn(&mut self) {
+        if let Some(ref mut dbg) = self.dbg {
+            dbg.sp = None;
+            self.builder.unset_current_debug_location();
+        }
+    }
+
+    pub(crate) fn dbg_finalize(&self) {
+        if let Some(ref dbg) = self.dbg {
+            dbg.dibuilder.finalize();
+        }
+    }
+}
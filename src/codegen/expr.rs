@@ -4,7 +4,7 @@ use inkwellkit::{FloatPredicate, IntPredicate, VMMod};
 use itertools::Itertools;
 use m6lexerkit::{sym2str, Symbol};
 
-use super::CodeGen;
+use super::{CodeGen, LogicBlock};
 use crate::ast_lowering::{APriType, AType, AVal, AVar, ConstVal, MIRTy, MIR};
 use crate::parser::SyntaxType as ST;
 
@@ -18,8 +18,12 @@ impl<'ctx> CodeGen<'ctx> {
             mirty,
             ty,
             val,
+            span,
         } = mir;
 
+        self.cur_span = span;
+        self.dbg_set_loc(span);
+
         if let Some(bv) = self.translate_avar(AVar { ty, val }) {
             match mirty {
                 MIRTy::ValBind => {
@@ -52,22 +56,18 @@ impl<'ctx> CodeGen<'ctx> {
                 self.translate_fn_call(call_fn, args)
             }
             AVal::BOpExpr { op, operands } => {
-                self.translate_bop_expr(op, operands)
+                self.translate_bop_expr(op, operands, var.ty)
             }
             AVal::ConstAlias(const_val) => self.translate_const_val(const_val),
             AVal::Break => self.translate_break(),
             AVal::Continue => self.translate_continue(),
             AVal::Return(sym_opt) => self.translate_return(sym_opt),
             AVal::InfiLoopExpr(blk_idx) => {
-                let res = self.translate_infi_loop(blk_idx);
-
-                if res.is_none() {
-                    return None;
-                }
-
-                res.unwrap()
+                self.translate_infi_loop(var.ty, blk_idx)
+            }
+            AVal::TypeCast { name, from, ty } => {
+                self.translate_type_cast(name, from, ty)
             }
-            AVal::TypeCast { name, ty } => self.translate_type_cast(name, ty),
             AVal::Var(sym, tagid) => self.translate_var(sym, tagid),
             AVal::Assign(sym, tagid, valsym) => {
                 let bv = self.find_sym(valsym).unwrap();
@@ -92,15 +92,27 @@ impl<'ctx> CodeGen<'ctx> {
         }
     }
 
+    /// `ty` is the type of result, which is also the one of operands for
+    /// arithmetic operators
     fn translate_bop_expr(
-        &self,
+        &mut self,
         op: ST,
         operands: (Symbol, Symbol),
+        ty: AType,
     ) -> BasicValueEnum<'ctx> {
         let ope1st = self.find_sym(operands.0).unwrap();
         let ope2nd = self.find_sym(operands.1).unwrap();
+        let signed = matches!(ty, AType::Pri(APriType::Int(sw)) if sw < 0);
 
         match op {
+            ST::add | ST::sub | ST::mul
+                if self.rt_checks() && ope1st.is_int_value() =>
+            {
+                let operand1 = ope1st.into_int_value();
+                let operand2 = ope2nd.into_int_value();
+                self.build_checked_int_arith(op, signed, operand1, operand2)
+                    .into()
+            }
             ST::add => {
                 if ope1st.is_int_value() {
                     let operand1 = ope1st.into_int_value();
@@ -141,9 +153,25 @@ impl<'ctx> CodeGen<'ctx> {
                 }
             }
             ST::div => {
+                if self.rt_checks() {
+                    self.build_zero_divisor_check(ope2nd);
+                }
+
                 if ope1st.is_int_value() {
                     let operand1 = ope1st.into_int_value();
                     let operand2 = ope2nd.into_int_value();
+
+                    if !signed {
+                        return self
+                            .builder
+                            .build_int_unsigned_div(operand1, operand2, "")
+                            .into();
+                    }
+
+                    if self.rt_checks() {
+                        self.build_div_overflow_check(operand1, operand2);
+                    }
+
                     self.builder
                         .build_int_signed_div(operand1, operand2, "")
                         .into()
@@ -197,20 +225,48 @@ impl<'ctx> CodeGen<'ctx> {
     fn translate_type_cast(
         &self,
         name: Symbol,
+        from: AType,
         ty: AType,
     ) -> BasicValueEnum<'ctx> {
         let bv = self.find_sym(name).unwrap();
 
         if bv.is_int_value() {
+            // bool and unsigned source are zero extended
+            let signed =
+                matches!(from, AType::Pri(APriType::Int(sw)) if sw < 0);
+
             match ty {
                 AType::Pri(pri) => match pri {
                     APriType::Float(_) => self.builder.build_cast(
-                        InstructionOpcode::SIToFP,
+                        if signed {
+                            InstructionOpcode::SIToFP
+                        } else {
+                            InstructionOpcode::UIToFP
+                        },
                         bv,
                         pri.as_float_ty(),
                         "",
                     ),
-                    APriType::Int(_swidth) => bv,
+                    APriType::Int(_) => {
+                        let iv = bv.into_int_value();
+                        let width = iv.get_type().get_bit_width();
+                        let int_t = pri.as_int_ty();
+
+                        if width < int_t.get_bit_width() {
+                            if signed {
+                                self.builder.build_int_s_extend(iv, int_t, "")
+                            } else {
+                                self.builder.build_int_z_extend(iv, int_t, "")
+                            }
+                            .into()
+                        } else if width > int_t.get_bit_width() {
+                            self.builder
+                                .build_int_truncate(iv, int_t, "")
+                                .into()
+                        } else {
+                            bv
+                        }
+                    }
                     APriType::Ptr => todo!(),
                     APriType::OpaqueStruct(_) => unreachable!(),
                 },
@@ -275,6 +331,11 @@ impl<'ctx> CodeGen<'ctx> {
             // Do nothing
         }
 
+        // scopes up to the fn are left
+        for blk_idx in self.sc.iter().rev() {
+            self.drop_owned(*blk_idx);
+        }
+
         let blk_last =
             self.get_fnval().unwrap().get_last_basic_block().unwrap();
         self.builder.build_unconditional_branch(blk_last);
@@ -283,31 +344,43 @@ impl<'ctx> CodeGen<'ctx> {
     }
 
     fn translate_continue(&self) -> BasicValueEnum<'ctx> {
-        let bb_cur = self.cur_blk().continue_to.unwrap();
-        self.builder.build_unconditional_branch(bb_cur);
+        let lblk = self.leave_loop_scopes();
+        self.builder
+            .build_unconditional_branch(lblk.continue_to.unwrap());
 
         VMMod::null()
     }
 
-    fn translate_break(&mut self) -> BasicValueEnum<'ctx> {
-        if self.cur_blk().break_to.is_none() {
-            self.cur_blk_mut().break_to = Some(self.insert_nonterminal_bb());
-        }
-
-        let bb_nxt = self.cur_blk().break_to.unwrap();
-        self.link_bb(bb_nxt);
+    fn translate_break(&self) -> BasicValueEnum<'ctx> {
+        let lblk = self.leave_loop_scopes();
+        self.builder.build_unconditional_branch(lblk.break_to.unwrap());
 
         VMMod::null()
     }
 
+    /// Drop scopes up to the innermost loop body and return the body block
+    fn leave_loop_scopes(&self) -> &LogicBlock<'ctx> {
+        let pos = self
+            .sc
+            .iter()
+            .rposition(|idx| self.blks[*idx].continue_to.is_some())
+            .unwrap();
+
+        for blk_idx in self.sc[pos..].iter().rev() {
+            self.drop_owned(*blk_idx);
+        }
+
+        &self.blks[self.sc[pos]]
+    }
+
     fn translate_fn_call(
-        &self,
+        &mut self,
         call_fn: Symbol,
         args: Vec<Symbol>,
     ) -> BasicValueEnum<'ctx> {
         let bv_args = args
-            .into_iter()
-            .map(|sym| {
+            .iter()
+            .map(|&sym| {
                 if let Some(bv) = self.find_sym(sym) {
                     bv.into()
                 } else {
@@ -324,6 +397,18 @@ impl<'ctx> CodeGen<'ctx> {
             unreachable!("Unknown fn call: {:?}", call_fn);
         };
 
+        let call_fn_name = sym2str(call_fn);
+
+        if self.rt_checks()
+            && (call_fn_name.starts_with("vec_get_")
+                || call_fn_name.starts_with("vec_set_"))
+        {
+            self.build_bounds_check(
+                self.find_sym(args[0]).unwrap().into_pointer_value(),
+                self.find_sym(args[1]).unwrap().into_int_value(),
+            );
+        }
+
         match self
             .builder
             .build_call(fnval_call, &bv_args[..], "")
@@ -349,16 +434,44 @@ impl<'ctx> CodeGen<'ctx> {
         let ret = self.amod.scopes[blk_idx].as_var();
 
         for mir in mirs.into_iter() {
+            // the rest is unreachable
+            if self.is_terminated() {
+                break;
+            }
+
             self.translate_mir(mir);
         }
 
-        let ret = self.translate_avar(ret);
+        let ret = if self.is_terminated() {
+            Some(VMMod::null())
+        } else {
+            self.translate_avar(ret)
+        };
+
+        // it has been dropped if block is terminated by `ret`, `break` ...
+        if !self.is_terminated() {
+            self.drop_owned(blk_idx);
+        }
 
         self.sc.pop();
 
         ret
     }
 
+    /// Drop owned values of scope bound so far, in reverse order
+    fn drop_owned(&self, blk_idx: usize) {
+        for (sym, drop_fn) in self.amod.scopes[blk_idx].owned.iter().rev() {
+            let Some(bv) = self.blks[blk_idx].in_scope_find_val_sym(*sym)
+            else {
+                continue;
+            };
+
+            let fnval =
+                self.vmmod.module.get_function(&sym2str(*drop_fn)).unwrap();
+            self.builder.build_call(fnval, &[bv.into()], "");
+        }
+    }
+
     fn translate_if(
         &mut self,
         ty: AType,
@@ -394,11 +507,13 @@ impl<'ctx> CodeGen<'ctx> {
 
             let bv_if = self.translate_block(blk_idx);
 
-            if !self.blks[blk_idx].has_ret {
+            if !self.is_terminated() {
                 self.builder.build_unconditional_branch(bb_nxt);
 
                 if !matches!(ty, AType::Void | AType::Never) {
-                    phi_local.push((bv_if.unwrap(), bb_if));
+                    // block may be split (e.g. by runtime checks)
+                    let bb_end = self.builder.get_insert_block().unwrap();
+                    phi_local.push((bv_if.unwrap(), bb_end));
                 }
             }
 
@@ -408,11 +523,13 @@ impl<'ctx> CodeGen<'ctx> {
                 if let Some(else_idx) = else_blk {
                     let bv_else = self.translate_block(else_idx);
 
-                    if !self.blks[else_idx].has_ret {
+                    if !self.is_terminated() {
                         self.builder.build_unconditional_branch(bb_nxt);
 
                         if !matches!(ty, AType::Void | AType::Never) {
-                            phi_local.push((bv_else.unwrap(), bb_else));
+                            let bb_end =
+                                self.builder.get_insert_block().unwrap();
+                            phi_local.push((bv_else.unwrap(), bb_end));
                         }
                     }
                 } else {
@@ -438,20 +555,32 @@ impl<'ctx> CodeGen<'ctx> {
 
     fn translate_infi_loop(
         &mut self,
+        ty: AType,
         blk_idx: usize,
-    ) -> Option<BasicValueEnum<'ctx>> {
+    ) -> BasicValueEnum<'ctx> {
         /* Setup loop config */
         let bb_loop = self.insert_nonterminal_bb();
+        let bb_exit = self.insert_nonterminal_bb();
         self.blks[blk_idx].continue_to = Some(bb_loop);
+        self.blks[blk_idx].break_to = Some(bb_exit);
 
         self.link_bb(bb_loop);
 
         // println!("infi loop {:#?}", self.amod.scopes[blk_idx]);
 
-        let bv = self.translate_block(blk_idx);
+        self.translate_block(blk_idx);
+
+        if !self.is_terminated() {
+            self.builder.build_unconditional_branch(bb_loop);
+        }
 
-        self.builder.build_unconditional_branch(bb_loop);
+        // left only through `break`
+        self.builder.position_at_end(bb_exit);
 
-        bv
+        if ty == AType::Never {
+            self.builder.build_unreachable();
+        }
+
+        VMMod::null()
     }
 }
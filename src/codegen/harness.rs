@@ -0,0 +1,255 @@
+use inkwellkit::{get_ctx, module::Linkage, AddressSpace};
+use itertools::Itertools;
+use m6lexerkit::{sym2str, Symbol};
+
+use super::CodeGen;
+use crate::{ast_lowering::A3ttrName, config::Harness};
+
+
+impl Harness {
+    fn attr(&self) -> A3ttrName {
+        match self {
+            Self::Test => A3ttrName::Test,
+            Self::Bench => A3ttrName::Bench,
+        }
+    }
+
+    /// Runner of runtime, see `clib/src/test.c` and `clib/src/bench.c`
+    fn runner(&self) -> &'static str {
+        match self {
+            Self::Test => "bas_test_main",
+            Self::Bench => "bas_bench_main",
+        }
+    }
+}
+
+
+
+impl<'ctx> CodeGen<'ctx> {
+    ///////////////////////////////////////////////////////////////////////////
+    //// Test Harness
+
+    /// `main` of source is replaced by the harness
+    pub(crate) fn is_replaced_main(&self, name: Symbol) -> bool {
+        self.config.harness.is_some() && sym2str(name) == "main"
+    }
+
+    /// `main(argc, argv)` passing names and pointers of `@test` (`@bench`)
+    /// functions to the runtime runner
+    pub(crate) fn gen_harness(&mut self, harness: Harness) {
+        let ctx = get_ctx();
+        let i32_t = ctx.i32_type();
+        let i8ptr_t = ctx.i8_type().ptr_type(AddressSpace::Generic);
+        let i8ptrptr_t = i8ptr_t.ptr_type(AddressSpace::Generic);
+
+        let tests = self
+            .amod
+            .afns
+            .values()
+            .filter(|afn| afn.attrs.has(harness.attr()))
+            .map(|afn| (sym2str(afn.idt.value), afn.name))
+            .collect_vec();
+
+        let main = self.vmmod.module.add_function(
+            "main",
+            i32_t.fn_type(&[i32_t.into(), i8ptrptr_t.into()], false),
+            None,
+        );
+        let bb = ctx.append_basic_block(main, "");
+        self.builder.position_at_end(bb);
+
+        let arr_t = i8ptr_t.array_type(tests.len() as u32);
+        let names = self.builder.build_alloca(arr_t, "names");
+        let fns = self.builder.build_alloca(arr_t, "fns");
+
+        for (i, (name, full_name)) in tests.iter().enumerate() {
+            let idx = [i32_t.const_zero(), i32_t.const_int(i as u64, false)];
+
+            let name_ptr = self
+                .builder
+                .build_global_string_ptr(name, "")
+                .as_pointer_value();
+            let fn_ptr = self
+                .vmmod
+                .module
+                .get_function(&sym2str(*full_name))
+                .unwrap()
+                .as_global_value()
+                .as_pointer_value();
+            let fn_ptr = self.builder.build_pointer_cast(fn_ptr, i8ptr_t, "");
+
+            unsafe {
+                let slot = self.builder.build_in_bounds_gep(names, &idx, "");
+                self.builder.build_store(slot, name_ptr);
+
+                let slot = self.builder.build_in_bounds_gep(fns, &idx, "");
+                self.builder.build_store(slot, fn_ptr);
+            }
+        }
+
+        let runner = harness.runner();
+        let runner_fn =
+            self.vmmod.module.get_function(runner).unwrap_or_else(|| {
+                self.vmmod.module.add_function(
+                    runner,
+                    i32_t.fn_type(
+                        &[
+                            i32_t.into(),
+                            i8ptrptr_t.into(),
+                            i32_t.into(),
+                            i8ptrptr_t.into(),
+                            i8ptrptr_t.into(),
+                        ],
+                        false,
+                    ),
+                    Some(Linkage::External),
+                )
+            });
+
+        let args = [
+            main.get_nth_param(0).unwrap().into(),
+            main.get_nth_param(1).unwrap().into(),
+            i32_t.const_int(tests.len() as u64, false).into(),
+            self.builder
+                .build_pointer_cast(names, i8ptrptr_t, "")
+                .into(),
+            self.builder.build_pointer_cast(fns, i8ptrptr_t, "").into(),
+        ];
+
+        let code = self
+            .builder
+            .build_call(runner_fn, &args, "")
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+
+        self.builder.build_return(Some(&code));
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, process::Command};
+
+    use crate::{
+        codegen::sh_llvm_config,
+        config::{Harness, OptLv},
+        driver::RunCompiler,
+    };
+
+    #[test]
+    fn test_harness() -> Result<(), Box<dyn std::error::Error>> {
+        for (path, harness) in [
+            ("./examples/unit_test.bath", Harness::Test),
+            ("./examples/bench.bath", Harness::Bench),
+        ] {
+            let mut config = sh_llvm_config(true);
+            config.harness = Some(harness);
+
+            RunCompiler::new(&PathBuf::from(path), config)?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_harness_run() -> Result<(), Box<dyn std::error::Error>> {
+        let mut config = sh_llvm_config(true);
+        config.harness = Some(Harness::Test);
+
+        let filters: [&[&str]; 3] =
+            [&[], &["pass"], &["--exact", "test_fail"]];
+
+        let outputs = RunCompiler::with_exe(
+            &"./examples/unit_test_fail.bath",
+            config,
+            |exe| {
+                filters
+                    .iter()
+                    .map(|args| Command::new(exe).args(*args).output())
+                    .collect::<Result<Vec<_>, _>>()
+            },
+        )?;
+        let stdouts = outputs
+            .iter()
+            .map(|output| String::from_utf8_lossy(&output.stdout))
+            .collect::<Vec<_>>();
+
+        // the failed one doesn't stop the next one
+        assert_eq!(outputs[0].status.code(), Some(1));
+        assert!(stdouts[0].contains("test test_fail ... FAILED"));
+        assert!(stdouts[0].contains("killed by signal"));
+        assert!(stdouts[0].contains("test test_pass ... ok"));
+        assert!(stdouts[0].contains("failures:\n    test_fail\n"));
+        assert!(stdouts[0].contains("1 passed; 1 failed; 0 filtered out"));
+        assert!(String::from_utf8_lossy(&outputs[0].stderr)
+            .contains("assertion failed"));
+
+        assert_eq!(outputs[1].status.code(), Some(0));
+        assert!(stdouts[1].contains("running 1 test\n"));
+        assert!(stdouts[1].contains("1 passed; 0 failed; 1 filtered out"));
+
+        assert_eq!(outputs[2].status.code(), Some(1));
+        assert!(stdouts[2].contains("0 passed; 1 failed; 1 filtered out"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bench_baseline() -> Result<(), Box<dyn std::error::Error>> {
+        let mut config = sh_llvm_config(false);
+        config.optlv = OptLv::Opt3;
+        config.harness = Some(Harness::Bench);
+
+        let (outputs, baseline) = RunCompiler::with_exe(
+            &"./examples/bench.bath",
+            config,
+            |exe| {
+                let path = exe.with_file_name("baseline");
+                let saved = Command::new(exe)
+                    .arg("--save-baseline")
+                    .arg(&path)
+                    .arg("bench_loop")
+                    .output()?;
+                let compared = Command::new(exe)
+                    .arg("--baseline")
+                    .arg(&path)
+                    .output()?;
+
+                Ok((vec![saved, compared], std::fs::read_to_string(&path)?))
+            },
+        )?;
+        let stdouts = outputs
+            .iter()
+            .map(|output| String::from_utf8_lossy(&output.stdout))
+            .collect::<Vec<_>>();
+
+        assert!(outputs.iter().all(|output| output.status.success()));
+
+        // calibrated beyond one iteration, the loop isn't folded away
+        let line = stdouts[0]
+            .lines()
+            .find(|line| line.starts_with("bench bench_loop ... "))
+            .unwrap();
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let mean = fields[3].parse::<f64>()?;
+        let iters = fields[fields.len() - 2].parse::<u32>()?;
+
+        assert!(mean > 0.0);
+        assert!(iters > 1);
+        assert!(stdouts[0].contains("1 measured; 1 filtered out"));
+
+        let entry = baseline.split_whitespace().collect::<Vec<_>>();
+
+        assert_eq!(entry.len(), 4);
+        assert_eq!(entry[0], "bench_loop");
+
+        assert!(stdouts[1].contains("bench bench_fib ... "));
+        assert!(stdouts[1].contains("[no baseline]"));
+        assert!(stdouts[1].contains("% from "));
+
+        Ok(())
+    }
+}
@@ -1,17 +1,20 @@
-use inkwellkit::{config::OptLv, get_ctx, module::Linkage};
+use inkwellkit::{get_ctx, module::Linkage};
 use itertools::Itertools;
 use m6lexerkit::{sym2str, Symbol};
 
-use super::CodeGen;
+use super::{CodeGen, CodeGenResult2};
 use crate::ast_lowering::{AParamPat, AVal, MIR, AType, AnExtFnDec, A3ttrName};
 
 
 
 impl<'ctx> CodeGen<'ctx> {
 
-    pub(crate) fn gen_mod(&mut self) {
+    pub(crate) fn gen_mod(&mut self) -> CodeGenResult2 {
         // Generate fn declaration
         for afndec in self.amod.afns.values() {
+            if self.is_replaced_main(afndec.name) {
+                continue;
+            }
             self.gen_fn_dec(&afndec.as_ext_fn_dec(), None);
         }
         for afndec in self.amod.efns.values() {
@@ -21,7 +24,40 @@ impl<'ctx> CodeGen<'ctx> {
             self.gen_fn_dec(afndec, Some(Linkage::External));
         }
 
-        self.gen_items()
+        self.gen_globals()?;
+        self.gen_items();
+
+        if let Some(harness) = self.config.harness {
+            self.gen_harness(harness);
+        }
+
+        self.dbg_finalize();
+
+        Ok(())
+    }
+
+    /// Module level variables
+    pub(crate) fn gen_globals(&self) -> CodeGenResult2 {
+        for ((sym, tagid), ty) in self.amod.globals.iter() {
+            let basic_ty = self.gen_aty_as_basic_type(ty)?;
+            let gv = self.vmmod.module.add_global(
+                basic_ty,
+                None,
+                &global_name(*sym, *tagid),
+            );
+            gv.set_initializer(&basic_ty.const_zero());
+        }
+
+        for ((sym, tagid), ty) in self.amod.eglobals.iter() {
+            let gv = self.vmmod.module.add_global(
+                self.gen_aty_as_basic_type(ty)?,
+                None,
+                &global_name(*sym, *tagid),
+            );
+            gv.set_linkage(Linkage::External);
+        }
+
+        Ok(())
     }
 
     pub(crate) fn gen_items(&mut self) {
@@ -31,10 +67,13 @@ impl<'ctx> CodeGen<'ctx> {
             tagid: _,
             ty: _,
             val,
+            span: _,
         } in self.root_scope().mirs.clone().into_iter()
         {
             match val {
-                AVal::DefFn { name, scope_idx } => {
+                AVal::DefFn { name, scope_idx }
+                    if !self.is_replaced_main(name) =>
+                {
                     self.gen_fn_body(name, scope_idx)
                 }
                 _ => (),
@@ -47,7 +86,7 @@ impl<'ctx> CodeGen<'ctx> {
         let vm_args = afndec
             .params
             .iter()
-            .map(|AParamPat { formal: _, ty }| {
+            .map(|AParamPat { ty, .. }| {
                 self.gen_aty_as_basic_meta_type(ty)
             })
             .collect_vec();
@@ -75,6 +114,7 @@ impl<'ctx> CodeGen<'ctx> {
         let blk_fn_0 = ctx.append_basic_block(fn_val, "");
         self.push_bb(scope_idx, blk_fn_0);
         self.builder.position_at_end(blk_fn_0);
+        self.dbg_enter_fn(name, fn_val);
 
         // push into fn_alloc
         let fn_alloc = self.amod.allocs.get(&name).unwrap();
@@ -88,6 +128,22 @@ impl<'ctx> CodeGen<'ctx> {
             self.fn_alloc.insert((*sym, *tagid), var);
         }
 
+        self.dbg_declare_vars(name);
+
+        for (sym, tagid) in self
+            .amod
+            .globals
+            .keys()
+            .chain(self.amod.eglobals.keys())
+        {
+            let gv = self
+                .vmmod
+                .module
+                .get_global(&global_name(*sym, *tagid))
+                .unwrap();
+            self.fn_alloc.insert((*sym, *tagid), gv.as_pointer_value());
+        }
+
         // set terminator
         let bb_terminal = self.insert_terminal_bb(fn_val);
 
@@ -127,10 +183,14 @@ impl<'ctx> CodeGen<'ctx> {
 
         self.builder.build_return(ret);
 
-        if fn_val.verify(true) {
-            if self.config.optlv != OptLv::Debug {
-                self.fpm.run_on(&fn_val);
-            }
-        }
+        self.dbg_leave_fn();
+
+        // Optimization is delayed to `run_passes`
+        fn_val.verify(true);
     }
 }
+
+
+pub(crate) fn global_name(sym: Symbol, tagid: usize) -> String {
+    format!("{}#{}", sym2str(sym), tagid)
+}
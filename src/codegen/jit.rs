@@ -0,0 +1,122 @@
+use inkwellkit::{
+    execution_engine::ExecutionEngine,
+    support::load_library_permanently,
+    targets::{InitializationConfig, Target},
+    VMMod,
+};
+
+use super::{check_entry, CodeGen, CodeGenError};
+use crate::{
+    ast_lowering::{AMod, ExtSymSet},
+    config::{CompilerConfig, IRStage, OptLv, PrintTy, TargetType},
+    env::libbas_so_path,
+};
+
+
+
+impl<'ctx> CodeGen<'ctx> {
+    ///////////////////////////////////////////////////////////////////////////
+    //// JIT Execution
+
+    /// Generate `amod` and its dependency modules (e.g. core) in memory,
+    /// then run `main` in process and return its exit code.
+    pub(crate) fn run_jit(
+        src: SrcFileInfo,
+        amod: AMod,
+        ess: ExtSymSet,
+        deps: Vec<(AMod, ExtSymSet)>,
+        optlv: OptLv,
+    ) -> Result<i32, CodeGenError> {
+        check_entry(&amod)?;
+
+        let mut dep_vmmods = vec![];
+
+        for (dep_amod, dep_ess) in deps.into_iter() {
+            let (vmmod, _) = Self::gen_vmmod(
+                None,
+                dep_amod,
+                dep_ess,
+                jit_config(optlv),
+            )?;
+            dep_vmmods.push(vmmod);
+        }
+
+        let (vmmod, _) =
+            Self::gen_vmmod(Some(src), amod, ess, jit_config(optlv))?;
+
+        let ee = create_jit_engine(&vmmod, optlv)?;
+
+        for dep in dep_vmmods.iter() {
+            ee.add_module(&dep.module).map_err(|_| {
+                CodeGenError(format!(
+                    "Add module {:?} into JIT failed",
+                    dep.module.get_name()
+                ))
+            })?;
+        }
+
+        let main = vmmod.module.get_function("main").unwrap();
+
+        let code = unsafe { ee.run_function_as_main(main, &[]) };
+
+        Ok(code)
+    }
+}
+
+
+/// Create execution engine for `vmmod`, with runtime library (libbas)
+/// loaded into the process.
+pub(crate) fn create_jit_engine<'ctx>(
+    vmmod: &VMMod<'ctx>,
+    optlv: OptLv,
+) -> Result<ExecutionEngine<'ctx>, CodeGenError> {
+    Target::initialize_native(&InitializationConfig::default())
+        .map_err(|s| CodeGenError(s))?;
+
+    load_runtime()?;
+
+    vmmod
+        .module
+        .create_jit_execution_engine(optlv.into())
+        .map_err(|llvmstr| CodeGenError::from(llvmstr))
+}
+
+
+/// Module is only generated in memory, so target and emit type are dummy.
+pub(crate) fn jit_config(optlv: OptLv) -> CompilerConfig {
+    CompilerConfig {
+        optlv,
+        target_type: TargetType::ReLoc,
+        emit_types: vec![],
+        ir_stage: IRStage::Post,
+        passes: Default::default(),
+        print_type: PrintTy::StdErr,
+        target: Default::default(),
+        linker: Default::default(),
+        debug: false,
+        harness: None,
+        diag: Default::default(),
+    }
+}
+
+
+/// Load libbas (shared object version) symbols into current process
+fn load_runtime() -> Result<(), CodeGenError> {
+    let libbas_so = libbas_so_path()?;
+
+    if !libbas_so.exists() {
+        return Err(CodeGenError(format!(
+            "JIT requires runtime {:?}, try `make getlib` or unset \
+             BAS_HOME",
+            libbas_so
+        )));
+    }
+
+    // return true if it failed
+    if load_library_permanently(&libbas_so.to_string_lossy()) {
+        return Err(CodeGenError(format!("Load {:?} failed", libbas_so)));
+    }
+
+    Ok(())
+}
+
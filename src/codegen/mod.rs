@@ -4,22 +4,32 @@ use indexmap::{indexmap, IndexMap};
 use inkwellkit::{
     basic_block::BasicBlock,
     builder::Builder,
-    config::*,
     get_ctx,
-    passes::PassManager,
     support::LLVMString,
     values::{BasicValueEnum, FunctionValue, PointerValue},
     VMMod,
 };
-use m6lexerkit::{str2sym, sym2str, Symbol};
+use m6lexerkit::{str2sym, sym2str, Span, SrcFileInfo, Symbol};
 
-use crate::ast_lowering::{AMod, AScope, ExtSymSet};
+use crate::{
+    ast_lowering::{AMod, AScope, ExtSymSet},
+    config::*,
+    env::EnvError,
+};
 
+mod check;
+mod debug;
 pub(crate) mod expr;
+mod harness;
 pub(crate) mod item;
+mod jit;
+mod passes;
 mod targets;
 pub(crate) mod ty;
 
+use debug::DebugInfo;
+pub(crate) use jit::{create_jit_engine, jit_config};
+
 
 
 pub struct CodeGenExport {
@@ -38,6 +48,11 @@ impl From<LLVMString> for CodeGenError {
         CodeGenError(llvmstring.to_string())
     }
 }
+impl From<EnvError> for CodeGenError {
+    fn from(err: EnvError) -> Self {
+        CodeGenError(err.to_string())
+    }
+}
 impl std::fmt::Display for CodeGenError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:#?}", self)
@@ -60,29 +75,67 @@ pub(crate) struct CodeGen<'ctx> {
     fn_alloc: IndexMap<(Symbol, usize), PointerValue<'ctx>>,
 
     // fn_params: IndexMap<Symbol, BasicValueEnum<'ctx>>,
-    fpm: PassManager<FunctionValue<'ctx>>,
     sc: Vec<usize>,
 
     phi_ret: Vec<(BasicValueEnum<'ctx>, BasicBlock<'ctx>)>,
 
     builder: Builder<'ctx>,
+
+    /// Source of `amod`, used by debug info and runtime check messages
+    src: Option<SrcFileInfo>,
+    /// Span of the MIR being translated
+    cur_span: Span,
+    dbg: Option<DebugInfo<'ctx>>,
 }
 
 impl<'ctx> CodeGen<'ctx> {
     pub(crate) fn run(
+        src: SrcFileInfo,
         amod: AMod,
         ess: ExtSymSet,
         config: CompilerConfig,
     ) -> CodeGenResult {
-        if matches!(config.target_type, TargetType::Bin) {
-            if !amod.afns.contains_key(&str2sym("main")) {
-                return Err(CodeGenError(format!(
-                    "No entry(main) found for {:?}",
-                    amod.name
-                )));
-            }
+        if matches!(config.target_type, TargetType::Bin)
+            && config.harness.is_none()
+        {
+            check_entry(&amod)?;
         }
 
+        let mut it = Self::init(amod, ess, config);
+        it.set_src(src);
+
+        it.gen_mod()?;
+        it.gen_file().map(move |_| it.export())
+    }
+
+    /// Generate LLVM module in memory without writing any file
+    pub(crate) fn gen_vmmod(
+        src: Option<SrcFileInfo>,
+        amod: AMod,
+        ess: ExtSymSet,
+        config: CompilerConfig,
+    ) -> Result<(VMMod<'ctx>, CodeGenExport), CodeGenError> {
+        let mut it = Self::init(amod, ess, config);
+
+        if let Some(src) = src {
+            it.set_src(src);
+        }
+
+        it.gen_mod()?;
+
+        if it.lto() {
+            it.link_core_bc()?;
+        }
+        it.run_passes()?;
+
+        let CodeGen {
+            vmmod, amod, ess, ..
+        } = it;
+
+        Ok((vmmod, CodeGenExport { amod, ess }))
+    }
+
+    fn init(amod: AMod, ess: ExtSymSet, config: CompilerConfig) -> Self {
         let vmmod = VMMod::new(&sym2str(amod.name));
         let blks: Vec<LogicBlock> = amod
             .scopes
@@ -91,7 +144,6 @@ impl<'ctx> CodeGen<'ctx> {
                 paren: ascope.paren,
                 bbs: vec![],
 
-                has_ret: ascope.ret_var.is_some(),
                 break_to: None,
                 continue_to: None,
 
@@ -101,35 +153,32 @@ impl<'ctx> CodeGen<'ctx> {
             })
             .collect();
 
-        let fpm = PassManager::create(&vmmod.module);
-
-        fpm.add_instruction_combining_pass();
-        fpm.add_reassociate_pass();
-        fpm.add_gvn_pass();
-        fpm.add_cfg_simplification_pass();
-        fpm.add_basic_alias_analysis_pass();
-        fpm.add_promote_memory_to_register_pass();
-        fpm.add_instruction_combining_pass();
-        fpm.add_reassociate_pass();
-        fpm.add_tail_call_elimination_pass();
-
-        fpm.initialize();
-
-        let mut it = Self {
+        Self {
             vmmod,
             amod,
             ess,
             config,
             blks,
             fn_alloc: indexmap! {},
-            fpm,
             sc: vec![0],
             phi_ret: vec![],
             builder: VMMod::get_builder(),
-        };
+            src: None,
+            cur_span: Span::default(),
+            dbg: None,
+        }
+    }
 
-        it.gen_mod();
-        it.gen_file().map(move |_| it.export())
+    /// Also start debug info of `src` for `-g`
+    fn set_src(&mut self, src: SrcFileInfo) {
+        if self.config.debug {
+            self.dbg = Some(DebugInfo::new(
+                &self.vmmod,
+                &src,
+                self.config.optlv != OptLv::Debug,
+            ));
+        }
+        self.src = Some(src);
     }
 
     pub(crate) fn export(self) -> CodeGenExport {
@@ -232,6 +281,14 @@ impl<'ctx> CodeGen<'ctx> {
         self.builder.build_unconditional_branch(bb);
         self.builder.position_at_end(bb);
     }
+
+    /// Current BB is terminated (by `ret`, `break` or `continue`)
+    pub(crate) fn is_terminated(&self) -> bool {
+        self.builder
+            .get_insert_block()
+            .and_then(|bb| bb.get_terminator())
+            .is_some()
+    }
 }
 
 
@@ -243,9 +300,17 @@ pub(crate) struct LogicBlock<'ctx> {
 
     pub(crate) break_to: Option<BasicBlock<'ctx>>,
     pub(crate) continue_to: Option<BasicBlock<'ctx>>,
+}
+
+pub(crate) fn check_entry(amod: &AMod) -> CodeGenResult2 {
+    if !amod.afns.contains_key(&str2sym("main")) {
+        return Err(CodeGenError(format!(
+            "No entry(main) found for {:?}",
+            amod.name
+        )));
+    }
 
-    /// There is `ret` instruction in this block, so we don't go next basicblock
-    pub(crate) has_ret: bool,
+    Ok(())
 }
 
 pub(crate) fn is_implicit_sym(sym: Symbol) -> bool {
@@ -276,8 +341,15 @@ pub(crate) fn sh_llvm_config(debug: bool) -> CompilerConfig {
     CompilerConfig {
         optlv: if debug { OptLv::Debug } else { OptLv::Opt2 },
         target_type: TargetType::Bin,
-        emit_type: EmitType::LLVMIR,
+        emit_types: vec![EmitType::LLVMIR],
+        ir_stage: IRStage::Post,
+        passes: PassConfig::default(),
         print_type: PrintTy::StdErr,
+        target: TargetSpec::default(),
+        linker: LinkerConfig::default(),
+        debug: false,
+        harness: None,
+        diag: DiagConfig::default(),
     }
 }
 
@@ -286,22 +358,54 @@ pub(crate) fn sh_llvm_config(debug: bool) -> CompilerConfig {
 use std::path::PathBuf;
 
 #[cfg(test)]
-#[allow(unused)]
 pub(crate) fn sh_obj_config(debug: bool, path: PathBuf) -> CompilerConfig {
     CompilerConfig {
         optlv: if debug { OptLv::Debug } else { OptLv::Opt2 },
         target_type: TargetType::Bin,
-        emit_type: EmitType::Obj,
+        emit_types: vec![EmitType::Obj],
+        ir_stage: IRStage::Post,
+        passes: PassConfig::default(),
         print_type: PrintTy::File(path),
+        target: TargetSpec::default(),
+        linker: LinkerConfig::default(),
+        debug: false,
+        harness: None,
+        diag: DiagConfig::default(),
     }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
-
-    use crate::{codegen::sh_llvm_config, driver::RunCompiler};
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        process::Command,
+    };
+
+    use m6lexerkit::SrcFileInfo;
+
+    use super::CodeGen;
+    use crate::{
+        ast_lowering::{lower_src, opt::optimize, AMod, ExtSymSet, Pass2Export},
+        codegen::{sh_llvm_config, sh_obj_config},
+        config::{CompilerConfig, Pass, PassConfig, TargetType},
+        driver::RunCompiler,
+        env::libbas_o_path,
+    };
+
+    /// Front phases of `path` with core lib, optimized
+    fn lower(
+        path: &str,
+    ) -> Result<(SrcFileInfo, AMod, ExtSymSet), Box<dyn std::error::Error>>
+    {
+        let Pass2Export {
+            src, mut amod, ess, ..
+        } = lower_src(path, true)?;
+        optimize(&mut amod);
+
+        Ok((src, amod, ess))
+    }
 
     #[test]
     fn test_codegen() -> Result<(), Box<dyn std::error::Error>> {
@@ -311,4 +415,161 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_passes() -> Result<(), Box<dyn std::error::Error>> {
+        let path = PathBuf::from("./examples/exp0.bath");
+
+        // module pipeline of `-O`
+        RunCompiler::new(&path, sh_llvm_config(false))?;
+
+        let config = CompilerConfig {
+            passes: PassConfig {
+                custom: Some(vec![
+                    Pass::Mem2Reg,
+                    Pass::InstCombine,
+                    Pass::Inline,
+                    Pass::GlobalDCE,
+                ]),
+                lto: false,
+            },
+            ..sh_llvm_config(true)
+        };
+        RunCompiler::new(&path, config)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_debug_info() -> Result<(), Box<dyn std::error::Error>> {
+        let (src, amod, ess) = lower("./examples/exp0.bath")?;
+
+        let config = CompilerConfig {
+            debug: true,
+            ..sh_llvm_config(true)
+        };
+        let (vmmod, _) = CodeGen::gen_vmmod(Some(src), amod, ess, config)?;
+
+        vmmod.module.verify()?;
+
+        let ir = vmmod.module.print_to_string().to_string();
+
+        assert!(ir.contains("!DICompileUnit("));
+        assert!(ir.contains("!DISubprogram(name: \"main\""));
+        assert!(ir.contains("call void @llvm.dbg.declare("));
+        assert!(ir.contains("!DILocalVariable(name: \"count\""));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lto() -> Result<(), Box<dyn std::error::Error>> {
+        // library keeps `count` alive without internalizing
+        fn ir_of(lto: bool) -> Result<String, Box<dyn std::error::Error>> {
+            let (src, amod, ess) = lower("./examples/lto.bath")?;
+            let config = CompilerConfig {
+                target_type: TargetType::DyLib,
+                passes: PassConfig { custom: None, lto },
+                ..sh_llvm_config(false)
+            };
+            let (vmmod, _) =
+                CodeGen::gen_vmmod(Some(src), amod, ess, config)?;
+
+            Ok(vmmod.module.print_to_string().to_string())
+        }
+
+        assert!(ir_of(false)?.contains("call i32 @\"len@"));
+        assert!(!ir_of(true)?.contains("call i32 @\"len@"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_staticlib() -> Result<(), Box<dyn std::error::Error>> {
+        let members = |path: &Path| -> std::io::Result<Vec<String>> {
+            let output = Command::new("ar").arg("t").arg(path).output()?;
+
+            Ok(String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(ToOwned::to_owned)
+                .collect())
+        };
+
+        let path = std::env::temp_dir()
+            .join(format!("bas-staticlib-{}.a", std::process::id()));
+        let config = CompilerConfig {
+            target_type: TargetType::StaticLib,
+            ..sh_obj_config(true, path.clone())
+        };
+
+        RunCompiler::new(&"./examples/lto.bath", config)?;
+
+        let archived = members(&path)?;
+        fs::remove_file(&path)?;
+
+        // module object, core lib and members of the runtime archive
+        assert!(archived[0].starts_with("bas-staticlib-"));
+        assert_eq!(archived[1], "core.o");
+        assert_eq!(archived[2..], members(&libbas_o_path(None)?)?[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_loop_break() -> Result<(), Box<dyn std::error::Error>> {
+        let status = RunCompiler::with_exe(
+            &"./examples/loop_type.bath",
+            sh_llvm_config(true),
+            |exe| Command::new(exe).status(),
+        )?;
+
+        assert_eq!(status.code(), Some(5 + 1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_loop_continue() -> Result<(), Box<dyn std::error::Error>> {
+        let output = RunCompiler::with_exe(
+            &"./examples/loop_continue.bath",
+            sh_llvm_config(true),
+            |exe| Command::new(exe).output(),
+        )?;
+
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "skip 1\nskip 2\n3\n4\n5\n"
+        );
+        assert_eq!(output.status.code(), Some(3 + 4 + 5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmd_str() -> Result<(), Box<dyn std::error::Error>> {
+        let output = RunCompiler::with_exe(
+            &"./examples/cmd_str.bath",
+            sh_llvm_config(true),
+            |exe| Command::new(exe).output(),
+        )?;
+
+        // read after their command results are dropped
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "hello world\n");
+        assert!(output.status.success());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cast_sign() -> Result<(), Box<dyn std::error::Error>> {
+        let status = RunCompiler::with_exe(
+            &"./examples/cast.bath",
+            sh_llvm_config(true),
+            |exe| Command::new(exe).status(),
+        )?;
+
+        assert_eq!(status.code(), Some(3 + 10));
+
+        Ok(())
+    }
 }
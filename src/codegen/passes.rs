@@ -0,0 +1,131 @@
+use inkwellkit::{
+    get_ctx,
+    module::Module,
+    passes::{PassManager, PassManagerBuilder},
+    OptimizationLevel,
+};
+
+use super::{CodeGen, CodeGenError, CodeGenResult2};
+use crate::{
+    config::{OptLv, Pass, TargetType},
+    env::core_bc_path,
+};
+
+
+impl Pass {
+    fn add_to(self, pm: &PassManager<Module>) {
+        match self {
+            Self::Mem2Reg => pm.add_promote_memory_to_register_pass(),
+            Self::InstCombine => pm.add_instruction_combining_pass(),
+            Self::Reassociate => pm.add_reassociate_pass(),
+            Self::GVN => pm.add_gvn_pass(),
+            Self::SimplifyCFG => pm.add_cfg_simplification_pass(),
+            Self::BasicAA => pm.add_basic_alias_analysis_pass(),
+            Self::SCCP => pm.add_sccp_pass(),
+            Self::ADCE => pm.add_aggressive_dce_pass(),
+            Self::DSE => pm.add_dead_store_elimination_pass(),
+            Self::LICM => pm.add_licm_pass(),
+            Self::TailCallElim => pm.add_tail_call_elimination_pass(),
+            Self::Inline => pm.add_function_inlining_pass(),
+            Self::AlwaysInline => pm.add_always_inliner_pass(),
+            Self::IPSCCP => pm.add_ipsccp_pass(),
+            Self::GlobalOpt => pm.add_global_optimizer_pass(),
+            Self::GlobalDCE => pm.add_global_dce_pass(),
+            Self::ConstMerge => pm.add_constant_merge_pass(),
+            Self::Internalize => pm.add_internalize_pass(true),
+        }
+    }
+}
+
+
+
+impl<'ctx> CodeGen<'ctx> {
+    ///////////////////////////////////////////////////////////////////////////
+    //// Optimization Passes
+
+    /// Core lib is merged into module instead of being linked
+    pub(crate) fn lto(&self) -> bool {
+        self.config.passes.lto
+            && self.config.target_type != TargetType::ReLoc
+    }
+
+    /// Link bitcode of core lib into module
+    pub(crate) fn link_core_bc(&self) -> CodeGenResult2 {
+        let path = core_bc_path(self.config.target.triple.as_deref())?;
+
+        let core = Module::parse_bitcode_from_path(&path, get_ctx())
+            .map_err(|llvmstr| {
+                CodeGenError(format!(
+                    "Load core bitcode {:?} failed: {}",
+                    path, llvmstr
+                ))
+            })?;
+
+        self.vmmod.module.link_in_module(core)?;
+
+        Ok(())
+    }
+
+    /// Run `--passes` if it's specified, or else the pipeline of `-O` level
+    /// (nothing for debug)
+    pub(crate) fn run_passes(&self) -> CodeGenResult2 {
+        let module = &self.vmmod.module;
+
+        module.verify()?;
+
+        if let Some(ref passes) = self.config.passes.custom {
+            let mpm = PassManager::create(());
+
+            for pass in passes.iter() {
+                pass.add_to(&mpm);
+            }
+
+            mpm.run_on(module);
+        } else if self.config.optlv != OptLv::Debug {
+            self.run_pipeline();
+        }
+
+        Ok(())
+    }
+
+    /// Function passes on each body, then module passes with inlining and
+    /// global DCE (and LTO passes)
+    fn run_pipeline(&self) {
+        let module = &self.vmmod.module;
+        let level: OptimizationLevel = self.config.optlv.into();
+
+        let pmb = PassManagerBuilder::create();
+        pmb.set_optimization_level(level);
+        // thresholds of clang
+        pmb.set_inliner_with_threshold(match level {
+            OptimizationLevel::Aggressive => 250,
+            _ => 225,
+        });
+
+        let fpm = PassManager::create(module);
+        pmb.populate_function_pass_manager(&fpm);
+
+        fpm.initialize();
+        for fn_val in module.get_functions() {
+            fpm.run_on(&fn_val);
+        }
+        fpm.finalize();
+
+        let mpm = PassManager::create(());
+        pmb.populate_module_pass_manager(&mpm);
+
+        if self.lto() {
+            // symbols of dylib and staticlib are used by others
+            let internalize = self.config.target_type == TargetType::Bin;
+
+            if internalize {
+                mpm.add_internalize_pass(true);
+            }
+            pmb.populate_lto_pass_manager(&mpm, internalize, true);
+        }
+
+        mpm.add_global_dce_pass();
+
+        mpm.run_on(module);
+    }
+}
@@ -1,20 +1,21 @@
 use std::{
-    fs,
+    env, fs,
+    io::{self, Write},
     path::{Path, PathBuf},
     process::{Command, Stdio},
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use inkwellkit::{
-    config::{EmitType, PrintTy, TargetType},
-    targets::{
-        CodeModel, FileType, InitializationConfig, RelocMode, Target,
-        TargetMachine,
-    },
+use inkwellkit::targets::{
+    CodeModel, FileType, InitializationConfig, RelocMode, Target,
+    TargetMachine, TargetTriple,
 };
 
 use super::{CodeGen, CodeGenError, CodeGenResult2};
-use crate::env::{libbas_o_path, core_lib_path};
+use crate::{
+    config::{EmitType, IRStage, PrintTy, TargetType},
+    env::{libbas_o_path, core_lib_path},
+};
 
 
 fn unique_suffix() -> String {
@@ -26,13 +27,53 @@ fn unique_suffix() -> String {
 }
 
 
+/// Linker driver and extra flags to select the linker.
+///
+/// Priority: config > `CC` env > `<triple>-gcc` (cross) > `cc`, a raw
+/// linker (`lld`, `ld.<flavor>`) of config is run by the next driver.
+fn select_linker(
+    linker: Option<&str>,
+    cc: Option<&str>,
+    triple: Option<&str>,
+) -> (String, Vec<String>) {
+    let driver = match (cc, triple) {
+        (Some(cc), _) if !cc.is_empty() => cc.to_owned(),
+        (_, Some(triple)) => format!("{triple}-gcc"),
+        _ => "cc".to_owned(),
+    };
+
+    let Some(linker) = linker else {
+        return (driver, vec![]);
+    };
+
+    let name = Path::new(linker)
+        .file_name()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    // Raw linker needs crt objects and libc, leave them to the driver
+    if name == "lld" {
+        (driver, vec!["-fuse-ld=lld".to_owned()])
+    } else if let Some(flavor) = name.strip_prefix("ld.") {
+        (driver, vec![format!("-fuse-ld={flavor}")])
+    } else {
+        (linker.to_owned(), vec![])
+    }
+}
+
+
 
 impl<'ctx> CodeGen<'ctx> {
     ///////////////////////////////////////////////////////////////////////////
     //// Target Generation
 
+    /// Output path of `emit_type`, `None` means stderr
+    fn emit_path(&self, emit_type: EmitType) -> Option<PathBuf> {
+        self.config.emit_path(emit_type)
+    }
+
     fn tmp_obj_fname(&self) -> PathBuf {
-        let src_path = self.config.print_type.get_path().unwrap();
+        let src_path = self.emit_path(EmitType::Obj).unwrap();
         let filename = src_path.file_name().unwrap().to_str().unwrap();
 
         src_path
@@ -41,18 +82,50 @@ impl<'ctx> CodeGen<'ctx> {
     }
 
     pub(crate) fn gen_file(&self) -> CodeGenResult2 {
-        match self.config.emit_type {
-            EmitType::Obj => {
-                self.emit_obj()?;
+        let emit_types = &self.config.emit_types;
 
-                Ok(())
+        // module triple and data layout also matter for IR of cross target
+        let machine = if !self.config.target.is_host()
+            || emit_types
+                .iter()
+                .any(|ty| matches!(ty, EmitType::Obj | EmitType::Asm))
+        {
+            Some(self.target_machine()?)
+        } else {
+            None
+        };
+
+        if self.lto() {
+            self.link_core_bc()?;
+        }
+
+        if self.config.ir_stage == IRStage::Pre
+            && emit_types.contains(&EmitType::LLVMIR)
+        {
+            self.emit_llvmir()?;
+        }
+
+        self.run_passes()?;
+
+        for emit_type in emit_types.iter() {
+            match emit_type {
+                EmitType::Obj => self.emit_obj(machine.as_ref().unwrap())?,
+                EmitType::Asm => self.emit_asm(machine.as_ref().unwrap())?,
+                EmitType::LLVMIR => {
+                    if self.config.ir_stage == IRStage::Post {
+                        self.emit_llvmir()?
+                    }
+                }
+                EmitType::BC => self.emit_bc()?,
+                // written by driver
+                EmitType::Tokens | EmitType::TT | EmitType::MIR => (),
             }
-            EmitType::LLVMIR => self.emit_llvmir(),
-            _ => todo!(),
         }
+
+        Ok(())
     }
 
-    fn emit_obj(&self) -> CodeGenResult2 {
+    fn emit_obj(&self, machine: &TargetMachine) -> CodeGenResult2 {
         if matches!(self.config.print_type, PrintTy::StdErr) {
             return Err(CodeGenError(
                 format!(
@@ -62,30 +135,6 @@ impl<'ctx> CodeGen<'ctx> {
             ))
         }
 
-        Target::initialize_native(&InitializationConfig::default())
-            .map_err(|s| CodeGenError(s))?;
-
-        let triple = TargetMachine::get_default_triple();
-        self.vmmod.module.set_triple(&triple);
-
-        let target = Target::from_triple(&triple).unwrap();
-
-        let machine = target
-            .create_target_machine(
-                &triple,
-                "generic",
-                "",
-                self.config.optlv.into(),
-                self.reloc_mode(),
-                CodeModel::Default,
-            )
-            .unwrap();
-
-        self.vmmod
-            .module
-            .set_data_layout(&machine.get_target_data().get_data_layout());
-
-
         match self.config.target_type {
             TargetType::Bin => {
                 let tmp_input = self.tmp_obj_fname();
@@ -96,24 +145,138 @@ impl<'ctx> CodeGen<'ctx> {
                     &tmp_input,
                 )?;
 
-                self.link_core(&tmp_input)?;
+                self.link_core(&tmp_input, &[])?;
                 self.clean_obj(&tmp_input)?;
             }
             TargetType::ReLoc => {
                 machine.write_to_file(
                     &self.vmmod.module,
                     FileType::Object,
-                    &self.config.print_type.get_path().unwrap(),
+                    &self.emit_path(EmitType::Obj).unwrap(),
+                )?;
+            }
+            TargetType::DyLib => {
+                let tmp_input = self.tmp_obj_fname();
+
+                machine.write_to_file(
+                    &self.vmmod.module,
+                    FileType::Object,
+                    &tmp_input,
                 )?;
+
+                self.link_core(&tmp_input, &["-shared"])?;
+                self.clean_obj(&tmp_input)?;
             }
-            _ => todo!(),
+            TargetType::StaticLib => {
+                let tmp_input = self.tmp_obj_fname();
+
+                machine.write_to_file(
+                    &self.vmmod.module,
+                    FileType::Object,
+                    &tmp_input,
+                )?;
+
+                self.archive_core(&tmp_input)?;
+                self.clean_obj(&tmp_input)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create target machine from `config.target` and set triple and data
+    /// layout of the module to match it
+    fn target_machine(&self) -> Result<TargetMachine, CodeGenError> {
+        let spec = &self.config.target;
+
+        let triple = match spec.triple {
+            Some(ref triple) => {
+                Target::initialize_all(&InitializationConfig::default());
+                TargetTriple::create(triple)
+            }
+            None => {
+                Target::initialize_native(&InitializationConfig::default())
+                    .map_err(|s| CodeGenError(s))?;
+                TargetMachine::get_default_triple()
+            }
+        };
+
+        let target = Target::from_triple(&triple)
+            .map_err(|llvmstr| CodeGenError::from(llvmstr))?;
+
+        let (cpu, mut features) = match spec.cpu.as_deref() {
+            Some("native") => (
+                TargetMachine::get_host_cpu_name().to_string(),
+                vec![TargetMachine::get_host_cpu_features().to_string()],
+            ),
+            Some(cpu) => (cpu.to_owned(), vec![]),
+            None => ("generic".to_owned(), vec![]),
+        };
+        features.extend(spec.features.iter().cloned());
+
+        let machine = target
+            .create_target_machine(
+                &triple,
+                &cpu,
+                &features.join(","),
+                self.config.optlv.into(),
+                self.reloc_mode(),
+                CodeModel::Default,
+            )
+            .ok_or_else(|| {
+                CodeGenError(format!(
+                    "Unsupported target {:?} (cpu: {}, features: {:?})",
+                    triple, cpu, features
+                ))
+            })?;
+
+        self.vmmod.module.set_triple(&triple);
+        self.vmmod
+            .module
+            .set_data_layout(&machine.get_target_data().get_data_layout());
+
+        Ok(machine)
+    }
+
+    fn emit_asm(&self, machine: &TargetMachine) -> CodeGenResult2 {
+        if let Some(path) = self.emit_path(EmitType::Asm) {
+            machine.write_to_file(
+                &self.vmmod.module,
+                FileType::Assembly,
+                &path,
+            )?;
+        } else {
+            let buf = machine.write_to_memory_buffer(
+                &self.vmmod.module,
+                FileType::Assembly,
+            )?;
+
+            io::stderr()
+                .write_all(buf.as_slice())
+                .map_err(|st| CodeGenError(st.to_string()))?;
         }
 
         Ok(())
     }
 
+    fn emit_bc(&self) -> CodeGenResult2 {
+        let Some(path) = self.emit_path(EmitType::BC) else {
+            return Err(CodeGenError(format!(
+                "Unsupported output type {:?} for emit bitcode",
+                self.config.print_type
+            )));
+        };
+
+        // return true if it succeeded
+        if self.vmmod.module.write_bitcode_to_path(&path) {
+            Ok(())
+        } else {
+            Err(CodeGenError(format!("Write bitcode {:?} failed", path)))
+        }
+    }
+
     fn emit_llvmir(&self) -> CodeGenResult2 {
-        if let PrintTy::File(ref path) = self.config.print_type {
+        if let Some(path) = self.emit_path(EmitType::LLVMIR) {
             self.vmmod
                 .module
                 .print_to_file(path)
@@ -125,24 +288,180 @@ impl<'ctx> CodeGen<'ctx> {
 
     fn reloc_mode(&self) -> RelocMode {
         match self.config.target_type {
+            TargetType::Bin if self.config.linker.pie => RelocMode::PIC,
             TargetType::Bin => RelocMode::Default,
-            TargetType::ReLoc => RelocMode::PIC,
-            TargetType::DyLib => RelocMode::DynamicNoPic,
+            TargetType::ReLoc | TargetType::DyLib | TargetType::StaticLib => {
+                RelocMode::PIC
+            }
         }
     }
 
-    fn link_core(&self, input: &Path) -> CodeGenResult2 {
-        Command::new("gcc")
+    /// Return linker driver and extra flags to select the linker
+    fn linker(&self) -> (String, Vec<String>) {
+        select_linker(
+            self.config.linker.linker.as_deref(),
+            env::var("CC").ok().as_deref(),
+            self.config.target.triple.as_deref(),
+        )
+    }
+
+    fn link_args(&self) -> Vec<String> {
+        let linker_config = &self.config.linker;
+        let mut args = vec![];
+
+        for lib_path in linker_config.lib_paths.iter() {
+            args.push(format!("-L{}", lib_path.display()));
+        }
+
+        for lib in linker_config.libs.iter() {
+            args.push(format!("-l{lib}"));
+        }
+
+        if self.config.target_type == TargetType::Bin {
+            match (linker_config.static_link, linker_config.pie) {
+                (true, true) => args.push("-static-pie".to_owned()),
+                (true, false) => args.push("-static".to_owned()),
+                (false, true) => args.push("-pie".to_owned()),
+                (false, false) => (),
+            }
+        }
+
+        if linker_config.strip {
+            args.push("-s".to_owned());
+        }
+
+        args
+    }
+
+    fn check_runtime(&self) -> CodeGenResult2 {
+        let triple = self.config.target.triple.as_deref();
+
+        let runtime = libbas_o_path(triple)?;
+
+        if !runtime.exists() {
+            // host runtime is materialized unless BAS_HOME is overridden
+            let hint = match triple {
+                Some(triple) => format!(
+                    "build it by `make getlib TARGET={triple}` with the same \
+                     BAS_HOME"
+                ),
+                None => "check BAS_HOME or unset it".to_owned(),
+            };
+
+            return Err(CodeGenError(format!(
+                "Runtime {:?} for target {} not found, {}",
+                runtime,
+                triple.unwrap_or("host"),
+                hint
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn link_core(&self, input: &Path, extra: &[&str]) -> CodeGenResult2 {
+        let triple = self.config.target.triple.as_deref();
+
+        self.check_runtime()?;
+
+        let (linker, linker_flags) = self.linker();
+
+        // `-l` libs come after objects so that archives resolve their symbols
+        let output = Command::new(&linker)
+            .args(linker_flags)
+            .args(extra)
             .arg(input)
-            .arg(libbas_o_path())
-            .arg(core_lib_path())
+            .arg(libbas_o_path(triple)?)
+            .args(self.core_obj()?)
+            .args(self.link_args())
             .arg("-o")
-            .arg(self.config.print_type.get_path().unwrap())
+            .arg(self.emit_path(EmitType::Obj).unwrap())
             .stdin(Stdio::null())
             .stdout(Stdio::inherit())
-            .status()
-            .and_then(|_| Ok(()))
-            .or_else(|st| Err(CodeGenError(st.to_string())))
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|st| {
+                CodeGenError(format!("Run linker `{linker}` failed: {st}"))
+            })?;
+
+        if !output.status.success() {
+            return Err(CodeGenError(format!(
+                "Linker `{}` failed ({}):\n{}",
+                linker,
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Bundle module object, core lib and runtime into one archive
+    fn archive_core(&self, input: &Path) -> CodeGenResult2 {
+        let triple = self.config.target.triple.as_deref();
+
+        self.check_runtime()?;
+
+        let output = self.emit_path(EmitType::Obj).unwrap();
+
+        // `ar` appends into existing archive
+        if output.exists() {
+            self.clean_obj(&output)?;
+        }
+
+        // MRI script, `ADDLIB` merges members of the runtime archive
+        let script = format!(
+            "CREATE {}\nADDMOD {}\n{}ADDLIB {}\nSAVE\nEND\n",
+            output.display(),
+            input.display(),
+            self.core_obj()?
+                .map(|path| format!("ADDMOD {}\n", path.display()))
+                .unwrap_or_default(),
+            libbas_o_path(triple)?.display(),
+        );
+
+        let ar = match triple {
+            Some(triple) => format!("{triple}-ar"),
+            None => "ar".to_owned(),
+        };
+
+        let mut child = Command::new(ar)
+            .arg("-M")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|st| CodeGenError(st.to_string()))?;
+
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(script.as_bytes())
+            .map_err(|st| CodeGenError(st.to_string()))?;
+
+        let output = child
+            .wait_with_output()
+            .map_err(|st| CodeGenError(st.to_string()))?;
+
+        if !output.status.success() {
+            return Err(CodeGenError(format!(
+                "ar failed ({}):\n{}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Object of core lib, `None` if it's merged by LTO
+    fn core_obj(&self) -> Result<Option<PathBuf>, CodeGenError> {
+        if self.lto() {
+            Ok(None)
+        } else {
+            Ok(Some(core_lib_path(self.config.target.triple.as_deref())?))
+        }
     }
 
     fn clean_obj(&self, input: &Path) -> CodeGenResult2 {
@@ -150,3 +469,90 @@ impl<'ctx> CodeGen<'ctx> {
             .or_else(|st| Err(CodeGenError(st.to_string())))
     }
 }
+
+
+
+#[cfg(test)]
+mod tests {
+    use m6lexerkit::str2sym;
+
+    use super::{select_linker, CodeGen, CodeGenError};
+    use crate::{
+        ast_lowering::{AMod, ExtSymSet},
+        codegen::sh_llvm_config,
+        config::{CompilerConfig, TargetSpec},
+    };
+
+    /// Module IR after creating target machine of `triple`
+    fn target_ir(triple: &str) -> Result<String, CodeGenError> {
+        let config = CompilerConfig {
+            target: TargetSpec {
+                triple: Some(triple.to_owned()),
+                ..Default::default()
+            },
+            ..sh_llvm_config(true)
+        };
+        let it = CodeGen::init(
+            AMod::init(str2sym("target")),
+            ExtSymSet { mods: vec![] },
+            config,
+        );
+
+        it.target_machine()?;
+
+        Ok(it.vmmod.module.print_to_string().to_string())
+    }
+
+    #[test]
+    fn test_target_triple() -> Result<(), CodeGenError> {
+        let ir = target_ir("aarch64-unknown-linux-gnu")?;
+        assert!(ir.contains("target triple = \"aarch64-unknown-linux-gnu\""));
+        assert!(ir.contains("target datalayout = \"e-m:e-"));
+
+        // 32 bit pointers
+        let ir = target_ir("i686-unknown-linux-gnu")?;
+        assert!(ir.contains("target triple = \"i686-unknown-linux-gnu\""));
+        assert!(ir.contains("-p:32:32-"));
+
+        assert!(target_ir("nowhere-unknown-none").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_linker() {
+        let cross = Some("aarch64-linux-gnu");
+        let driver = |linker, cc| select_linker(linker, cc, cross).0;
+
+        // config > `CC` > `<triple>-gcc` > `cc`
+        assert_eq!(driver(Some("clang"), Some("gcc")), "clang");
+        assert_eq!(driver(None, Some("gcc-12")), "gcc-12");
+        assert_eq!(driver(None, Some("")), "aarch64-linux-gnu-gcc");
+        assert_eq!(driver(None, None), "aarch64-linux-gnu-gcc");
+        assert_eq!(select_linker(None, None, None), ("cc".to_owned(), vec![]));
+    }
+
+
+    #[test]
+    fn test_select_raw_linker() {
+        let fuse_lld = vec!["-fuse-ld=lld".to_owned()];
+
+        assert_eq!(
+            select_linker(Some("lld"), None, None),
+            ("cc".to_owned(), fuse_lld.clone())
+        );
+        assert_eq!(
+            select_linker(Some("/usr/bin/ld.lld"), Some("clang"), None),
+            ("clang".to_owned(), fuse_lld)
+        );
+
+        // cross driver instead of host `cc`
+        assert_eq!(
+            select_linker(Some("ld.gold"), None, Some("i686-linux-gnu")),
+            (
+                "i686-linux-gnu-gcc".to_owned(),
+                vec!["-fuse-ld=gold".to_owned()]
+            )
+        );
+    }
+}
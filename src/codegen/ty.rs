@@ -1,12 +1,12 @@
 use inkwellkit::
-    {types::{ BasicMetadataTypeEnum, RetTypeEnum }, AddressSpace };
+    {types::{ BasicMetadataTypeEnum, BasicTypeEnum, RetTypeEnum }, AddressSpace };
 
 use inkwellkit::{ load_vm_common_ty, get_ctx };
 use m6lexerkit::sym2str;
 
 use crate::ast_lowering::{ AType, APriType };
 
-use super::CodeGen;
+use super::{CodeGen, CodeGenError};
 
 
 // pub const ID_VEC: &str = "Vec";
@@ -30,11 +30,19 @@ impl<'ctx> CodeGen<'ctx> {
                     _ => unimplemented!("{:?}", aty)
                 },
                 APriType::Ptr => i8ptr_t.into(),
-                APriType::OpaqueStruct(name) =>
-                    get_ctx()
-                    .opaque_struct_type(&sym2str(*name))
-                    .ptr_type(AddressSpace::Generic)
-                    .into(),
+                APriType::OpaqueStruct(name) => {
+                    let name = sym2str(*name);
+
+                    // reuse it, or else a renamed new one is created
+                    self.vmmod
+                        .module
+                        .get_struct_type(&name)
+                        .unwrap_or_else(|| {
+                            get_ctx().opaque_struct_type(&name)
+                        })
+                        .ptr_type(AddressSpace::Generic)
+                        .into()
+                },
             },
             AType::Arr(ty, d) => {
                 self.aty_arr_as_ret_type(ty, d)
@@ -55,6 +63,21 @@ impl<'ctx> CodeGen<'ctx> {
         }
     }
 
+    pub(super) fn gen_aty_as_basic_type(
+        &self,
+        aty: &AType,
+    ) -> Result<BasicTypeEnum<'ctx>, CodeGenError> {
+        match aty {
+            AType::Pri(_) | AType::Arr(..) => {
+                Ok(self.gen_aty_as_ret_type(aty).try_into().unwrap())
+            }
+            AType::AA(_) => Err(CodeGenError(
+                "associative array isn't supported yet".to_owned(),
+            )),
+            _ => unreachable!("{:#?}", aty),
+        }
+    }
+
     pub fn aty_arr_as_ret_type(
         &self,
         _aty: &APriType,
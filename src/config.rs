@@ -0,0 +1,377 @@
+//! Compiler configuration, extends `inkwellkit::config`
+
+use std::{fmt, path::PathBuf};
+
+use clap::ArgEnum;
+pub use inkwellkit::config::{OptLv, PrintTy};
+
+pub use crate::diagnostics::ErrorFormat;
+use crate::lint::{LintLevel, LintSel};
+
+
+pub struct CompilerConfig {
+    pub optlv: OptLv,
+    pub target_type: TargetType,
+    /// Emit several artifacts in one run, if there are more than one, each
+    /// artifact except `Obj` replaces extension of the output path (see
+    /// `check_emit_paths`)
+    pub emit_types: Vec<EmitType>,
+    /// Emit LLVM IR before or after the optimization passes
+    pub ir_stage: IRStage,
+    pub passes: PassConfig,
+    pub print_type: PrintTy,
+    pub target: TargetSpec,
+    pub linker: LinkerConfig,
+    /// Generate DWARF debug info
+    pub debug: bool,
+    /// Generate harness `main` running `@test` or `@bench` functions
+    /// instead of the `main` of source
+    pub harness: Option<Harness>,
+    pub diag: DiagConfig,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum TargetType {
+    /// Executable
+    #[clap(name = "bin")]
+    Bin,
+    /// Relocatable object
+    #[clap(name = "lib")]
+    ReLoc,
+    /// Shared object linked with runtime
+    #[clap(name = "dylib")]
+    DyLib,
+    /// Static archive bundled with core lib and runtime
+    #[clap(name = "staticlib")]
+    StaticLib,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum EmitType {
+    /// Object file or linked target (specified by target type)
+    #[clap(name = "obj")]
+    Obj,
+    #[clap(name = "asm")]
+    Asm,
+    #[clap(name = "llvm-ir")]
+    LLVMIR,
+    /// LLVM bitcode
+    #[clap(name = "bc")]
+    BC,
+    /// Tokens of lexer
+    #[clap(name = "tokens")]
+    Tokens,
+    /// Syntax tree of parser
+    #[clap(name = "tt")]
+    TT,
+    /// Analyzed module (MIR) with its scopes
+    #[clap(name = "mir")]
+    MIR,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum IRStage {
+    /// Before optimization passes run
+    #[clap(name = "pre")]
+    Pre,
+    #[clap(name = "post")]
+    Post,
+}
+
+
+/// LLVM pass, named as `opt` of LLVM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum Pass {
+    #[clap(name = "mem2reg")]
+    Mem2Reg,
+    #[clap(name = "instcombine")]
+    InstCombine,
+    #[clap(name = "reassociate")]
+    Reassociate,
+    #[clap(name = "gvn")]
+    GVN,
+    #[clap(name = "simplifycfg")]
+    SimplifyCFG,
+    #[clap(name = "basicaa")]
+    BasicAA,
+    #[clap(name = "sccp")]
+    SCCP,
+    #[clap(name = "adce")]
+    ADCE,
+    #[clap(name = "dse")]
+    DSE,
+    #[clap(name = "licm")]
+    LICM,
+    #[clap(name = "tailcallelim")]
+    TailCallElim,
+    #[clap(name = "inline")]
+    Inline,
+    #[clap(name = "always-inline")]
+    AlwaysInline,
+    #[clap(name = "ipsccp")]
+    IPSCCP,
+    #[clap(name = "globalopt")]
+    GlobalOpt,
+    #[clap(name = "globaldce")]
+    GlobalDCE,
+    #[clap(name = "constmerge")]
+    ConstMerge,
+    /// Make all symbols except `main` internal
+    #[clap(name = "internalize")]
+    Internalize,
+}
+
+
+/// Entry generated by `basc test` or `basc bench`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Harness {
+    Test,
+    Bench,
+}
+
+
+/// Target machine description, `None` means the host default
+#[derive(Debug, Clone, Default)]
+pub struct TargetSpec {
+    pub triple: Option<String>,
+    /// `native` means host cpu
+    pub cpu: Option<String>,
+    /// like `+sse4.2`, `-avx`
+    pub features: Vec<String>,
+}
+
+
+
+/// Options passed to linker driver when generating bin or dylib
+#[derive(Debug, Clone, Default)]
+pub struct LinkerConfig {
+    /// Linker driver like `cc`, `clang`, or linker like `ld.lld` (used
+    /// through `cc -fuse-ld=lld`), fallback to `CC` env and then `cc`
+    pub linker: Option<String>,
+    /// `-l`
+    pub libs: Vec<String>,
+    /// `-L`
+    pub lib_paths: Vec<PathBuf>,
+    pub static_link: bool,
+    pub pie: bool,
+    pub strip: bool,
+}
+
+
+/// LLVM passes run on the generated module
+#[derive(Debug, Clone, Default)]
+pub struct PassConfig {
+    /// Run exactly these passes in order instead of the pipeline of `-O`
+    /// level (even `-O debug`)
+    pub custom: Option<Vec<Pass>>,
+    /// Merge bitcode of core lib into the module before passes, so that
+    /// core functions can be inlined, core object isn't linked then
+    pub lto: bool,
+}
+
+
+/// Output format of diagnostics and lint levels from command line
+#[derive(Debug, Clone, Default)]
+pub struct DiagConfig {
+    pub error_format: ErrorFormat,
+    /// Applied in order, later one overrides
+    pub lint_levels: Vec<(LintSel, LintLevel)>,
+}
+
+
+impl Default for CompilerConfig {
+    fn default() -> Self {
+        Self {
+            optlv: OptLv::Debug,
+            target_type: TargetType::default(),
+            emit_types: vec![EmitType::default()],
+            ir_stage: IRStage::default(),
+            passes: PassConfig::default(),
+            print_type: PrintTy::StdErr,
+            target: TargetSpec::default(),
+            linker: LinkerConfig::default(),
+            debug: false,
+            harness: None,
+            diag: DiagConfig::default(),
+        }
+    }
+}
+
+
+impl Default for TargetType {
+    fn default() -> Self {
+        Self::Bin
+    }
+}
+
+
+impl fmt::Display for TargetType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_possible_value().unwrap().get_name())
+    }
+}
+
+
+impl Default for EmitType {
+    fn default() -> Self {
+        Self::Obj
+    }
+}
+
+
+impl fmt::Display for EmitType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_possible_value().unwrap().get_name())
+    }
+}
+
+
+impl EmitType {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Obj => "o",
+            Self::Asm => "s",
+            Self::LLVMIR => "ll",
+            Self::BC => "bc",
+            Self::Tokens => "tokens",
+            Self::TT => "tt",
+            Self::MIR => "mir",
+        }
+    }
+
+    /// Dump of front stages, written before code generation
+    pub fn is_dump(&self) -> bool {
+        matches!(self, Self::Tokens | Self::TT | Self::MIR)
+    }
+}
+
+
+impl CompilerConfig {
+    /// Output path of `emit_type`, `None` means stderr
+    pub fn emit_path(&self, emit_type: EmitType) -> Option<PathBuf> {
+        let PrintTy::File(ref path) = self.print_type else {
+            return None;
+        };
+
+        if self.emit_types.len() == 1 || emit_type == EmitType::Obj {
+            Some(path.clone())
+        } else {
+            Some(path.with_extension(emit_type.extension()))
+        }
+    }
+
+    /// Artifacts must be written to different files, i.e. extension of the
+    /// output path isn't the one of other emit types
+    pub fn check_emit_paths(&self) -> Result<(), String> {
+        let mut paths: Vec<(PathBuf, EmitType)> = vec![];
+
+        for emit_type in self.emit_types.iter().copied() {
+            let Some(path) = self.emit_path(emit_type) else {
+                continue;
+            };
+
+            if let Some((_, prev)) = paths.iter().find(|(prev_path, prev)| {
+                *prev_path == path && *prev != emit_type
+            }) {
+                return Err(format!(
+                    "emit types `{prev}` and `{emit_type}` are both written \
+                     to `{}`, use an output path without their extension",
+                    path.display()
+                ));
+            }
+
+            paths.push((path, emit_type));
+        }
+
+        Ok(())
+    }
+}
+
+
+impl Default for IRStage {
+    fn default() -> Self {
+        Self::Post
+    }
+}
+
+
+impl fmt::Display for IRStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_possible_value().unwrap().get_name())
+    }
+}
+
+
+impl TargetSpec {
+    pub fn is_host(&self) -> bool {
+        self.triple.is_none()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{CompilerConfig, EmitType, PrintTy};
+
+    fn config(emit_types: Vec<EmitType>, output: &str) -> CompilerConfig {
+        CompilerConfig {
+            emit_types,
+            print_type: PrintTy::File(PathBuf::from(output)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_emit_paths() {
+        // only one, it's written to the output path as is
+        let single = config(vec![EmitType::LLVMIR], "foo.txt");
+        assert_eq!(
+            single.emit_path(EmitType::LLVMIR),
+            Some(PathBuf::from("foo.txt"))
+        );
+        assert!(single.check_emit_paths().is_ok());
+
+        // others replace extension of the output path
+        let multi = config(
+            vec![EmitType::Obj, EmitType::LLVMIR, EmitType::Asm],
+            "out/foo",
+        );
+        assert_eq!(
+            [EmitType::Obj, EmitType::LLVMIR, EmitType::Asm]
+                .map(|ty| multi.emit_path(ty).unwrap()),
+            ["out/foo", "out/foo.ll", "out/foo.s"].map(PathBuf::from)
+        );
+        assert!(multi.check_emit_paths().is_ok());
+
+        let no_obj = config(vec![EmitType::LLVMIR, EmitType::BC], "foo.ll");
+        assert!(no_obj.check_emit_paths().is_ok());
+
+        let stderr = CompilerConfig {
+            emit_types: vec![EmitType::Tokens, EmitType::MIR],
+            ..Default::default()
+        };
+        assert_eq!(stderr.emit_path(EmitType::MIR), None);
+        assert!(stderr.check_emit_paths().is_ok());
+    }
+
+    #[test]
+    fn test_emit_paths_conflict() {
+        // the object would be overwritten by the IR
+        let conflict =
+            config(vec![EmitType::Obj, EmitType::LLVMIR], "foo.ll");
+        assert_eq!(
+            conflict.check_emit_paths().unwrap_err(),
+            "emit types `obj` and `llvm-ir` are both written to `foo.ll`, \
+             use an output path without their extension"
+        );
+
+        // repeated one isn't a conflict
+        let repeated = config(vec![EmitType::Obj, EmitType::Obj], "foo.o");
+        assert!(repeated.check_emit_paths().is_ok());
+    }
+}
@@ -0,0 +1,433 @@
+//! Structured diagnostics shared by all compiler phases
+//!
+//! Each diagnostic has a stable code, a severity, a primary span, optional
+//! secondary labeled spans, notes and help text. It renders either for
+//! human (optionally colored) or as one JSON object per line.
+
+use std::{
+    error::Error,
+    fmt::{self, Write},
+    io::IsTerminal,
+};
+
+use clap::ArgEnum;
+use m6lexerkit::{Span, SrcFileInfo};
+use serde_json::json;
+
+use crate::{
+    ast_lowering::SemanticError, lexer::LexError, lint::LintError,
+    parser::ParseError,
+};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum ErrorFormat {
+    #[clap(name = "human")]
+    Human,
+    /// One JSON object per diagnostic per line
+    #[clap(name = "json")]
+    Json,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// Stable code like `E0201`
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    /// The first one is primary
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+}
+
+
+/// Diagnostics of one source file
+pub struct Diagnostics<'a> {
+    pub src: &'a SrcFileInfo,
+    pub diags: Vec<Diagnostic>,
+}
+
+
+impl Default for ErrorFormat {
+    fn default() -> Self {
+        Self::Human
+    }
+}
+
+
+impl fmt::Display for ErrorFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_possible_value().unwrap().get_name())
+    }
+}
+
+
+impl Severity {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Note => "note",
+        }
+    }
+
+    /// ANSI color
+    fn color(&self) -> &'static str {
+        match self {
+            Self::Error => "\x1b[1;31m",
+            Self::Warning => "\x1b[1;33m",
+            Self::Note => "\x1b[1;32m",
+        }
+    }
+}
+
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    fn new(
+        severity: Severity,
+        code: &'static str,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            code,
+            severity,
+            message: message.into(),
+            labels: vec![],
+            notes: vec![],
+            help: vec![],
+        }
+    }
+
+    pub fn with_primary(
+        mut self,
+        span: Span,
+        message: impl Into<String>,
+    ) -> Self {
+        self.labels.insert(
+            0,
+            Label {
+                span,
+                message: message.into(),
+                primary: true,
+            },
+        );
+        self
+    }
+
+    pub fn with_label(
+        mut self,
+        span: Span,
+        message: impl Into<String>,
+    ) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels.iter().find(|label| label.primary).map(|l| l.span)
+    }
+
+
+    ///////////////////////////////////////////////////////////////////////////
+    //// Human Renderer
+
+    pub fn render_human(&self, src: &SrcFileInfo, color: bool) -> String {
+        let paint = |style: &str, s: &str| {
+            if color {
+                format!("{style}{s}\x1b[0m")
+            } else {
+                s.to_owned()
+            }
+        };
+        const BLUE: &str = "\x1b[1;34m";
+        const BOLD: &str = "\x1b[1m";
+
+        let mut out = String::new();
+
+        let gutter_width = self
+            .labels
+            .iter()
+            .map(|label| src.boffset2srcloc(label.span.from).ln)
+            .max()
+            .unwrap_or(1)
+            .to_string()
+            .len();
+        let pad = " ".repeat(gutter_width);
+
+        let _ = writeln!(
+            out,
+            "{}{}",
+            paint(
+                self.severity.color(),
+                &format!("{}[{}]", self.severity.name(), self.code)
+            ),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+
+        if let Some(span) = self.primary_span() {
+            let loc = src.boffset2srcloc(span.from);
+            let _ = writeln!(
+                out,
+                "{pad}{} {}:{}:{}",
+                paint(BLUE, "-->"),
+                src.get_path().to_string_lossy(),
+                loc.ln,
+                loc.col
+            );
+        }
+
+        for label in self.labels.iter() {
+            let loc = src.boffset2srcloc(label.span.from);
+            let linestr = src.linestr(label.span.from).unwrap_or_default();
+            let linestr = linestr.trim_end_matches(['\n', '\r']);
+
+            let width = label
+                .span
+                .len()
+                .min(linestr.chars().count().saturating_sub(loc.col - 1))
+                .max(1);
+            let (mark, style) = if label.primary {
+                ("^", self.severity.color())
+            } else {
+                ("-", BLUE)
+            };
+
+            let _ = writeln!(out, "{pad} {}", paint(BLUE, "|"));
+            let _ = writeln!(
+                out,
+                "{} {linestr}",
+                paint(BLUE, &format!("{:>gutter_width$} |", loc.ln))
+            );
+            let underline =
+                format!("{} {}", mark.repeat(width), label.message);
+            let _ = writeln!(
+                out,
+                "{pad} {} {}{}",
+                paint(BLUE, "|"),
+                " ".repeat(loc.col - 1),
+                paint(style, underline.trim_end())
+            );
+        }
+
+        for note in self.notes.iter() {
+            let _ = writeln!(out, "{pad} {} note: {note}", paint(BLUE, "="));
+        }
+        for help in self.help.iter() {
+            let _ = writeln!(out, "{pad} {} help: {help}", paint(BLUE, "="));
+        }
+
+        out
+    }
+
+
+    ///////////////////////////////////////////////////////////////////////////
+    //// JSON Renderer
+
+    /// Single line JSON object, lines and columns are 1-based, end is
+    /// exclusive
+    pub fn render_json(&self, src: &SrcFileInfo) -> String {
+        let labels = self
+            .labels
+            .iter()
+            .map(|label| {
+                let start = src.boffset2srcloc(label.span.from);
+                let last = src.boffset2srcloc(
+                    label.span.end.max(label.span.from + 1) - 1,
+                );
+
+                json!({
+                    "message": label.message,
+                    "primary": label.primary,
+                    "byte_start": label.span.from,
+                    "byte_end": label.span.end,
+                    "line_start": start.ln,
+                    "column_start": start.col,
+                    "line_end": last.ln,
+                    "column_end": last.col + 1,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({
+            "code": self.code,
+            "severity": self.severity.name(),
+            "message": self.message,
+            "file": src.get_path().to_string_lossy(),
+            "labels": labels,
+            "notes": self.notes,
+            "help": self.help,
+        })
+        .to_string()
+    }
+}
+
+
+impl<'a> Diagnostics<'a> {
+    /// Extract diagnostics from compiler errors of front phases
+    pub fn from_error(err: &'a (dyn Error + 'static)) -> Option<Self> {
+        if let Some(err) = err.downcast_ref::<LexError>() {
+            Some(Self {
+                src: err.src(),
+                diags: err.diagnostics(),
+            })
+        } else if let Some(err) = err.downcast_ref::<ParseError>() {
+            Some(Self {
+                src: err.src(),
+                diags: err.diagnostics(),
+            })
+        } else if let Some(err) = err.downcast_ref::<SemanticError>() {
+            Some(Self {
+                src: err.src(),
+                diags: err.diagnostics(),
+            })
+        } else if let Some(err) = err.downcast_ref::<LintError>() {
+            Some(Self {
+                src: err.src(),
+                diags: err.diagnostics(),
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn render(&self, format: ErrorFormat, color: bool) -> String {
+        let mut out = String::new();
+
+        for diag in self.diags.iter() {
+            match format {
+                ErrorFormat::Human => {
+                    out.push_str(&diag.render_human(self.src, color));
+                    out.push('\n');
+                }
+                ErrorFormat::Json => {
+                    out.push_str(&diag.render_json(self.src));
+                    out.push('\n');
+                }
+            }
+        }
+
+        out
+    }
+}
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Function
+
+/// Print `err` to stderr in `format`, errors without diagnostics are
+/// reported as a plain message
+pub fn report(err: &(dyn Error + 'static), format: ErrorFormat) {
+    if let Some(diags) = Diagnostics::from_error(err) {
+        emit(&diags, format);
+        return;
+    }
+
+    match format {
+        ErrorFormat::Human => eprintln!("error: {err}"),
+        ErrorFormat::Json => eprintln!(
+            "{{\"code\":null,\"severity\":\"error\",\"message\":{}}}",
+            json_str(&err.to_string())
+        ),
+    }
+}
+
+
+/// Print diagnostics to stderr, colored if it's a terminal
+pub fn emit(diags: &Diagnostics, format: ErrorFormat) {
+    let color = std::io::stderr().is_terminal()
+        && std::env::var_os("NO_COLOR").is_none();
+
+    eprint!("{}", diags.render(format, color));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::{Diagnostic, Diagnostics, ErrorFormat};
+    use crate::driver::RunCompiler;
+
+    #[test]
+    fn test_dup_item_def() {
+        let err = RunCompiler::jit(
+            &"./examples/err_dup_fn.bath",
+            crate::config::OptLv::Debug,
+            &Default::default(),
+        )
+        .unwrap_err();
+        let diags = Diagnostics::from_error(&*err).unwrap();
+
+        assert_eq!(diags.diags.len(), 1);
+        assert_eq!(diags.diags[0].code, "E0201");
+        assert_eq!(diags.diags[0].labels.len(), 2);
+
+        let json = diags.render(ErrorFormat::Json, false);
+
+        assert!(json.contains(r#""code":"E0201""#));
+        assert!(json.contains(r#""message":"previous definition here""#));
+        assert!(json.contains(r#""line_start":2"#));
+    }
+
+    #[test]
+    fn test_render_json() {
+        let err = RunCompiler::jit(
+            &"./examples/err_dup_fn.bath",
+            crate::config::OptLv::Debug,
+            &Default::default(),
+        )
+        .unwrap_err();
+        let diags = Diagnostics::from_error(&*err).unwrap();
+
+        let diag = Diagnostic {
+            message: "a\"b\\c\n\x01".to_owned(),
+            ..diags.diags[0].clone()
+        };
+        let json: Value =
+            serde_json::from_str(&diag.render_json(diags.src)).unwrap();
+
+        assert_eq!(json["message"], "a\"b\\c\n\x01");
+        assert_eq!(json["labels"][0]["primary"], true);
+    }
+}
@@ -0,0 +1,255 @@
+//! Documentation pages of module exports
+//!
+//! Each exported function of `AModExp` is listed under its base name (so
+//! overloads share one section) with the demangled signature, attributes
+//! and the text of `##` comments above it.
+
+
+use std::{error::Error, fmt::Write, path::Path};
+
+use clap::ArgEnum;
+use indexmap::IndexMap;
+use m6lexerkit::{sym2str, SrcFileInfo, Symbol};
+
+use crate::{
+    ast_lowering::{AModExp, AnExtFnDec, ExtSymSet, SemanticAnalyzerPass1},
+    lexer::tokenize,
+    name_mangling::{base_name, signature},
+    parser::parse,
+};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum DocFormat {
+    #[clap(name = "md")]
+    Markdown,
+    #[clap(name = "html")]
+    Html,
+}
+
+
+/// Overloads of one base name
+struct DocItem<'a> {
+    name: Symbol,
+    decs: Vec<&'a AnExtFnDec>,
+}
+
+
+impl Default for DocFormat {
+    fn default() -> Self {
+        Self::Markdown
+    }
+}
+
+
+impl DocFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Html => "html",
+        }
+    }
+}
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Function
+
+/// Page of the module at `path`, titled by the file stem
+pub fn doc_file(
+    path: &Path,
+    format: DocFormat,
+) -> Result<String, Box<dyn Error>> {
+    let src = SrcFileInfo::new(&path)?;
+    let tokens = tokenize(&src)?;
+    let tt = parse(tokens, &src)?;
+
+    // signatures don't depend on other modules
+    let amod =
+        SemanticAnalyzerPass1::run(src, tt, ExtSymSet { mods: vec![] })?
+            .amod
+            .export();
+
+    let title = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    Ok(render(&title, &amod, format))
+}
+
+
+pub(crate) fn render(
+    title: &str,
+    amod: &AModExp,
+    format: DocFormat,
+) -> String {
+    let items = doc_items(amod);
+
+    match format {
+        DocFormat::Markdown => render_markdown(title, &items),
+        DocFormat::Html => render_html(title, &items),
+    }
+}
+
+
+/// Group by base name, sorted by it
+fn doc_items(amod: &AModExp) -> Vec<DocItem> {
+    let mut items: IndexMap<Symbol, Vec<&AnExtFnDec>> = IndexMap::new();
+
+    for dec in amod.afns.values() {
+        items.entry(base_name(dec.full_name)).or_default().push(dec);
+    }
+    items.sort_by(|name1, _, name2, _| sym2str(*name1).cmp(&sym2str(*name2)));
+
+    items
+        .into_iter()
+        .map(|(name, decs)| DocItem { name, decs })
+        .collect()
+}
+
+
+/// Attributes (one line) and signature
+fn dec_src(name: Symbol, dec: &AnExtFnDec) -> String {
+    let attrs = dec.attrs.to_src();
+    let sig = signature(name, &dec.params, &dec.ret);
+
+    if attrs.is_empty() {
+        sig
+    } else {
+        format!("{}\n{sig}", attrs.join(" "))
+    }
+}
+
+
+fn render_markdown(title: &str, items: &[DocItem]) -> String {
+    let mut out = String::new();
+
+    let _ = writeln!(out, "# {title}\n");
+
+    for item in items.iter() {
+        let name = sym2str(item.name);
+        let _ = writeln!(out, "- [`{name}`](#{name})");
+    }
+
+    for item in items.iter() {
+        let _ = writeln!(out, "\n## `{}`", sym2str(item.name));
+
+        for dec in item.decs.iter() {
+            let _ =
+                writeln!(out, "\n```bas\n{}\n```", dec_src(item.name, dec));
+
+            if let Some(ref doc) = dec.doc {
+                let _ = writeln!(out, "\n{doc}");
+            }
+        }
+    }
+
+    out
+}
+
+
+fn render_html(title: &str, items: &[DocItem]) -> String {
+    let mut out = String::new();
+    let title = escape_html(title);
+
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{title}</title>\n</head>\n<body>\n<h1>{title}</h1>"
+    );
+
+    out.push_str("<ul>\n");
+    for item in items.iter() {
+        let name = escape_html(&sym2str(item.name));
+        let _ = writeln!(
+            out,
+            "<li><a href=\"#{name}\"><code>{name}</code></a></li>"
+        );
+    }
+    out.push_str("</ul>\n");
+
+    for item in items.iter() {
+        let name = escape_html(&sym2str(item.name));
+        let _ = writeln!(out, "<h2 id=\"{name}\"><code>{name}</code></h2>");
+
+        for dec in item.decs.iter() {
+            let _ = writeln!(
+                out,
+                "<pre><code>{}</code></pre>",
+                escape_html(&dec_src(item.name, dec))
+            );
+
+            if let Some(ref doc) = dec.doc {
+                for para in doc.split("\n\n") {
+                    let _ = writeln!(out, "<p>{}</p>", escape_html(para));
+                }
+            }
+        }
+    }
+
+    out.push_str("</body>\n</html>\n");
+
+    out
+}
+
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{doc_file, escape_html, DocFormat};
+
+    #[test]
+    fn test_doc() -> Result<(), Box<dyn std::error::Error>> {
+        let path = PathBuf::from("./examples/doc_strs.bath");
+
+        let md = doc_file(&path, DocFormat::Markdown)?;
+
+        assert!(md.starts_with("# doc_strs\n"));
+        assert!(md.contains(
+            "```bas\n@no_mangle\nfn strlen(s: ptr) -> i32\n```\n\nLength in \
+             bytes\n"
+        ));
+        // overloads share one section
+        assert_eq!(md.matches("## `show`").count(), 1);
+        assert!(md.contains("Int version"));
+        assert!(md.contains("Float version\n\nSecond paragraph"));
+        assert!(md.contains("fn bare()\n```\n"));
+        assert!(!md.contains("not a doc"));
+
+        let html = doc_file(&path, DocFormat::Html)?;
+
+        assert!(html.contains("<h2 id=\"show\"><code>show</code></h2>"));
+        assert!(html.contains("<p>Second paragraph</p>"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html("<a & \"b\">"),
+            "&lt;a &amp; &quot;b&quot;&gt;"
+        );
+    }
+}
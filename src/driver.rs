@@ -1,17 +1,28 @@
-use std::{error::Error, path::Path};
+use std::{
+    error::Error,
+    fs, io,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use inkwellkit::config::{self, CompilerConfig};
 use m6lexerkit::SrcFileInfo;
 
 use crate::{
     ast_lowering::{
+        opt::optimize,
         Pass1Export, SemanticAnalyzerPass1,
         Pass2Export, SemanticAnalyzerPass2,
         AMod, AModExp, ExtSymSet, TokenTree2,
     },
     codegen::{CodeGen, CodeGenExport},
-    env::{ boostrap_dir, core_lib_path },
+    config::{
+        self, CompilerConfig, DiagConfig, EmitType, OptLv, PrintTy,
+        TargetSpec, TargetType,
+    },
+    dump::{dump_mir, dump_tokens, dump_tt},
+    env::{ core_lib_path, core_lib_stale, core_src_path },
     lexer::tokenize,
+    lint::emit_lints,
     parser::parse,
 };
 
@@ -33,7 +44,7 @@ impl RunCompiler {
         src: &P,
         config: CompilerConfig,
     ) -> Result<Self, Box<dyn Error>> {
-        let core = Self::boot()?;
+        let core = Self::boot(&config.target)?;
 
         // println!("core: {core:?}");
 
@@ -42,10 +53,10 @@ impl RunCompiler {
         let src = SrcFileInfo::new(src)?;
 
         let tokens = tokenize(&src)?;
+        dump(&config, EmitType::Tokens, || dump_tokens(&src, &tokens))?;
 
         let tt = parse(tokens, &src)?;
-
-        // println!("tt: {tt:#?}");
+        dump(&config, EmitType::TT, || dump_tt(&tt))?;
 
         let Pass1Export {
             src,
@@ -54,19 +65,107 @@ impl RunCompiler {
             ess,
         } = SemanticAnalyzerPass1::run(src, tt, ess)?;
 
-        let Pass2Export { amod, ess, .. } =
-            SemanticAnalyzerPass2::run(src, tt2, amod, ess)?;
+        let Pass2Export {
+            src,
+            mut amod,
+            ess,
+            lints,
+            ..
+        } = SemanticAnalyzerPass2::run(src, tt2, amod, ess)?;
+
+        emit_lints(&src, &amod, lints, &config.diag)?;
+        optimize(&mut amod);
+        dump(&config, EmitType::MIR, || dump_mir(&amod))?;
 
-        // println!("amod: {amod:#?}");
+        if config.emit_types.iter().all(EmitType::is_dump) {
+            return Ok(Self {});
+        }
 
-        CodeGen::run(amod, ess, config)?;
+        CodeGen::run(src, amod, ess, config)?;
 
         Ok(Self {})
     }
 
 
-    pub fn boot() -> Result<AModExp, Box<dyn Error>> {
-        let core_path = boostrap_dir().join("core.bath");
+    /// Compile `src` and run it in process through JIT without writing any
+    /// file, return the exit code of `main`.
+    pub fn jit<P: AsRef<Path>>(
+        src: &P,
+        optlv: OptLv,
+        diag: &DiagConfig,
+    ) -> Result<i32, Box<dyn Error>> {
+        let (core, core_dep) = Self::boot_jit()?;
+
+        let ess = ExtSymSet { mods: vec![core] };
+
+        let src = SrcFileInfo::new(src)?;
+
+        let tokens = tokenize(&src)?;
+
+        let tt = parse(tokens, &src)?;
+
+        let Pass1Export {
+            src,
+            tt2,
+            amod,
+            ess,
+        } = SemanticAnalyzerPass1::run(src, tt, ess)?;
+
+        let Pass2Export {
+            src,
+            mut amod,
+            ess,
+            lints,
+            ..
+        } = SemanticAnalyzerPass2::run(src, tt2, amod, ess)?;
+
+        emit_lints(&src, &amod, lints, diag)?;
+        optimize(&mut amod);
+
+        let code =
+            CodeGen::run_jit(src, amod, ess, vec![core_dep], optlv)?;
+
+        Ok(code)
+    }
+
+
+    /// Build `src` as executable into a temp dir and `run` it by the path,
+    /// the dir is removed after that.
+    pub fn with_exe<P: AsRef<Path>, T>(
+        src: &P,
+        config: CompilerConfig,
+        run: impl FnOnce(&Path) -> io::Result<T>,
+    ) -> Result<T, Box<dyn Error>> {
+        // concurrent builds of one process don't share the dir
+        static SEQ: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "bas-exe-{}-{}",
+            std::process::id(),
+            SEQ.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir)?;
+
+        let exe = dir.join(src.as_ref().file_stem().unwrap_or_default());
+
+        let config = CompilerConfig {
+            target_type: TargetType::Bin,
+            emit_types: vec![EmitType::Obj],
+            print_type: PrintTy::File(exe.clone()),
+            ..config
+        };
+        let res = Self::new(src, config)
+            .and_then(|_| run(&exe).map_err(Into::into));
+
+        let _ = fs::remove_dir_all(&dir);
+
+        res
+    }
+
+
+    /// Load core lib prebuilt for `target`
+    pub fn boot(target: &TargetSpec) -> Result<AModExp, Box<dyn Error>> {
+        let core_path = core_src_path()?;
         let core_src = SrcFileInfo::new(&core_path)?;
 
         let tokens = tokenize(&core_src)?;
@@ -85,42 +184,108 @@ impl RunCompiler {
             ExtSymSet { mods: vec![] },
         )?;
 
-        let CodeGenExport { amod, .. } = Q::core_lib(src, tt2, amod, ess)?;
+        let CodeGenExport { amod, .. } = Q::core_lib(src, tt2, amod, ess, target)?;
 
         Ok(amod.export())
     }
 }
 
 
+impl RunCompiler {
+    /// Like boot, but core lib is analyzed completely and kept for
+    /// generating in memory instead of reading from `core.o`
+    pub(crate) fn boot_jit(
+    ) -> Result<(AModExp, (AMod, ExtSymSet)), Box<dyn Error>> {
+        let core_path = core_src_path()?;
+        let core_src = SrcFileInfo::new(&core_path)?;
+
+        let tokens = tokenize(&core_src)?;
+        let tt = parse(tokens, &core_src)?;
+
+        let Pass1Export {
+            src,
+            tt2,
+            amod,
+            ess,
+        } = SemanticAnalyzerPass1::run(
+            core_src,
+            tt,
+            ExtSymSet { mods: vec![] },
+        )?;
+
+        let Pass2Export { amod, ess, .. } =
+            SemanticAnalyzerPass2::run(src, tt2, amod, ess)?;
+
+        Ok((amod.export(), (amod, ess)))
+    }
+}
+
+
+/// Write dump of `emit_type` if it's required
+fn dump(
+    config: &CompilerConfig,
+    emit_type: EmitType,
+    f: impl FnOnce() -> String,
+) -> io::Result<()> {
+    if !config.emit_types.contains(&emit_type) {
+        return Ok(());
+    }
+
+    let text = f();
+
+    match config.emit_path(emit_type) {
+        Some(path) => fs::write(path, text),
+        None => {
+            eprint!("{text}");
+            Ok(())
+        }
+    }
+}
+
+
 impl Query {
     pub fn core_lib(
         src: SrcFileInfo,
         tt2: TokenTree2,
         amod: AMod,
         ess: ExtSymSet,
+        target: &TargetSpec,
     ) -> Result<CodeGenExport, Box<dyn Error>> {
-        let core_lib_path = core_lib_path();
+        let core_lib_path = core_lib_path(target.triple.as_deref())?;
 
-        // FIXME 暂时这样检测
-        if core_lib_path.exists() {
+        if !core_lib_stale(target.triple.as_deref())? {
             return Ok(CodeGenExport { amod, ess });
         }
 
-        let Pass2Export { amod, ess, .. } =
-            SemanticAnalyzerPass2::run(src, tt2, amod, ess)?;
+        let Pass2Export {
+            src, mut amod, ess, ..
+        } = SemanticAnalyzerPass2::run(src, tt2, amod, ess)?;
+
+        optimize(&mut amod);
 
-        // println!("amod: {amod:#?}");
+        // cross target directory may not exist yet
+        if let Some(dir) = core_lib_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
 
         let config = CompilerConfig {
             optlv: config::OptLv::Opt3,
             target_type: config::TargetType::ReLoc,
-            // emit_type: config::EmitType::LLVMIR,
-            emit_type: config::EmitType::Obj,
+            // emit_types: vec![config::EmitType::LLVMIR],
+            // bitcode (core.bc) is used by LTO
+            emit_types: vec![config::EmitType::Obj, config::EmitType::BC],
+            ir_stage: config::IRStage::Post,
+            passes: Default::default(),
             print_type: config::PrintTy::File(core_lib_path),
             // print_type: config::PrintTy::StdErr,
+            target: target.clone(),
+            linker: Default::default(),
+            debug: false,
+            harness: None,
+            diag: Default::default(),
         };
 
-        let codegen_export = CodeGen::run(amod, ess, config)?;
+        let codegen_export = CodeGen::run(src, amod, ess, config)?;
 
         Ok(codegen_export)
     }
@@ -132,9 +297,22 @@ impl Query {
 mod tests {
     use super::RunCompiler;
 
+    #[test]
+    fn test_jit() -> Result<(), Box<dyn std::error::Error>> {
+        let code = RunCompiler::jit(
+            &"./examples/exp_jit.bath",
+            crate::config::OptLv::Debug,
+            &Default::default(),
+        )?;
+
+        assert_eq!(code, 7);
+
+        Ok(())
+    }
+
     #[test]
     fn test_boot() -> Result<(), Box<dyn std::error::Error>> {
-        let _core = RunCompiler::boot().unwrap();
+        let _core = RunCompiler::boot(&Default::default()).unwrap();
 
         Ok(())
     }
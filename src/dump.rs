@@ -0,0 +1,318 @@
+//! Readable dumps of compiler stages for `--emit tokens|tt|mir`
+//!
+//! Dumps don't depend on addresses or hash order, so they are stable
+//! between runs and fit snapshot tests.
+//!
+//! In MIR dump, implicit values (SSA) are `%N` of the scope, explicit
+//! variables are `name#tagid`, and a scope referenced by a value (block,
+//! branch, loop or function body) is listed nested under it. Owned values
+//! are listed at the end of their scope with the drop fn.
+
+
+use std::fmt::Write;
+
+use m6entry::KVEntry as Entry;
+use m6lexerkit::{sym2str, SrcFileInfo, Symbol, Token};
+
+use crate::{
+    ast_lowering::{AMod, AVal, ConstVal, MIRTy, MIR},
+    codegen::is_implicit_sym,
+    parser::{SN, TT},
+};
+
+
+const INDENT: &str = "    ";
+
+
+struct MirDumper<'a> {
+    amod: &'a AMod,
+    out: String,
+    depth: usize,
+}
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Function
+
+/// One token per line: `line:col name "value"`
+pub(crate) fn dump_tokens(src: &SrcFileInfo, tokens: &[Token]) -> String {
+    let mut out = String::new();
+
+    for tok in tokens.iter() {
+        let loc = src.boffset2srcloc(tok.span.from);
+
+        let _ = writeln!(
+            out,
+            "{:<8} {:<18} {:?}",
+            format!("{}:{}", loc.ln, loc.col),
+            tok.name_string(),
+            tok.value_string()
+        );
+    }
+
+    out
+}
+
+
+/// Syntax type of nodes indented by depth, tokens with their value
+pub(crate) fn dump_tt(tt: &TT) -> String {
+    let mut out = String::new();
+
+    dump_tt_(&mut out, tt, 0);
+
+    out
+}
+
+
+fn dump_tt_(out: &mut String, tt: &TT, depth: usize) {
+    let indent = INDENT.repeat(depth);
+
+    for (st, sn) in tt.subs.iter() {
+        match sn {
+            SN::T(sub) => {
+                let _ = writeln!(out, "{indent}{st:?}");
+                dump_tt_(out, sub, depth + 1);
+            }
+            SN::E(tok) => {
+                let _ =
+                    writeln!(out, "{indent}{st:?} {:?}", tok.value_string());
+            }
+        }
+    }
+}
+
+
+/// Functions of module with their scopes
+pub(crate) fn dump_mir(amod: &AMod) -> String {
+    let mut dumper = MirDumper {
+        amod,
+        out: String::new(),
+        depth: 0,
+    };
+
+    dumper.dump_scope_body(0);
+
+    dumper.out
+}
+
+
+fn sym_str(sym: Symbol) -> String {
+    let s = sym2str(sym);
+
+    if is_implicit_sym(sym) {
+        format!("%{}", s.trim_start_matches("!__tmp_"))
+    } else {
+        s
+    }
+}
+
+
+/// Value and the nested scopes it references
+fn val_str(val: &AVal) -> (String, Vec<usize>) {
+    match val {
+        AVal::DefFn { name, scope_idx } => {
+            (format!("fn {}", sym2str(*name)), vec![*scope_idx])
+        }
+        AVal::IfBlock { if_exprs, else_blk } => {
+            let mut s = if_exprs
+                .iter()
+                .map(|(cond, blk)| {
+                    format!("if {} scope {blk}", sym_str(*cond))
+                })
+                .collect::<Vec<_>>()
+                .join(" else ");
+            let mut scopes =
+                if_exprs.iter().map(|(_, blk)| *blk).collect::<Vec<_>>();
+
+            if let Some(blk) = else_blk {
+                let _ = write!(s, " else scope {blk}");
+                scopes.push(*blk);
+            }
+
+            (s, scopes)
+        }
+        AVal::InfiLoopExpr(blk) => (format!("loop scope {blk}"), vec![*blk]),
+        AVal::BlockExpr(blk) => (format!("block scope {blk}"), vec![*blk]),
+        AVal::FnParam(idx) => (format!("param {idx}"), vec![]),
+        AVal::FnCall { call_fn, args } => (
+            format!(
+                "call {}({})",
+                sym2str(*call_fn),
+                args.iter()
+                    .map(|arg| sym_str(*arg))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            vec![],
+        ),
+        AVal::BOpExpr { op, operands } => (
+            format!("{op:?} {}, {}", sym_str(operands.0), sym_str(operands.1)),
+            vec![],
+        ),
+        AVal::TypeCast { name, ty, .. } => (
+            format!("cast {} as {}", sym_str(*name), ty.ident_name()),
+            vec![],
+        ),
+        AVal::ConstAlias(val) => {
+            let val = match val {
+                ConstVal::Int(int) => int.to_string(),
+                ConstVal::Float(float) => format!("{float:?}"),
+                ConstVal::Str(sym) => format!("{:?}", sym2str(*sym)),
+                ConstVal::Bool(bool) => bool.to_string(),
+            };
+
+            (format!("const {val}"), vec![])
+        }
+        AVal::Var(sym, tagid) => {
+            (format!("{}#{tagid}", sym2str(*sym)), vec![])
+        }
+        AVal::Assign(sym, tagid, val) => (
+            format!("assign {}#{tagid}, {}", sym2str(*sym), sym_str(*val)),
+            vec![],
+        ),
+        AVal::Break => ("break".to_owned(), vec![]),
+        AVal::Continue => ("continue".to_owned(), vec![]),
+        AVal::Return(Some(val)) => (format!("ret {}", sym_str(*val)), vec![]),
+        AVal::Return(None) => ("ret".to_owned(), vec![]),
+        AVal::PH => ("()".to_owned(), vec![]),
+    }
+}
+
+
+
+impl<'a> MirDumper<'a> {
+    fn line(&mut self, s: &str) {
+        let _ = writeln!(self.out, "{}{s}", INDENT.repeat(self.depth));
+    }
+
+    fn dump_scope(&mut self, idx: usize) {
+        self.line(&format!("scope {idx} {{"));
+        self.depth += 1;
+        self.dump_scope_body(idx);
+        self.depth -= 1;
+        self.line("}");
+    }
+
+    fn dump_scope_body(&mut self, idx: usize) {
+        let scope = &self.amod.scopes[idx];
+
+        for Entry(sym, (tagid, var)) in scope.explicit_bindings.iter() {
+            self.line(&format!(
+                "let {}#{tagid}: {}",
+                sym2str(*sym),
+                var.ty.ident_name()
+            ));
+        }
+
+        for mir in scope.mirs.iter() {
+            self.dump_mir(mir);
+        }
+
+        if !matches!(scope.tail.val, AVal::PH) {
+            self.line(&format!(
+                "tail {}: {}",
+                val_str(&scope.tail.val).0,
+                scope.tail.ty.ident_name()
+            ));
+        }
+
+        for (sym, drop_fn) in scope.owned.iter() {
+            self.line(&format!(
+                "drop {} by {}",
+                sym_str(*sym),
+                sym2str(*drop_fn)
+            ));
+        }
+    }
+
+    fn dump_mir(&mut self, mir: &MIR) {
+        let (val, scopes) = val_str(&mir.val);
+
+        if let AVal::DefFn { name, .. } = mir.val {
+            let head = match self.amod.afns.get(&name) {
+                Some(afn) => format!(
+                    "fn {}({}) -> {}",
+                    sym2str(name),
+                    afn.params
+                        .iter()
+                        .map(|param| format!(
+                            "{}: {}",
+                            sym2str(param.formal),
+                            param.ty.ident_name()
+                        ))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    afn.ret.ident_name()
+                ),
+                None => val,
+            };
+
+            self.line(&head);
+        } else {
+            let ty = mir.ty.ident_name();
+
+            match mir.mirty {
+                MIRTy::ValBind => {
+                    self.line(&format!("{}: {ty} = {val}", sym_str(mir.name)))
+                }
+                MIRTy::VarAssign => self.line(&format!(
+                    "{}#{}: {ty} <- {val}",
+                    sym2str(mir.name),
+                    mir.tagid.unwrap_or_default()
+                )),
+            }
+        }
+
+        self.depth += 1;
+        for idx in scopes {
+            self.dump_scope(idx);
+        }
+        self.depth -= 1;
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use m6lexerkit::SrcFileInfo;
+
+    use super::{dump_mir, dump_tokens, dump_tt};
+    use crate::{
+        ast_lowering::{lower_src, Pass2Export},
+        lexer::tokenize,
+        parser::parse,
+    };
+
+    #[test]
+    fn test_dump() -> Result<(), Box<dyn std::error::Error>> {
+        let src = SrcFileInfo::new(&"./examples/dump.bath")?;
+        let tokens = tokenize(&src)?;
+
+        let tokens_dump = dump_tokens(&src, &tokens);
+        // comment isn't a token
+        assert!(tokens_dump.starts_with("2:1"));
+        assert_eq!(tokens_dump.lines().count(), tokens.len());
+
+        let tt = parse(tokens, &src)?;
+
+        let tt_dump = dump_tt(&tt);
+        assert!(tt_dump.contains("Function\n"));
+        assert!(tt_dump.contains("IfExpr\n"));
+
+        let Pass2Export { amod, .. } =
+            lower_src("./examples/dump.bath", false)?;
+
+        let mir_dump = dump_mir(&amod);
+
+        assert!(mir_dump.starts_with("fn main() -> i32\n    scope "));
+        assert!(mir_dump.contains("let a#0: i32\n"));
+        assert!(mir_dump.contains("a#0: i32 <- "));
+        assert!(mir_dump.contains(" = if %"));
+        // dump is stable
+        assert_eq!(dump_mir(&amod), mir_dump);
+
+        Ok(())
+    }
+}
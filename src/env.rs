@@ -1,33 +1,253 @@
-use std::{env, fs, path::{Path, PathBuf}};
+use std::{
+    env,
+    error::Error,
+    fmt, fs,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+};
+
+
+/// Core lib source and prebuilt runtime embedded into the compiler
+const CORE_BATH: &str = include_str!("../boostrap/core.bath");
+const LIBBAS_A: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/libbas.a"));
+const LIBBAS_SO: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/libbas.so"));
+
+
+#[derive(Debug, Clone)]
+pub struct EnvError(String);
+
+pub type EnvResult<T> = Result<T, EnvError>;
+
+
+impl fmt::Display for EnvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl Error for EnvError {}
+
 
 
 /// BareLang installed home
-#[inline]
-pub fn bas_home() -> PathBuf {
-    let bas_home_str
-    = env::var("BAS_HOME").unwrap_or(".".to_string());
+///
+/// `BAS_HOME` if it's set, or else the per-user cache directory where the
+/// embedded core lib and runtime are materialized on first use.
+pub fn bas_home() -> EnvResult<PathBuf> {
+    static BAS_HOME: OnceLock<EnvResult<PathBuf>> = OnceLock::new();
+
+    BAS_HOME
+        .get_or_init(|| match env::var("BAS_HOME") {
+            Ok(bas_home_str) => fs::canonicalize(Path::new(&bas_home_str))
+                .map_err(|err| {
+                    EnvError(format!(
+                        "BAS_HOME {:?} is unavailable: {}",
+                        bas_home_str, err
+                    ))
+                }),
+            Err(_) => {
+                let dir = cache_dir()?;
+                materialize(&dir)?;
+                Ok(dir)
+            }
+        })
+        .clone()
+}
+
+
+/// `$XDG_CACHE_HOME/bas/<version>` or `~/.cache/bas/<version>`
+fn cache_dir() -> EnvResult<PathBuf> {
+    let cache_home = match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".cache"),
+            None => {
+                return Err(EnvError(
+                    "Neither BAS_HOME nor HOME is set".to_owned(),
+                ))
+            }
+        },
+    };
+
+    Ok(cache_home.join("bas").join(env!("CARGO_PKG_VERSION")))
+}
+
+
+/// Write embedded files into `dir` if they are missing or stale
+fn materialize(dir: &Path) -> EnvResult<()> {
+    let io_err = |path: &Path, err: std::io::Error| {
+        EnvError(format!("Materialize {:?} failed: {}", path, err))
+    };
+
+    let core_bath = dir.join("boostrap").join("core.bath");
+
+    fs::create_dir_all(core_bath.parent().unwrap())
+        .map_err(|err| io_err(dir, err))?;
+
+    // a newer core.bath makes prebuilt core lib of every target stale
+    write_if_changed(&core_bath, CORE_BATH.as_bytes())
+        .map_err(|err| io_err(&core_bath, err))?;
+
+    for (name, bytes) in [("libbas.a", LIBBAS_A), ("libbas.so", LIBBAS_SO)] {
+        let path = dir.join(name);
+
+        write_if_changed(&path, bytes).map_err(|err| io_err(&path, err))?;
+    }
+
+    Ok(())
+}
+
+
+/// Return true if it's written.
+///
+/// It's written into a temp file of the same directory and renamed into
+/// place, so that other compilers never see a partial one.
+fn write_if_changed(path: &Path, bytes: &[u8]) -> std::io::Result<bool> {
+    static CNT: AtomicUsize = AtomicUsize::new(0);
+
+    if let Ok(old) = fs::read(path) && old == bytes {
+        return Ok(false);
+    }
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(
+        ".{}-{}.tmp",
+        process::id(),
+        CNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp = PathBuf::from(tmp);
+
+    if let Err(err) =
+        fs::write(&tmp, bytes).and_then(|_| fs::rename(&tmp, path))
+    {
+        let _ = fs::remove_file(&tmp);
+        return Err(err);
+    }
+
+    Ok(true)
+}
 
-    fs::canonicalize(Path::new(
-        &bas_home_str
-    )).unwrap()
+
+/// Directory of prebuilt runtime for `triple`, host target uses bas home
+/// directly, others use `targets/<triple>`
+#[inline]
+pub fn target_dir(triple: Option<&str>) -> EnvResult<PathBuf> {
+    Ok(match triple {
+        Some(triple) => bas_home()?.join("targets").join(triple),
+        None => bas_home()?,
+    })
 }
 
 
 /// Staticlib
 #[inline]
-pub fn libbas_o_path() -> PathBuf {
-    bas_home().join("libbas.a")
+pub fn libbas_o_path(triple: Option<&str>) -> EnvResult<PathBuf> {
+    Ok(target_dir(triple)?.join("libbas.a"))
+}
+
+
+/// Shared object version of staticlib, used by JIT
+#[inline]
+pub fn libbas_so_path() -> EnvResult<PathBuf> {
+    Ok(bas_home()?.join("libbas.so"))
+}
+
+
+#[inline]
+pub fn boostrap_dir() -> EnvResult<PathBuf> {
+    Ok(bas_home()?.join("boostrap"))
+}
+
+
+/// Source of core lib, check existence for `BAS_HOME` override
+pub fn core_src_path() -> EnvResult<PathBuf> {
+    let path = boostrap_dir()?.join("core.bath");
+
+    if !path.exists() {
+        return Err(EnvError(format!(
+            "Core lib source {:?} not found, check BAS_HOME",
+            path
+        )));
+    }
+
+    Ok(path)
 }
 
 
 #[inline]
-pub fn boostrap_dir() -> PathBuf {
-    bas_home().join("boostrap")
+pub fn core_lib_path(triple: Option<&str>) -> EnvResult<PathBuf> {
+    Ok(target_dir(triple)?.join("core.o"))
 }
 
 
+/// Bitcode of core lib, merged into module by LTO
 #[inline]
-pub fn core_lib_path() -> PathBuf {
-    bas_home().join("core.o")
+pub fn core_bc_path(triple: Option<&str>) -> EnvResult<PathBuf> {
+    Ok(target_dir(triple)?.join("core.bc"))
+}
+
+
+/// Prebuilt core lib (object and bitcode) of `triple` is missing or older
+/// than its source
+pub fn core_lib_stale(triple: Option<&str>) -> EnvResult<bool> {
+    let modified =
+        |path: &Path| fs::metadata(path).and_then(|meta| meta.modified());
+
+    let Ok(src) = modified(&core_src_path()?) else {
+        return Ok(true);
+    };
+
+    for path in [core_lib_path(triple)?, core_bc_path(triple)?] {
+        match modified(&path) {
+            Ok(built) if built >= src => (),
+            _ => return Ok(true),
+        }
+    }
+
+    Ok(false)
 }
 
+
+
+#[cfg(test)]
+mod tests {
+    use std::{env, error::Error, fs, process};
+
+    use super::{materialize, write_if_changed, CORE_BATH, LIBBAS_A};
+
+    #[test]
+    fn test_materialize() -> Result<(), Box<dyn Error>> {
+        let dir = env::temp_dir().join(format!("bas-home-{}", process::id()));
+
+        materialize(&dir)?;
+
+        assert_eq!(fs::read(dir.join("libbas.a"))?, LIBBAS_A);
+        assert_eq!(
+            fs::read_to_string(dir.join("boostrap").join("core.bath"))?,
+            CORE_BATH
+        );
+
+        // no temp file is left
+        let mut names = fs::read_dir(&dir)?
+            .map(|entry| Ok(entry?.file_name().into_string().unwrap()))
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        names.sort();
+        assert_eq!(names, ["boostrap", "libbas.a", "libbas.so"]);
+
+        // stale one is replaced, the same one is kept
+        let path = dir.join("libbas.a");
+        assert!(write_if_changed(&path, b"stale")?);
+        materialize(&dir)?;
+        assert_eq!(fs::read(&path)?, LIBBAS_A);
+        assert!(!write_if_changed(&path, LIBBAS_A)?);
+
+        fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+}
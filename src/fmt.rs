@@ -0,0 +1,719 @@
+//! Source formatter
+//!
+//! Layout follows the `TokenTree` of parser, the text of each token is taken
+//! from source verbatim, comments and blank lines are from the trivia of
+//! tokens.
+//!
+//! A construct is printed on one line if it fits in `MAX_WIDTH`, otherwise:
+//!
+//! - function parameters are put one per line
+//!
+//! - call arguments are put one per line
+//!
+//! - a binary expression breaks before the operator (after it for
+//!   assignments) with the rest indented
+
+
+use std::{collections::HashMap, error::Error, path::Path};
+
+use m6lexerkit::{SrcFileInfo, Token};
+
+use crate::{
+    lexer::{tokenize_with_trivia, Trivia, TriviaToken},
+    parser::{parse, SN, ST, TT},
+};
+
+
+pub const MAX_WIDTH: usize = 79;
+const INDENT: &str = "    ";
+
+
+struct Formatter<'a> {
+    srcstr: &'a str,
+    /// Indexed by the start of token
+    trivia: HashMap<usize, &'a TriviaToken>,
+    out: String,
+    indent: usize,
+    /// Trailing comment has been written, next token goes to a new line
+    pending_break: bool,
+    /// Don't break lines in trying to fit
+    flat: bool,
+}
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Function
+
+/// Formatted text of source file
+pub fn format_src(src: &SrcFileInfo) -> Result<String, Box<dyn Error>> {
+    let (trivia_toks, eof_trivia) = tokenize_with_trivia(src)?;
+    let tt = parse(trivia_toks.iter().map(|tt| tt.tok).collect(), src)?;
+
+    let mut formatter = Formatter {
+        srcstr: src.get_srcstr(),
+        trivia: trivia_toks
+            .iter()
+            .map(|tt| (tt.tok.span.from, tt))
+            .collect(),
+        out: String::new(),
+        indent: 0,
+        pending_break: false,
+        flat: false,
+    };
+
+    formatter.fmt_module(&tt, &eof_trivia);
+
+    Ok(formatter.out)
+}
+
+
+/// Formatted text if it differs from the file
+pub fn format_file(path: &Path) -> Result<Option<String>, Box<dyn Error>> {
+    let src = SrcFileInfo::new(&path)?;
+    let formatted = format_src(&src)?;
+
+    Ok((formatted != src.get_srcstr()).then_some(formatted))
+}
+
+
+
+impl<'a> Formatter<'a> {
+    ///////////////////////////////////////////////////////////////////////////
+    //// Layout
+
+    fn fmt_module(&mut self, tt: &TT, eof_trivia: &[Trivia]) {
+        for (i, (st, sn)) in tt.subs.iter().enumerate() {
+            debug_assert_eq!(*st, ST::Item);
+
+            if i > 0 {
+                self.newline();
+                self.newline();
+            }
+            self.fmt_item(sn.as_tt());
+        }
+
+        if !eof_trivia.is_empty() {
+            if !self.out.is_empty() {
+                self.newline();
+            }
+            self.line_trivia(eof_trivia);
+        }
+
+        self.out.truncate(self.out.trim_end().len());
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+    }
+
+    fn fmt_item(&mut self, tt: &TT) {
+        for (st, sn) in tt.subs.iter() {
+            match st {
+                ST::Attrs => self.fmt_attrs(sn.as_tt()),
+                ST::Function => self.fmt_fn(sn.as_tt()),
+                _ => unreachable!("{st:?}"),
+            }
+        }
+    }
+
+    /// One attribute per line
+    fn fmt_attrs(&mut self, tt: &TT) {
+        for (st, sn) in tt.subs.iter() {
+            match st {
+                ST::attr => {
+                    if !self.at_line_start() {
+                        self.newline();
+                    }
+                    self.token(sn.as_tok());
+                }
+                ST::AttrArgs => self.fmt_list(sn.as_tt()),
+                _ => unreachable!("{st:?}"),
+            }
+        }
+
+        self.newline();
+    }
+
+    fn fmt_fn(&mut self, tt: &TT) {
+        let mut p = 0;
+
+        self.token(tt[p].1.as_tok()); // fn
+        self.space();
+        self.token(tt[p + 1].1.as_tok()); // name
+        p += 2;
+
+        let sig = &tt.subs[p..];
+        let reserve = if tt.subs.last().unwrap().0 == ST::semi {
+            1
+        } else {
+            2
+        };
+
+        if !self.try_fit(reserve, |f| f.fmt_sig(sig, false)) {
+            self.fmt_sig(sig, true);
+        }
+
+        while tt[p].0 != ST::BlockExpr && tt[p].0 != ST::semi {
+            p += 1;
+        }
+
+        if tt[p].0 == ST::semi {
+            self.token(tt[p].1.as_tok());
+        } else {
+            self.space();
+            self.fmt_block(tt[p].1.as_tt());
+        }
+    }
+
+    /// `(params) -> ty`
+    fn fmt_sig(&mut self, sig: &[(ST, SN)], broken: bool) {
+        for (st, sn) in sig.iter() {
+            match st {
+                ST::lparen => self.token(sn.as_tok()),
+                ST::FnParams => {
+                    if broken && !sn.as_tt().subs.is_empty() {
+                        self.indent += 1;
+                        self.newline();
+                        self.fmt_params(sn.as_tt(), true);
+                        self.indent -= 1;
+                        self.newline();
+                    } else {
+                        self.fmt_params(sn.as_tt(), false);
+                    }
+                }
+                ST::rparen => self.token(sn.as_tok()),
+                ST::rarrow => {
+                    self.space();
+                    self.token(sn.as_tok());
+                    self.space();
+                }
+                ST::Type => self.fmt_ty(sn.as_tt()),
+                _ => break,
+            }
+        }
+    }
+
+    fn fmt_params(&mut self, tt: &TT, broken: bool) {
+        for (st, sn) in tt.subs.iter() {
+            match st {
+                ST::FnParam => {
+                    let param = sn.as_tt();
+
+                    for (st, sn) in param.subs.iter() {
+                        match st {
+                            ST::PatNoTop => self.fmt_tokens(sn.as_tt()),
+                            ST::colon => {
+                                self.token(sn.as_tok());
+                                self.space();
+                            }
+                            ST::Type => self.fmt_ty(sn.as_tt()),
+                            _ => unreachable!("{st:?}"),
+                        }
+                    }
+                }
+                ST::comma => {
+                    self.token(sn.as_tok());
+
+                    if broken {
+                        self.newline();
+                    } else {
+                        self.space();
+                    }
+                }
+                _ => unreachable!("{st:?}"),
+            }
+        }
+    }
+
+    /// `(a, b)`
+    fn fmt_list(&mut self, tt: &TT) {
+        for (st, sn) in tt.subs.iter() {
+            self.token(sn.as_tok());
+
+            if *st == ST::comma {
+                self.space();
+            }
+        }
+    }
+
+    fn fmt_ty(&mut self, tt: &TT) {
+        self.fmt_tokens(tt)
+    }
+
+    fn fmt_block(&mut self, tt: &TT) {
+        let lbrace = tt[0].1.as_tok();
+        let stmts = tt[1].1.as_tt();
+        let rbrace = tt[2].1.as_tok();
+        let rbrace_trivia: &'a [Trivia] =
+            &self.trivia[&rbrace.span.from].leading;
+
+        let has_comment = rbrace_trivia
+            .iter()
+            .any(|trivia| matches!(trivia, Trivia::Comment(..)));
+
+        self.token(lbrace);
+
+        if stmts.subs.is_empty() && !has_comment {
+            self.token_(rbrace, false);
+            return;
+        }
+
+        let flat = self.flat;
+        self.flat = false;
+        self.indent += 1;
+
+        for (st, sn) in stmts.subs.iter() {
+            self.newline();
+
+            match st {
+                ST::Stmt => self.fmt_stmt(sn.as_tt()),
+                ST::Expr => self.fmt_expr(sn.as_tt(), 0),
+                _ => unreachable!("{st:?}"),
+            }
+        }
+
+        if has_comment {
+            self.newline();
+            self.line_trivia(rbrace_trivia);
+        }
+
+        self.indent -= 1;
+        self.line_break();
+        self.token_(rbrace, false);
+        self.flat = flat;
+    }
+
+    fn fmt_stmt(&mut self, tt: &TT) {
+        for (st, sn) in tt.subs.iter() {
+            match st {
+                ST::r#let => {
+                    self.token(sn.as_tok());
+                    self.space();
+                }
+                ST::PatNoTop => self.fmt_tokens(sn.as_tt()),
+                ST::colon => {
+                    self.token(sn.as_tok());
+                    self.space();
+                }
+                ST::Type => self.fmt_ty(sn.as_tt()),
+                ST::assign => {
+                    self.space();
+                    self.token(sn.as_tok());
+                    self.space();
+                }
+                ST::Expr => self.fmt_expr(sn.as_tt(), 1),
+                ST::semi => self.token(sn.as_tok()),
+                _ => unreachable!("{st:?}"),
+            }
+        }
+    }
+
+    /// `reserve`: width of the text following on the same line
+    fn fmt_expr(&mut self, tt: &TT, reserve: usize) {
+        if tt.subs.len() == 3 {
+            self.fmt_bop_expr(tt, reserve);
+            return;
+        }
+
+        let (st, sn) = &tt[0];
+
+        match st {
+            ST::Expr => self.fmt_expr(sn.as_tt(), reserve),
+            ST::FunCallExpr => self.fmt_funcall_expr(sn.as_tt(), reserve),
+            ST::GroupedExpr => {
+                let grouped = sn.as_tt();
+
+                self.token(grouped[0].1.as_tok());
+                self.fmt_expr(grouped[1].1.as_tt(), reserve + 1);
+                self.token(grouped[2].1.as_tok());
+            }
+            ST::BlockExpr => self.fmt_block(sn.as_tt()),
+            ST::IfExpr => self.fmt_if_expr(sn.as_tt()),
+            ST::InfiLoopExpr => {
+                let tt = sn.as_tt();
+
+                self.token(tt[0].1.as_tok());
+                self.space();
+                self.fmt_block(tt[1].1.as_tt());
+            }
+            ST::ReturnExpr => {
+                let tt = sn.as_tt();
+
+                self.token(tt[0].1.as_tok());
+
+                if tt.subs.len() > 1 {
+                    self.space();
+                    self.fmt_expr(tt[1].1.as_tt(), reserve);
+                }
+            }
+            ST::PathExpr => self.fmt_path_expr(sn.as_tt()),
+            ST::LitExpr
+            | ST::CmdExpr
+            | ST::SideEffectExpr
+            | ST::BreakExpr
+            | ST::ContinueExpr => self.fmt_tokens(sn.as_tt()),
+            _ => unreachable!("{st:?}"),
+        }
+    }
+
+    fn fmt_bop_expr(&mut self, tt: &TT, reserve: usize) {
+        let lhs = tt[0].1.as_tt();
+        let (bop, bop_sn) = &tt[1];
+        let rhs = tt[2].1.as_tt();
+
+        let flat = |f: &mut Self| {
+            f.fmt_expr(lhs, 0);
+            f.space();
+            f.token(bop_sn.as_tok());
+            f.space();
+            f.fmt_expr(rhs, reserve);
+        };
+
+        if self.flat {
+            flat(self);
+            return;
+        }
+        if self.try_fit(reserve, flat) {
+            return;
+        }
+
+        let is_assign = bop.name().ends_with("assign");
+
+        if is_assign {
+            self.fmt_expr(lhs, 0);
+            self.space();
+            self.token(bop_sn.as_tok());
+        } else {
+            self.fmt_expr(lhs, 0);
+        }
+
+        self.indent += 1;
+        self.newline();
+
+        if !is_assign {
+            self.token(bop_sn.as_tok());
+            self.space();
+        }
+        self.fmt_expr(rhs, reserve);
+        self.indent -= 1;
+    }
+
+    fn fmt_funcall_expr(&mut self, tt: &TT, reserve: usize) {
+        self.fmt_path_expr(tt[0].1.as_tt());
+
+        let args = tt[1].1.as_tt();
+
+        let flat = |f: &mut Self| f.fmt_call_args(args, reserve, false);
+
+        if self.flat || args.subs.len() == 2 {
+            flat(self);
+            return;
+        }
+        if self.try_fit(reserve, flat) {
+            return;
+        }
+
+        self.fmt_call_args(args, reserve, true);
+    }
+
+    /// `(a, b)`, or one argument per line if `broken`
+    fn fmt_call_args(&mut self, tt: &TT, reserve: usize, broken: bool) {
+        for (st, sn) in tt.subs.iter() {
+            match st {
+                ST::lparen => {
+                    self.token(sn.as_tok());
+
+                    if broken {
+                        self.indent += 1;
+                        self.newline();
+                    }
+                }
+                ST::Expr if broken => self.fmt_expr(sn.as_tt(), 1),
+                ST::Expr => self.fmt_expr(sn.as_tt(), reserve + 1),
+                ST::comma => {
+                    self.token(sn.as_tok());
+
+                    if broken {
+                        self.newline();
+                    } else {
+                        self.space();
+                    }
+                }
+                ST::rparen => {
+                    if broken {
+                        self.indent -= 1;
+                        self.newline();
+                    }
+
+                    self.token(sn.as_tok());
+                }
+                _ => unreachable!("{st:?}"),
+            }
+        }
+    }
+
+    fn fmt_if_expr(&mut self, tt: &TT) {
+        for (st, sn) in tt.subs.iter() {
+            match st {
+                ST::r#if => {
+                    self.token(sn.as_tok());
+                    self.space();
+                }
+                ST::Expr => {
+                    self.fmt_expr(sn.as_tt(), 2);
+                    self.space();
+                }
+                ST::BlockExpr => self.fmt_block(sn.as_tt()),
+                ST::r#else => {
+                    self.space();
+                    self.token(sn.as_tok());
+                    self.space();
+                }
+                ST::IfExpr => self.fmt_if_expr(sn.as_tt()),
+                _ => unreachable!("{st:?}"),
+            }
+        }
+    }
+
+    /// `tag#seg::seg`
+    fn fmt_path_expr(&mut self, tt: &TT) {
+        for (i, (st, sn)) in tt.subs.iter().enumerate() {
+            match st {
+                ST::tag => self.token(sn.as_tok()),
+                ST::PathExprSeg => {
+                    if i > 0 && tt[i - 1].0 == ST::PathExprSeg {
+                        self.out.push_str("::");
+                    }
+                    self.fmt_tokens(sn.as_tt());
+                }
+                _ => unreachable!("{st:?}"),
+            }
+        }
+    }
+
+    /// Tokens without space between them
+    fn fmt_tokens(&mut self, tt: &TT) {
+        for (_st, sn) in tt.subs.iter() {
+            match sn {
+                SN::E(tok) => self.token(tok),
+                SN::T(tt) => self.fmt_tokens(tt),
+            }
+        }
+    }
+
+
+    ///////////////////////////////////////////////////////////////////////////
+    //// Output
+
+    fn token(&mut self, tok: &Token) {
+        self.token_(tok, true)
+    }
+
+    /// Write the source text of `tok` with its comments
+    fn token_(&mut self, tok: &Token, with_leading: bool) {
+        let trivia_tok = self.trivia[&tok.span.from];
+
+        if with_leading && !trivia_tok.leading.is_empty() {
+            if self.at_line_start() {
+                self.line_trivia(&trivia_tok.leading);
+            } else if trivia_tok
+                .leading
+                .iter()
+                .any(|trivia| matches!(trivia, Trivia::Comment(..)))
+            {
+                self.indent += 1;
+                self.newline();
+                self.line_trivia(&trivia_tok.leading);
+                self.indent -= 1;
+            }
+        }
+
+        if self.pending_break {
+            self.indent += 1;
+            self.newline();
+            self.indent -= 1;
+        }
+
+        self.out
+            .push_str(&self.srcstr[tok.span.from..tok.span.end]);
+
+        if let Some(comment) = &trivia_tok.trailing {
+            self.out.push_str("  ");
+            self.out.push_str(comment);
+            self.pending_break = true;
+        }
+    }
+
+    /// Comments each on its own line starting from line start, at most one
+    /// blank line is kept and none at the start of block.
+    fn line_trivia(&mut self, trivia: &[Trivia]) {
+        for trivia in trivia.iter() {
+            match trivia {
+                Trivia::Comment(comment) => {
+                    self.out.push_str(comment);
+                    self.newline();
+                }
+                Trivia::BlankLine => {
+                    let prev = self.out.trim_end_matches(' ');
+
+                    if !(prev.is_empty()
+                        || prev.ends_with("\n\n")
+                        || prev.ends_with("{\n"))
+                    {
+                        self.newline();
+                    }
+                }
+            }
+        }
+    }
+
+    fn space(&mut self) {
+        if !self.at_line_start() {
+            self.out.push(' ');
+        }
+    }
+
+    fn newline(&mut self) {
+        self.trim_line_end();
+        self.out.push('\n');
+        self.out.push_str(&INDENT.repeat(self.indent));
+        self.pending_break = false;
+    }
+
+    /// Newline or re-indent the empty current line
+    fn line_break(&mut self) {
+        if self.at_line_start() && !self.out.is_empty() {
+            let line_start = self.out.rfind('\n').map_or(0, |n| n + 1);
+
+            self.out.truncate(line_start);
+            self.out.push_str(&INDENT.repeat(self.indent));
+        } else {
+            self.newline();
+        }
+    }
+
+    fn trim_line_end(&mut self) {
+        let len = self.out.trim_end_matches(' ').len();
+        self.out.truncate(len);
+    }
+
+    fn cur_line(&self) -> &str {
+        self.out.rsplit('\n').next().unwrap_or_default()
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.cur_line().trim_start().is_empty()
+    }
+
+    /// Write through `f` without breaking lines, roll back if the first or
+    /// the last line (plus `reserve`) exceeds `MAX_WIDTH`.
+    fn try_fit(&mut self, reserve: usize, f: impl FnOnce(&mut Self)) -> bool {
+        let len = self.out.len();
+        let pending_break = self.pending_break;
+        let flat = self.flat;
+        let line_start = self.out[..len].rfind('\n').map_or(0, |n| n + 1);
+
+        self.flat = true;
+        f(self);
+        self.flat = flat;
+
+        let written = &self.out[line_start..];
+        let first = written.split('\n').next().unwrap_or_default();
+        let last = written.rsplit('\n').next().unwrap_or_default();
+
+        if first.chars().count() <= MAX_WIDTH
+            && last.chars().count() + reserve <= MAX_WIDTH
+        {
+            return true;
+        }
+
+        self.out.truncate(len);
+        self.pending_break = pending_break;
+
+        false
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use m6lexerkit::SrcFileInfo;
+
+    use super::{format_src, MAX_WIDTH};
+    use crate::lexer::tokenize;
+
+    /// Source of `text`, the file is removed once it's read
+    fn src_of(text: &str) -> SrcFileInfo {
+        static SEQ: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "bas-fmt-test-{}-{}.bath",
+            std::process::id(),
+            SEQ.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, text).unwrap();
+
+        let src = SrcFileInfo::new(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        src.unwrap()
+    }
+
+    fn format_path(path: &str) -> String {
+        format_src(&SrcFileInfo::new(&path).unwrap()).unwrap()
+    }
+
+    fn token_values(src: &SrcFileInfo) -> Vec<String> {
+        tokenize(src)
+            .unwrap()
+            .into_iter()
+            .map(|tok| tok.value_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_fmt() {
+        let formatted = format_path("./examples/fmt_messy.bath");
+
+        assert_eq!(
+            formatted,
+            "\
+# entry
+
+fn main() -> int {
+    let a: int = 1 + 2 * 3;  # trailing
+
+    # before ret
+    ret a
+}
+"
+        );
+        assert_eq!(format_src(&src_of(&formatted)).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_fmt_examples() {
+        for path in ["./examples/exp0.bath", "./examples/lint.bath"] {
+            let src = SrcFileInfo::new(&path).unwrap();
+            let formatted = format_src(&src).unwrap();
+
+            // idempotent and keeps tokens
+            let again = src_of(&formatted);
+
+            assert_eq!(format_src(&again).unwrap(), formatted);
+            assert_eq!(token_values(&again), token_values(&src));
+            assert!(formatted.lines().all(|l| l.len() <= MAX_WIDTH));
+        }
+    }
+
+    #[test]
+    fn test_fmt_width() {
+        let formatted = format_path("./examples/fmt_width.bath");
+
+        assert!(formatted.contains("\nfn long(\n    argument0: int,\n"));
+        assert!(formatted.contains("\n) -> int {\n"));
+    }
+}
@@ -1,10 +1,12 @@
 use m6lexerkit::{
     lazy_static,
-    make_token_matcher_rules, tokenize as tokenize__, SrcFileInfo,
-    TokenMatchResult, TokenizeResult,
+    make_token_matcher_rules, Span, SrcFileInfo, Token,
+    TokenMatchResult,
     prelude::*
 };
 
+use crate::diagnostics::Diagnostic;
+
 
 make_token_matcher_rules! {
     tag       => "[[:alpha:]_][[:alnum:]_]*#",
@@ -12,8 +14,8 @@ make_token_matcher_rules! {
     attr      => r#"@\w+"#,
 
     // Lit
-    lit_int => r"[+|-]?(([0-9]+)|(0x[0-9a-f]+))",
-    lit_float => r"[+|-]?([0-9]+\.[0-9])",
+    lit_int => r"[+-]?(0x[0-9a-fA-F]+|[0-9]+)",
+    lit_float => r"[+|-]?([0-9]+\.[0-9]+)",
     sqstr,
     dqstr,
     aqstr,
@@ -95,31 +97,306 @@ lazy_static::lazy_static! {
 }
 
 
-pub(crate) fn tokenize(source: &SrcFileInfo) -> TokenizeResult {
+pub enum LexErrorReason {
+    InvalidChar(String),
+    /// like "string literal", "command"
+    Unterminated(&'static str),
+    MalformedNum(String),
+}
+
+
+/// All lexical errors of a source file
+pub struct LexError {
+    src: SrcFileInfo,
+    cause_lists: Vec<(LexErrorReason, Span)>,
+}
+
+pub(crate) type LexResult = Result<Vec<Token>, LexError>;
+
+
+/// Comments and blank lines between tokens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Trivia {
+    /// `# ..` on its own line
+    Comment(String),
+    /// One or more empty lines
+    BlankLine,
+}
+
+
+#[derive(Debug, Clone)]
+pub(crate) struct TriviaToken {
+    pub(crate) tok: Token,
+    /// Trivia before the token
+    pub(crate) leading: Vec<Trivia>,
+    /// Comment following the token on the same line
+    pub(crate) trailing: Option<String>,
+}
+
+
+impl LexErrorReason {
+    pub(crate) fn to_diagnostic(&self, span: Span) -> Diagnostic {
+        match self {
+            Self::InvalidChar(s) => Diagnostic::error(
+                "E0001",
+                format!("invalid character `{}`", s.escape_debug()),
+            )
+            .with_primary(span, ""),
+            Self::Unterminated(kind) => Diagnostic::error(
+                "E0002",
+                format!("unterminated {kind}"),
+            )
+            .with_primary(span, "missing the closing delimiter"),
+            Self::MalformedNum(s) => Diagnostic::error(
+                "E0003",
+                format!("malformed number `{s}`"),
+            )
+            .with_primary(span, "")
+            .with_help("numbers are like `42`, `0x2a` or `4.2`"),
+        }
+    }
+}
+
+
+impl LexError {
+    pub(crate) fn src(&self) -> &SrcFileInfo {
+        &self.src
+    }
+
+    pub(crate) fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.cause_lists
+            .iter()
+            .map(|(cause, span)| cause.to_diagnostic(*span))
+            .collect()
+    }
+}
+
+
+impl std::fmt::Debug for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f)?;
+
+        for diag in self.diagnostics() {
+            writeln!(f, "{}", diag.render_human(&self.src, false))?;
+        }
+
+        Ok(())
+    }
+}
+
+
+impl std::fmt::Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for LexError {}
+
+
+
+pub(crate) fn tokenize(source: &SrcFileInfo) -> LexResult {
     tokenize_(source).and_then(|toks| {
         Ok(toks
             .into_iter()
             .filter(|tok| !BLANK_TOK_SET.contains(&tok.name_string().as_str()))
-            .map(|mut tok| {
-                if tok.check_name("attr") {
-                    tok = tok.mapval(&tok.value_string()[1..]);
-                }
-                if tok.check_name("tag") {
-                    let s = &tok.value_string();
-                    tok = tok.mapval(&s[..s.len() - 1]);
-                }
-
-                tok.rename_by_value(&KEY_SET)
-            })
+            .map(cook)
             // .chain([Token::eof()])
             .collect::<Vec<Token>>())
     })
 }
 
 
-#[inline]
-fn tokenize_(source: &SrcFileInfo) -> TokenizeResult {
-    tokenize__(source, &MATCHERS[..])
+/// Same tokens as `tokenize` with comments and blank lines kept, the
+/// second one is the trivia at the end of file.
+pub(crate) fn tokenize_with_trivia(
+    source: &SrcFileInfo,
+) -> Result<(Vec<TriviaToken>, Vec<Trivia>), LexError> {
+    let srcstr = source.get_srcstr();
+    let toks = tokenize_(source)?;
+
+    let mut trivia_toks: Vec<TriviaToken> = vec![];
+    let mut pending = vec![];
+    // end of the last token or comment
+    let mut last_end: Option<usize> = None;
+
+    for tok in toks.into_iter() {
+        if tok.check_name("sp") || tok.check_name("newline") {
+            continue;
+        }
+
+        let text = srcstr[tok.span.from..tok.span.end].trim_end();
+        let newlines = last_end
+            .map_or(0, |end| srcstr[end..tok.span.from].matches('\n').count());
+
+        if newlines >= 2 {
+            pending.push(Trivia::BlankLine);
+        }
+        last_end = Some(tok.span.from + text.len());
+
+        if tok.check_name("sharp_line_comment") {
+            if newlines == 0
+                && pending.is_empty()
+                && let Some(prev) = trivia_toks.last_mut()
+                && prev.trailing.is_none()
+            {
+                prev.trailing = Some(text.to_owned());
+            } else {
+                pending.push(Trivia::Comment(text.to_owned()));
+            }
+            continue;
+        }
+
+        trivia_toks.push(TriviaToken {
+            tok: cook(tok),
+            leading: std::mem::take(&mut pending),
+            trailing: None,
+        });
+    }
+
+    Ok((trivia_toks, pending))
+}
+
+
+/// Doc comment: `##` lines right above the line of `pos`, with the `##`
+/// and one following space stripped
+pub(crate) fn doc_comment(source: &SrcFileInfo, pos: usize) -> Option<String> {
+    let srcstr = source.get_srcstr();
+    let line_start = srcstr[..pos].rfind('\n').map_or(0, |i| i + 1);
+
+    let mut lines = srcstr[..line_start]
+        .lines()
+        .rev()
+        .map_while(|line| line.trim().strip_prefix("##"))
+        .map(|text| text.strip_prefix(' ').unwrap_or(text))
+        .collect::<Vec<_>>();
+
+    if lines.is_empty() {
+        return None;
+    }
+    lines.reverse();
+
+    Some(lines.join("\n"))
+}
+
+
+/// Strip sigils of attributes and tags, and recognize keywords
+fn cook(mut tok: Token) -> Token {
+    if tok.check_name("attr") {
+        tok = tok.mapval(&tok.value_string()[1..]);
+    }
+    if tok.check_name("tag") {
+        let s = &tok.value_string();
+        tok = tok.mapval(&s[..s.len() - 1]);
+    }
+
+    tok.rename_by_value(&KEY_SET)
+}
+
+
+/// Longest match over `MATCHERS`, report error and skip on failure so
+/// that all lexical errors are collected in one pass.
+fn tokenize_(source: &SrcFileInfo) -> LexResult {
+    let srcstr = source.get_srcstr();
+
+    let mut tokens = vec![];
+    let mut cause_lists = vec![];
+    let mut i = 0;
+
+    while i < srcstr.len() {
+        let mut longest: Option<Token> = None;
+        let mut failed = false;
+
+        for matcher in MATCHERS.iter() {
+            match matcher(srcstr, i) {
+                Some(Ok(tok)) => {
+                    if longest
+                        .as_ref()
+                        .map_or(true, |prev| tok.span.end > prev.span.end)
+                    {
+                        longest = Some(tok);
+                    }
+                }
+                Some(Err(_)) => failed = true,
+                None => (),
+            }
+        }
+
+        let rem = &srcstr[i..];
+
+        if let Some(tok) = longest {
+            let end = tok.span.end;
+
+            if tok.check_name("lit_int") || tok.check_name("lit_float") {
+                // like `12ab`, `0xfg`, `1.5e`
+                let is_num_char = |c: char| c.is_alphanumeric() || c == '_';
+                let tail = rem[end - i..]
+                    .find(|c: char| !(is_num_char(c) || c == '.'))
+                    .unwrap_or(srcstr.len() - end);
+
+                if rem[end - i..end - i + tail].starts_with(is_num_char) {
+                    let span = Span { from: i, end: end + tail };
+
+                    cause_lists.push((
+                        LexErrorReason::MalformedNum(
+                            srcstr[span.from..span.end].to_owned(),
+                        ),
+                        span,
+                    ));
+                    i = span.end;
+                    continue;
+                }
+            }
+
+            tokens.push(tok);
+            i = end;
+            continue;
+        }
+
+        let kind = if rem.starts_with("!(") {
+            Some("command")
+        } else if rem.starts_with(['"', '\'', '`']) {
+            Some("string literal")
+        } else if failed {
+            Some("literal")
+        } else {
+            None
+        };
+
+        if let Some(kind) = kind {
+            // skip the rest of line
+            let end = rem.find('\n').map_or(srcstr.len(), |n| i + n);
+            let span = Span { from: i, end };
+
+            cause_lists.push((LexErrorReason::Unterminated(kind), span));
+            i = end;
+        } else {
+            // merge continuous invalid chars
+            let invalid_len = rem
+                .char_indices()
+                .find(|(n, _)| {
+                    *n > 0
+                        && MATCHERS.iter().any(|m| m(srcstr, i + n).is_some())
+                })
+                .map_or(rem.len(), |(n, _)| n);
+            let span = Span { from: i, end: i + invalid_len };
+
+            cause_lists.push((
+                LexErrorReason::InvalidChar(rem[..invalid_len].to_owned()),
+                span,
+            ));
+            i = span.end;
+        }
+    }
+
+    if cause_lists.is_empty() {
+        Ok(tokens)
+    } else {
+        Err(LexError {
+            src: source.clone(),
+            cause_lists,
+        })
+    }
 }
 
 
@@ -156,4 +433,44 @@ mod tests {
             Err(err) => println!("{}", err),
         }
     }
+
+    #[test]
+    fn test_lex_error_recovery() {
+        let srcfile = SrcFileInfo::new(&"./examples/err_lex.bath").unwrap();
+        let err = tokenize(&srcfile).unwrap_err();
+
+        let codes = err
+            .diagnostics()
+            .into_iter()
+            .map(|diag| diag.code)
+            .collect::<Vec<_>>();
+
+        assert_eq!(codes, ["E0003", "E0001", "E0002"]);
+    }
+
+    #[test]
+    fn test_lit_num() {
+        let srcfile = SrcFileInfo::new(&"./examples/lit_num.bath").unwrap();
+        let tokens = tokenize(&srcfile).unwrap();
+
+        let lits = tokens
+            .iter()
+            .filter(|tok| {
+                tok.check_name("lit_int") || tok.check_name("lit_float")
+            })
+            .map(|tok| (tok.name_string(), tok.value_string()))
+            .collect::<Vec<_>>();
+
+        // hex of either case isn't split at `x`, fraction has more than one
+        // digit
+        assert_eq!(
+            lits,
+            [
+                ("lit_int".to_owned(), "0x1f".to_owned()),
+                ("lit_int".to_owned(), "0xFF".to_owned()),
+                ("lit_float".to_owned(), "1.25".to_owned()),
+                ("lit_int".to_owned(), "42".to_owned()),
+            ]
+        );
+    }
 }
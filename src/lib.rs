@@ -15,8 +15,16 @@ pub mod driver;
 mod ast_lowering;
 mod codegen;
 pub mod shell;
+pub mod repl;
 pub mod env;
 pub(crate) mod aux;
 pub(crate) mod name_mangling;
 pub mod spec;
+pub mod config;
+pub mod diagnostics;
+pub mod lint;
+pub mod fmt;
+pub mod doc;
+pub(crate) mod dump;
+pub mod lsp;
 
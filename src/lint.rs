@@ -0,0 +1,455 @@
+//! Warnings raised by semantic analysis
+//!
+//! Level of a lint is decided by (later one overrides):
+//!
+//! 1. default level (warn)
+//!
+//! 1. `-A`, `-W`, `-D` of command line (`warnings` selects all lints)
+//!
+//! 1. `@allow(..)`, `@warn(..)`, `@deny(..)` of the enclosing item
+
+use std::{fmt, str::FromStr};
+
+use m6lexerkit::{sym2str, Span, SrcFileInfo, Symbol};
+
+use crate::{
+    ast_lowering::{A3ttrName, A3ttrVal, A3ttrs, AMod},
+    config::DiagConfig,
+    diagnostics::{emit, Diagnostic, Diagnostics, Severity},
+};
+
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Lint {
+    /// Variables and parameters never read
+    UnusedVariables,
+    /// Discarded non-void function call result
+    UnusedResults,
+    /// Statements after `ret`
+    UnreachableCode,
+    /// `let` rebinding a name in the same scope
+    Shadowing,
+    /// Functions and external declarations never called
+    DeadCode,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+
+/// Lint name on command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintSel {
+    /// `warnings`
+    All,
+    One(Lint),
+}
+
+
+pub(crate) enum LintCause {
+    UnusedVariable(Symbol),
+    UnusedParam(Symbol),
+    /// fn name
+    UnusedResult(Symbol),
+    /// span of the `ret`
+    UnreachableCode(Span),
+    /// name, previous binding
+    Shadowing(Symbol, Option<Span>),
+    UnusedFn(Symbol),
+    UnusedExtFn(Symbol),
+}
+
+
+/// (cause, span, enclosing item)
+pub(crate) type LintLists = Vec<(LintCause, Span, Option<Symbol>)>;
+
+
+/// Compile fails for denied lints
+pub struct LintError {
+    src: SrcFileInfo,
+    diags: Vec<Diagnostic>,
+}
+
+
+const LINTS: [Lint; 5] = [
+    Lint::UnusedVariables,
+    Lint::UnusedResults,
+    Lint::UnreachableCode,
+    Lint::Shadowing,
+    Lint::DeadCode,
+];
+
+
+impl Lint {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::UnusedVariables => "unused_variables",
+            Self::UnusedResults => "unused_results",
+            Self::UnreachableCode => "unreachable_code",
+            Self::Shadowing => "shadowing",
+            Self::DeadCode => "dead_code",
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnusedVariables => "W0001",
+            Self::UnusedResults => "W0002",
+            Self::UnreachableCode => "W0003",
+            Self::Shadowing => "W0004",
+            Self::DeadCode => "W0005",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        LINTS.into_iter().find(|lint| lint.name() == name)
+    }
+}
+
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+
+impl FromStr for LintSel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "warnings" {
+            return Ok(Self::All);
+        }
+
+        Lint::from_name(s).map(Self::One).ok_or_else(|| {
+            format!(
+                "unknown lint `{s}`, expect `warnings` or one of {}",
+                LINTS.map(|lint| lint.name()).join(", ")
+            )
+        })
+    }
+}
+
+
+impl LintSel {
+    fn matches(&self, lint: Lint) -> bool {
+        match self {
+            Self::All => true,
+            Self::One(one) => *one == lint,
+        }
+    }
+}
+
+
+impl LintCause {
+    pub(crate) fn lint(&self) -> Lint {
+        match self {
+            Self::UnusedVariable(..) | Self::UnusedParam(..) => {
+                Lint::UnusedVariables
+            }
+            Self::UnusedResult(..) => Lint::UnusedResults,
+            Self::UnreachableCode(..) => Lint::UnreachableCode,
+            Self::Shadowing(..) => Lint::Shadowing,
+            Self::UnusedFn(..) | Self::UnusedExtFn(..) => Lint::DeadCode,
+        }
+    }
+
+    fn to_diagnostic(&self, span: Span) -> Diagnostic {
+        let code = self.lint().code();
+
+        match self {
+            Self::UnusedVariable(sym) | Self::UnusedParam(sym) => {
+                let kind = if matches!(self, Self::UnusedParam(..)) {
+                    "parameter"
+                } else {
+                    "variable"
+                };
+
+                Diagnostic::warning(
+                    code,
+                    format!("unused {kind} `{}`", sym2str(*sym)),
+                )
+                .with_primary(span, "")
+                .with_help(format!(
+                    "if this is intentional, prefix it with an underscore: \
+                     `_{}`",
+                    sym2str(*sym)
+                ))
+            }
+            Self::UnusedResult(fname) => Diagnostic::warning(
+                code,
+                format!("unused result of function `{}`", sym2str(*fname)),
+            )
+            .with_primary(span, "the returned value is discarded")
+            .with_help("bind it with `let _ = ..` if this is intentional"),
+            Self::UnreachableCode(ret_span) => {
+                Diagnostic::warning(code, "unreachable statement")
+                    .with_primary(span, "unreachable statement")
+                    .with_label(
+                        *ret_span,
+                        "any code following this `ret` is unreachable",
+                    )
+            }
+            Self::Shadowing(sym, prev) => {
+                let diag = Diagnostic::warning(
+                    code,
+                    format!(
+                        "`{}` shadows a binding in the same scope",
+                        sym2str(*sym)
+                    ),
+                )
+                .with_primary(span, "");
+
+                if let Some(prev) = prev {
+                    diag.with_label(*prev, "previous binding here")
+                } else {
+                    diag
+                }
+            }
+            Self::UnusedFn(name) => Diagnostic::warning(
+                code,
+                format!("function `{}` is never used", sym2str(*name)),
+            )
+            .with_primary(span, ""),
+            Self::UnusedExtFn(name) => Diagnostic::warning(
+                code,
+                format!(
+                    "external function declaration `{}` is never used",
+                    sym2str(*name)
+                ),
+            )
+            .with_primary(span, ""),
+        }
+    }
+}
+
+
+impl LintError {
+    pub(crate) fn src(&self) -> &SrcFileInfo {
+        &self.src
+    }
+
+    pub(crate) fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diags.clone()
+    }
+}
+
+
+impl fmt::Debug for LintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f)?;
+
+        for diag in self.diags.iter() {
+            writeln!(f, "{}", diag.render_human(&self.src, false))?;
+        }
+
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for LintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for LintError {}
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Function
+
+/// Print warnings and fail on denied ones
+pub(crate) fn emit_lints(
+    src: &SrcFileInfo,
+    amod: &AMod,
+    lints: LintLists,
+    config: &DiagConfig,
+) -> Result<(), LintError> {
+    let (denied, warnings) = lint_diagnostics(amod, lints, config)
+        .into_iter()
+        .partition::<Vec<_>, _>(|diag| diag.severity == Severity::Error);
+
+    if !warnings.is_empty() {
+        emit(
+            &Diagnostics {
+                src,
+                diags: warnings,
+            },
+            config.error_format,
+        );
+    }
+
+    if denied.is_empty() {
+        Ok(())
+    } else {
+        Err(LintError {
+            src: src.clone(),
+            diags: denied,
+        })
+    }
+}
+
+
+/// Diagnostics of warned and denied (as error) lints
+pub(crate) fn lint_diagnostics(
+    amod: &AMod,
+    lints: LintLists,
+    config: &DiagConfig,
+) -> Vec<Diagnostic> {
+    let mut diags = vec![];
+
+    for (cause, span, item) in lints.into_iter() {
+        let lint = cause.lint();
+
+        let attrs = item.and_then(|name| {
+            amod.afns
+                .get(&name)
+                .map(|afn| &afn.attrs)
+                .or_else(|| amod.efns.get(&name).map(|efn| &efn.attrs))
+        });
+        let level = lint_level(lint, config, attrs);
+
+        let mut diag = cause.to_diagnostic(span);
+
+        match level {
+            LintLevel::Allow => (),
+            LintLevel::Warn => diags.push(diag),
+            LintLevel::Deny => {
+                diag.severity = Severity::Error;
+                diag.notes.push(format!("lint `{lint}` is denied"));
+                diags.push(diag);
+            }
+        }
+    }
+
+    diags
+}
+
+
+fn lint_level(
+    lint: Lint,
+    config: &DiagConfig,
+    attrs: Option<&A3ttrs>,
+) -> LintLevel {
+    let mut level = LintLevel::Warn;
+
+    for (sel, sel_level) in config.lint_levels.iter() {
+        if sel.matches(lint) {
+            level = *sel_level;
+        }
+    }
+
+    if let Some(attrs) = attrs {
+        for (attr, attr_level) in [
+            (A3ttrName::Deny, LintLevel::Deny),
+            (A3ttrName::Warn, LintLevel::Warn),
+            (A3ttrName::Allow, LintLevel::Allow),
+        ] {
+            if let Some(A3ttrVal::Lints(lints)) = attrs.get_attr(attr)
+                && lints.contains(&lint)
+            {
+                return attr_level;
+            }
+        }
+    }
+
+    level
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use m6lexerkit::sym2str;
+
+    use super::{emit_lints, lint_level, Lint, LintLevel, LintSel};
+    use crate::{
+        ast_lowering::{lower_src, Pass2Export},
+        config::DiagConfig,
+    };
+
+    #[test]
+    fn test_lint_sel() {
+        assert_eq!("warnings".parse(), Ok(LintSel::All));
+        assert_eq!(
+            "dead_code".parse(),
+            Ok(LintSel::One(Lint::DeadCode))
+        );
+        assert!("unused_imports".parse::<LintSel>().is_err());
+    }
+
+    #[test]
+    fn test_lints() -> Result<(), Box<dyn std::error::Error>> {
+        let Pass2Export {
+            src, amod, lints, ..
+        } = lower_src("./examples/lint.bath", false)?;
+
+        let mut found = lints
+            .iter()
+            .map(|(cause, _span, item)| {
+                let afn = &amod.afns[&item.unwrap()];
+                let level = lint_level(
+                    cause.lint(),
+                    &DiagConfig::default(),
+                    Some(&afn.attrs),
+                );
+
+                (sym2str(afn.idt.value), cause.lint(), level)
+            })
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "allowed".to_owned(),
+                    Lint::UnusedVariables,
+                    LintLevel::Allow
+                ),
+                (
+                    "never_called".to_owned(),
+                    Lint::DeadCode,
+                    LintLevel::Warn
+                ),
+                ("shadowing".to_owned(), Lint::Shadowing, LintLevel::Warn),
+                (
+                    "unreachable".to_owned(),
+                    Lint::UnreachableCode,
+                    LintLevel::Warn
+                ),
+                (
+                    "unused_result".to_owned(),
+                    Lint::UnusedResults,
+                    LintLevel::Warn
+                ),
+                (
+                    "unused_variable".to_owned(),
+                    Lint::UnusedVariables,
+                    LintLevel::Warn
+                ),
+            ]
+        );
+
+        // `-D warnings`
+        let config = DiagConfig {
+            lint_levels: vec![(LintSel::All, LintLevel::Deny)],
+            ..Default::default()
+        };
+        let err = emit_lints(&src, &amod, lints, &config).unwrap_err();
+
+        // `allowed` keeps its own level
+        assert_eq!(err.diagnostics().len(), 5);
+
+        Ok(())
+    }
+}
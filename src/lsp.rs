@@ -0,0 +1,942 @@
+//! Language server over stdio
+//!
+//! Every change of a document reruns the front phases (lex, parse, pass1 and
+//! pass2) on its full text and publishes the diagnostics. The last
+//! successful analysis of a document is kept for hover, go-to-definition and
+//! document symbols. Core lib is analyzed once at startup for completion and
+//! its definitions.
+//!
+//! There is no import syntax yet, so `core.bath` is the only external module.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    io::{self, BufRead, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use m6lexerkit::{sym2str, Span, SrcFileInfo, Symbol};
+use serde_json::{json, Value};
+
+use crate::{
+    ast_lowering::{
+        AMod, ARef, AVal, ExtSymSet, Pass1Export, Pass2Export, RefLists,
+        SemanticAnalyzerPass1, SemanticAnalyzerPass2,
+    },
+    config::DiagConfig,
+    diagnostics::{Diagnostic, Diagnostics, Severity},
+    env::core_src_path,
+    lexer::tokenize,
+    lint::lint_diagnostics,
+    name_mangling::{base_name, signature},
+    parser::parse,
+};
+
+
+/// JSON-RPC error code
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// LSP `SymbolKind` and `CompletionItemKind`
+const SYMBOL_KIND_FUNCTION: u32 = 12;
+const SYMBOL_KIND_VARIABLE: u32 = 13;
+const COMPLETION_KIND_FUNCTION: u32 = 3;
+
+
+pub struct LanguageServer {
+    /// Directory to save the text of documents for lexer, removed on exit
+    dir: PathBuf,
+    /// File of each document uri in `dir`
+    files: HashMap<String, PathBuf>,
+    core: Analysis,
+    core_uri: String,
+    /// Core lib exports
+    ess: ExtSymSet,
+    docs: HashMap<String, Document>,
+    shutdown: bool,
+}
+
+
+struct Document {
+    text: String,
+    /// Last successful analysis
+    analysis: Option<Analysis>,
+}
+
+
+/// Result of front phases of one source
+struct Analysis {
+    text: String,
+    amod: AMod,
+    refs: RefLists,
+}
+
+
+/// Definition site of name
+enum Def {
+    /// fn, (sym, tagid)
+    Local(Symbol, (Symbol, usize)),
+    /// fn, param idx
+    Param(Symbol, usize),
+    /// full name
+    Fn(Symbol),
+}
+
+
+impl LanguageServer {
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        let dir = std::env::temp_dir()
+            .join(format!("bas-lsp-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+
+        let core_path = core_src_path()?;
+        let core_src = SrcFileInfo::new(&core_path)?;
+
+        let tokens = tokenize(&core_src)?;
+        let tt = parse(tokens, &core_src)?;
+
+        let Pass1Export { amod, .. } = SemanticAnalyzerPass1::run(
+            core_src,
+            tt,
+            ExtSymSet { mods: vec![] },
+        )?;
+
+        let ess = ExtSymSet {
+            mods: vec![amod.export()],
+        };
+        let core = Analysis {
+            text: fs::read_to_string(&core_path)?,
+            amod,
+            refs: vec![],
+        };
+
+        Ok(Self {
+            dir,
+            files: HashMap::new(),
+            core,
+            core_uri: path_to_uri(&core_path),
+            ess,
+            docs: HashMap::new(),
+            shutdown: false,
+        })
+    }
+
+    /// Serve until `exit`, return the exit code of process
+    pub fn run<R: BufRead, W: Write>(
+        &mut self,
+        reader: R,
+        writer: W,
+    ) -> io::Result<i32> {
+        let res = self.serve(reader, writer);
+
+        let _ = fs::remove_dir_all(&self.dir);
+
+        res
+    }
+
+    fn serve<R: BufRead, W: Write>(
+        &mut self,
+        mut reader: R,
+        mut writer: W,
+    ) -> io::Result<i32> {
+        while let Some(msg) = read_message(&mut reader)? {
+            let Some(method) = msg["method"].as_str() else {
+                // response of client
+                continue;
+            };
+            let params = &msg["params"];
+
+            match (method, msg.get("id")) {
+                ("exit", _) => return Ok(if self.shutdown { 0 } else { 1 }),
+                (_, Some(id)) => {
+                    let resp = match self.handle_request(method, params) {
+                        Ok(result) => json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "result": result,
+                        }),
+                        Err((code, message)) => json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "error": { "code": code, "message": message },
+                        }),
+                    };
+
+                    write_message(&mut writer, &resp)?;
+                }
+                (_, None) => {
+                    for notif in self.handle_notification(method, params) {
+                        write_message(&mut writer, &notif)?;
+                    }
+                }
+            }
+        }
+
+        // stdin is closed without `exit`
+        Ok(1)
+    }
+
+
+    ///////////////////////////////////////////////////////////////////////////
+    //// Dispatch
+
+    fn handle_request(
+        &mut self,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, (i64, String)> {
+        Ok(match method {
+            "initialize" => json!({
+                "capabilities": {
+                    // full text
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "completionProvider": {},
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": {
+                    "name": "bas-lsp",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            }),
+            "shutdown" => {
+                self.shutdown = true;
+                Value::Null
+            }
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/completion" => self.completion(params),
+            "textDocument/documentSymbol" => self.document_symbol(params),
+            _ => {
+                return Err((
+                    METHOD_NOT_FOUND,
+                    format!("unsupported method `{method}`"),
+                ))
+            }
+        })
+    }
+
+    /// Return notifications to client
+    fn handle_notification(
+        &mut self,
+        method: &str,
+        params: &Value,
+    ) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"]
+                    .as_str()
+                    .unwrap_or_default();
+
+                vec![self.update(uri, text.to_owned())]
+            }
+            "textDocument/didChange" => {
+                // full sync, the last one is the whole text
+                let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                else {
+                    return vec![];
+                };
+
+                vec![self.update(uri, text.to_owned())]
+            }
+            "textDocument/didClose" => {
+                self.docs.remove(uri);
+
+                if let Some(path) = self.files.remove(uri) {
+                    let _ = fs::remove_file(path);
+                }
+
+                vec![publish_diagnostics(uri, vec![])]
+            }
+            _ => vec![],
+        }
+    }
+
+
+    ///////////////////////////////////////////////////////////////////////////
+    //// Analysis
+
+    /// Analyze new text of document and return its diagnostics
+    fn update(&mut self, uri: &str, text: String) -> Value {
+        let (diags, analysis) = match self.analyze(uri, &text) {
+            Ok((diags, analysis)) => (diags, Some(analysis)),
+            Err(err) => {
+                let diags = match Diagnostics::from_error(&*err) {
+                    Some(diags) => diags.diags,
+                    None => vec![
+                        Diagnostic::error("E0000", err.to_string())
+                            .with_primary(Span::default(), ""),
+                    ],
+                };
+
+                (diags, None)
+            }
+        };
+
+        let lsp_diags =
+            diags.iter().map(|diag| lsp_diagnostic(uri, &text, diag));
+        let notif = publish_diagnostics(uri, lsp_diags.collect());
+
+        let doc = self.docs.entry(uri.to_owned()).or_insert(Document {
+            text: String::new(),
+            analysis: None,
+        });
+        doc.text = text;
+
+        // keep the stale one for a broken text
+        if analysis.is_some() {
+            doc.analysis = analysis;
+        }
+
+        notif
+    }
+
+    fn analyze(
+        &mut self,
+        uri: &str,
+        text: &str,
+    ) -> Result<(Vec<Diagnostic>, Analysis), Box<dyn Error>> {
+        // rewrite the same file on each change
+        let nxt = self.files.len() + 1;
+        let path = self
+            .files
+            .entry(uri.to_owned())
+            .or_insert_with(|| self.dir.join(format!("doc_{nxt}.bath")))
+            .clone();
+        fs::write(&path, text)?;
+
+        let src = SrcFileInfo::new(&path)?;
+        let tokens = tokenize(&src)?;
+        let tt = parse(tokens, &src)?;
+
+        let Pass1Export {
+            src,
+            tt2,
+            amod,
+            ess,
+        } = SemanticAnalyzerPass1::run(src, tt, self.ess.clone())?;
+
+        let Pass2Export {
+            amod, lints, refs, ..
+        } = SemanticAnalyzerPass2::run(src, tt2, amod, ess)?;
+
+        let diags = lint_diagnostics(&amod, lints, &DiagConfig::default());
+
+        Ok((
+            diags,
+            Analysis {
+                text: text.to_owned(),
+                amod,
+                refs,
+            },
+        ))
+    }
+
+
+    ///////////////////////////////////////////////////////////////////////////
+    //// Language Feature
+
+    fn hover(&self, params: &Value) -> Value {
+        let Some((analysis, offset)) = self.locate(params) else {
+            return Value::Null;
+        };
+        let Some((span, def)) = analysis.def_at(offset) else {
+            return Value::Null;
+        };
+
+        let amod = &analysis.amod;
+        let sig = match def {
+            Def::Local(fname, (sym, tagid)) => {
+                let Some(ty) = amod.binding_ty(fname, &AVal::Var(sym, tagid))
+                else {
+                    return Value::Null;
+                };
+
+                format!("let {}: {}", sym2str(sym), ty.ident_name())
+            }
+            Def::Param(fname, idx) => {
+                let param = &amod.afns[&fname].params[idx];
+                let ty = amod
+                    .binding_ty(fname, &AVal::FnParam(idx as u32))
+                    .unwrap_or_else(|| param.ty.clone());
+
+                format!("{}: {}", sym2str(param.formal), ty.ident_name())
+            }
+            Def::Fn(fullname) => {
+                let Some(sig) = self.fn_signature(amod, fullname) else {
+                    return Value::Null;
+                };
+
+                sig
+            }
+        };
+
+        json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!("```bas\n{sig}\n```"),
+            },
+            "range": lsp_range(&analysis.text, span),
+        })
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let Some((analysis, offset)) = self.locate(params) else {
+            return Value::Null;
+        };
+        let Some((_span, def)) = analysis.def_at(offset) else {
+            return Value::Null;
+        };
+
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let amod = &analysis.amod;
+
+        let span = match def {
+            Def::Local(fname, key) => amod
+                .alloc_spans
+                .get(&fname)
+                .and_then(|spans| spans.get(&key))
+                .cloned(),
+            Def::Param(fname, idx) => {
+                Some(amod.afns[&fname].params[idx].span)
+            }
+            Def::Fn(fullname) => {
+                let core = &self.core;
+
+                if let Some(span) = fn_def_span(amod, fullname) {
+                    Some(span)
+                } else if let Some(span) = fn_def_span(&core.amod, fullname) {
+                    return lsp_location(&self.core_uri, &core.text, span);
+                } else {
+                    None
+                }
+            }
+        };
+
+        span.map(|span| lsp_location(uri, &analysis.text, span))
+            .unwrap_or(Value::Null)
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+
+        let local = self
+            .docs
+            .get(uri)
+            .and_then(|doc| doc.analysis.as_ref())
+            .map(|analysis| analysis.amod.export());
+
+        let items = local
+            .iter()
+            .flat_map(|exp| exp.afns.values())
+            .chain(self.ess.afns_iter())
+            .map(|dec| {
+                let name = base_name(dec.full_name);
+
+                json!({
+                    "label": sym2str(name),
+                    "kind": COMPLETION_KIND_FUNCTION,
+                    "detail": signature(name, &dec.params, &dec.ret),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!(items)
+    }
+
+    fn document_symbol(&self, params: &Value) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+
+        let Some(analysis) =
+            self.docs.get(uri).and_then(|doc| doc.analysis.as_ref())
+        else {
+            return json!([]);
+        };
+
+        let text = &analysis.text;
+        let amod = &analysis.amod;
+        let mut symbols = vec![];
+
+        for (fullname, afn) in amod.afns.iter() {
+            let locals = amod
+                .alloc_spans
+                .get(fullname)
+                .into_iter()
+                .flat_map(|spans| spans.iter())
+                .map(|((sym, tagid), span)| {
+                    let ty = amod
+                        .binding_ty(*fullname, &AVal::Var(*sym, *tagid))
+                        .map(|ty| ty.ident_name())
+                        .unwrap_or_default();
+
+                    json!({
+                        "name": sym2str(*sym),
+                        "detail": ty,
+                        "kind": SYMBOL_KIND_VARIABLE,
+                        "range": lsp_range(text, *span),
+                        "selectionRange": lsp_range(text, *span),
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            symbols.push(json!({
+                "name": sym2str(afn.idt.value),
+                "detail": signature(afn.idt.value, &afn.params, &afn.ret),
+                "kind": SYMBOL_KIND_FUNCTION,
+                "range": lsp_range(text, afn.idt.span),
+                "selectionRange": lsp_range(text, afn.idt.span),
+                "children": locals,
+            }));
+        }
+
+        for (fullname, span) in amod.efn_spans.iter() {
+            let efn = &amod.efns[fullname];
+            let name = base_name(*fullname);
+
+            symbols.push(json!({
+                "name": sym2str(name),
+                "detail": signature(name, &efn.params, &efn.ret),
+                "kind": SYMBOL_KIND_FUNCTION,
+                "range": lsp_range(text, *span),
+                "selectionRange": lsp_range(text, *span),
+            }));
+        }
+
+        json!(symbols)
+    }
+
+    /// Analysis of document and byte offset of `params.position`
+    fn locate(&self, params: &Value) -> Option<(&Analysis, usize)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let doc = self.docs.get(uri)?;
+        let analysis = doc.analysis.as_ref()?;
+
+        // position of stale analysis may be out of range
+        if doc.text != analysis.text {
+            return None;
+        }
+
+        let line = params["position"]["line"].as_u64()? as usize;
+        let character = params["position"]["character"].as_u64()? as usize;
+
+        Some((analysis, offset_of(&analysis.text, line, character)))
+    }
+
+    fn fn_signature(&self, amod: &AMod, fullname: Symbol) -> Option<String> {
+        let name = base_name(fullname);
+
+        if let Some(afn) = amod.afns.get(&fullname) {
+            Some(signature(name, &afn.params, &afn.ret))
+        } else if let Some(efn) = amod.efns.get(&fullname) {
+            Some(signature(name, &efn.params, &efn.ret))
+        } else {
+            self.ess
+                .find_func_by_name(fullname)
+                .map(|dec| signature(name, &dec.params, &dec.ret))
+        }
+    }
+}
+
+
+impl Analysis {
+    /// Reference under `offset` and its definition
+    fn def_at(&self, offset: usize) -> Option<(Span, Def)> {
+        let amod = &self.amod;
+
+        for (span, fname, aref) in self.refs.iter() {
+            if !contains(*span, offset) {
+                continue;
+            }
+
+            let def = match aref {
+                ARef::Binding(var) => match var.val {
+                    AVal::Var(sym, tagid) => {
+                        Def::Local(*fname, (sym, tagid))
+                    }
+                    AVal::FnParam(idx) => Def::Param(*fname, idx as usize),
+                    _ => continue,
+                },
+                ARef::Fn(fullname) => Def::Fn(*fullname),
+            };
+
+            return Some((*span, def));
+        }
+
+        // declarations themselves
+        for (fname, afn) in amod.afns.iter() {
+            if contains(afn.idt.span, offset) {
+                return Some((afn.idt.span, Def::Fn(*fname)));
+            }
+
+            for (idx, param) in afn.params.iter().enumerate() {
+                if contains(param.span, offset) {
+                    return Some((param.span, Def::Param(*fname, idx)));
+                }
+            }
+
+            let spans = amod.alloc_spans.get(fname).into_iter().flatten();
+
+            for (key, span) in spans {
+                if contains(*span, offset) {
+                    return Some((*span, Def::Local(*fname, *key)));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Function
+
+/// Read one message, `None` for EOF
+fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut len = None;
+
+    loop {
+        let mut line = String::new();
+
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length")
+        {
+            len = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let Some(len) = len else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing Content-Length header",
+        ));
+    };
+
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+
+    serde_json::from_slice(&buf)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+
+fn write_message<W: Write>(writer: &mut W, msg: &Value) -> io::Result<()> {
+    let body = msg.to_string();
+
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
+
+
+fn publish_diagnostics(uri: &str, diags: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diags },
+    })
+}
+
+
+fn lsp_diagnostic(uri: &str, text: &str, diag: &Diagnostic) -> Value {
+    let span = diag.primary_span().unwrap_or_default();
+    let severity = match diag.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+    };
+
+    let mut message = diag.message.clone();
+    for note in diag.notes.iter() {
+        message.push_str(&format!("\nnote: {note}"));
+    }
+    for help in diag.help.iter() {
+        message.push_str(&format!("\nhelp: {help}"));
+    }
+
+    let related = diag
+        .labels
+        .iter()
+        .filter(|label| !label.primary)
+        .map(|label| {
+            json!({
+                "location": lsp_location(uri, text, label.span),
+                "message": label.message,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "range": lsp_range(text, span),
+        "severity": severity,
+        "code": diag.code,
+        "source": "bas",
+        "message": message,
+        "relatedInformation": related,
+    })
+}
+
+
+fn lsp_location(uri: &str, text: &str, span: Span) -> Value {
+    json!({ "uri": uri, "range": lsp_range(text, span) })
+}
+
+
+fn lsp_range(text: &str, span: Span) -> Value {
+    let (start_line, start_char) = position_of(text, span.from);
+    let (end_line, end_char) = position_of(text, span.end.max(span.from));
+
+    json!({
+        "start": { "line": start_line, "character": start_char },
+        "end": { "line": end_line, "character": end_char },
+    })
+}
+
+
+/// Byte offset into (line, UTF-16 column), both 0-based
+fn position_of(text: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(text.len());
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = text[..line_start].matches('\n').count();
+
+    let character = text[line_start..]
+        .char_indices()
+        .take_while(|(i, _)| line_start + i < offset)
+        .map(|(_, c)| c.len_utf16())
+        .sum();
+
+    (line, character)
+}
+
+
+/// (line, UTF-16 column) into byte offset, clamped to the line
+fn offset_of(text: &str, line: usize, character: usize) -> usize {
+    let Some(line_start) = (if line == 0 {
+        Some(0)
+    } else {
+        text.match_indices('\n').nth(line - 1).map(|(i, _)| i + 1)
+    }) else {
+        return text.len();
+    };
+
+    let mut col = 0;
+
+    for (i, c) in text[line_start..].char_indices() {
+        if col >= character || c == '\n' {
+            return line_start + i;
+        }
+        col += c.len_utf16();
+    }
+
+    text.len()
+}
+
+
+/// Bytes except unreserved ones and `/` are percent-encoded
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = "file://".to_owned();
+
+    for b in path.to_string_lossy().bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{b:02X}"));
+        }
+    }
+
+    uri
+}
+
+
+fn contains(span: Span, offset: usize) -> bool {
+    span.from <= offset && offset <= span.end
+}
+
+
+fn fn_def_span(amod: &AMod, fullname: Symbol) -> Option<Span> {
+    amod.afns
+        .get(&fullname)
+        .map(|afn| afn.idt.span)
+        .or_else(|| amod.efn_spans.get(&fullname).cloned())
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use serde_json::{json, Value};
+
+    use std::path::Path;
+
+    use super::{
+        offset_of, path_to_uri, position_of, read_message, LanguageServer,
+    };
+
+    fn frame(msg: Value) -> String {
+        let body = msg.to_string();
+
+        format!("Content-Length: {}\r\n\r\n{body}", body.len())
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> String {
+        frame(json!({
+            "jsonrpc": "2.0", "id": id, "method": method, "params": params
+        }))
+    }
+
+    fn notify(method: &str, params: Value) -> String {
+        frame(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    fn text_pos(uri: &str, line: u64, character: u64) -> Value {
+        json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": line, "character": character },
+        })
+    }
+
+    #[test]
+    fn test_position() {
+        let text = "fn f() {\n  let 中a = 1;\n}\n";
+        let offset = text.find('a').unwrap();
+
+        assert_eq!(position_of(text, offset), (1, 7));
+        assert_eq!(offset_of(text, 1, 7), offset);
+        assert_eq!(offset_of(text, 1, 100), text.find("\n}").unwrap());
+    }
+
+    #[test]
+    fn test_path_to_uri() {
+        assert_eq!(
+            path_to_uri(Path::new("/a b/中#1.bath")),
+            "file:///a%20b/%E4%B8%AD%231.bath"
+        );
+    }
+
+    #[test]
+    fn test_scripted_session() -> Result<(), Box<dyn std::error::Error>> {
+        let uri = "file:///tmp/test.bath";
+        let text = concat!(
+            "fn add(x: int, y: int) -> int {\n",
+            "    let z = x + y;\n",
+            "    ret z;\n",
+            "}\n",
+            "\n",
+            "fn main() -> int {\n",
+            "    let a = 1;\n",
+            "    ret add(a, a);\n",
+            "}\n",
+        );
+        let bad_text = "fn main() -> int {\n    ret w;\n}\n";
+
+        let script = [
+            request(1, "initialize", json!({ "capabilities": {} })),
+            notify("initialized", json!({})),
+            notify(
+                "textDocument/didOpen",
+                json!({ "textDocument": {
+                    "uri": uri, "languageId": "bas", "version": 1,
+                    "text": text,
+                }}),
+            ),
+            // `z` of `ret z`
+            request(2, "textDocument/hover", text_pos(uri, 2, 8)),
+            request(3, "textDocument/definition", text_pos(uri, 2, 8)),
+            // `add` of `add(a, a)`
+            request(4, "textDocument/definition", text_pos(uri, 7, 9)),
+            request(5, "textDocument/completion", text_pos(uri, 7, 4)),
+            request(
+                6,
+                "textDocument/documentSymbol",
+                json!({ "textDocument": { "uri": uri } }),
+            ),
+            notify(
+                "textDocument/didChange",
+                json!({
+                    "textDocument": { "uri": uri, "version": 2 },
+                    "contentChanges": [{ "text": bad_text }],
+                }),
+            ),
+            request(7, "shutdown", Value::Null),
+            notify("exit", Value::Null),
+        ]
+        .concat();
+
+        let mut out = vec![];
+        let mut server = LanguageServer::new()?;
+        let code = server.run(Cursor::new(script.into_bytes()), &mut out)?;
+
+        assert_eq!(code, 0);
+        // one file for the document, all removed on exit
+        assert_eq!(server.files.len(), 1);
+        assert!(!server.dir.exists());
+
+        let mut reader = Cursor::new(out);
+        let mut msgs = vec![];
+
+        while let Some(msg) = read_message(&mut reader)? {
+            msgs.push(msg);
+        }
+
+        let resp = |id: u64| {
+            msgs.iter()
+                .find(|msg| msg["id"] == json!(id))
+                .map(|msg| msg["result"].clone())
+                .unwrap()
+        };
+        let diags = msgs
+            .iter()
+            .filter(|msg| msg["method"] == "textDocument/publishDiagnostics")
+            .map(|msg| msg["params"]["diagnostics"].clone())
+            .collect::<Vec<_>>();
+
+        assert_eq!(resp(1)["capabilities"]["hoverProvider"], json!(true));
+
+        assert!(resp(2)["contents"]["value"]
+            .as_str()
+            .unwrap()
+            .contains("let z: i32"));
+
+        let start = |id: u64| {
+            let start = &resp(id)["range"]["start"];
+
+            (start["line"].clone(), start["character"].clone())
+        };
+        assert_eq!(start(3), (json!(1), json!(8)));
+        assert_eq!(start(4), (json!(0), json!(3)));
+
+        let completion = resp(5).to_string();
+        assert!(completion.contains(r#""label":"add""#));
+        assert!(completion.contains("fn strlen(str: ptr) -> i32"));
+
+        let symbols = resp(6);
+        assert_eq!(symbols[0]["name"], "add");
+        assert_eq!(symbols[0]["children"][0]["name"], "z");
+
+        assert_eq!(diags.len(), 2);
+        assert_eq!(diags[0], json!([]));
+        assert_eq!(diags[1][0]["code"], "E0204");
+        assert_eq!(diags[1][0]["range"]["start"]["line"], 1);
+
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 use itertools::Itertools;
 use m6lexerkit::{Symbol, sym2str, str2sym};
 
-use crate::ast_lowering::{AType, APriType};
+use crate::ast_lowering::{AParamPat, AType, APriType};
 
 
 
@@ -152,7 +152,6 @@ pub fn mangling(name: Symbol, atys: &[AType]) -> Symbol {
     str2sym(&format!("{}@{}", sym2str(name), param_postfix))
 }
 
-#[allow(unused)]
 pub fn unmangling(mangling_name: Symbol) -> Option<(Symbol, Vec<AType>)> {
 
     let ss = sym2str(mangling_name);
@@ -183,6 +182,54 @@ pub fn unmangling(mangling_name: Symbol) -> Option<(Symbol, Vec<AType>)> {
 }
 
 
+/// Demangled name
+pub(crate) fn base_name(fullname: Symbol) -> Symbol {
+    unmangling(fullname).map_or_else(
+        || {
+            let s = sym2str(fullname);
+
+            // mangled without params
+            match s.split_once('@') {
+                Some((base, _)) => str2sym(base),
+                None => fullname,
+            }
+        },
+        |(base, _)| base,
+    )
+}
+
+
+/// Like `fn name(a: i32) -> i32`
+pub(crate) fn signature(
+    name: Symbol,
+    params: &[AParamPat],
+    ret: &AType,
+) -> String {
+    let params = params
+        .iter()
+        .map(|param| {
+            let formal = sym2str(param.formal);
+
+            if formal.is_empty() {
+                param.ty.ident_name()
+            } else {
+                format!("{formal}: {}", param.ty.ident_name())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    match ret {
+        AType::Void => format!("fn {}({params})", sym2str(name)),
+        _ => format!(
+            "fn {}({params}) -> {}",
+            sym2str(name),
+            ret.ident_name()
+        ),
+    }
+}
+
+
 
 #[cfg(test)]
 mod tests {
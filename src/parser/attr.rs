@@ -10,8 +10,36 @@ impl Parser {
 
         while self.peek1_t().check_name("attr") {
             subs.push((ST::attr, SN::E(self.unchecked_advance())));
+
+            if self.peek1_t().check_name("lparen") {
+                subs.push((ST::AttrArgs, SN::T(self.parse_attr_args()?)));
+            }
+        }
+
+        Ok(TT { subs })
+    }
+
+    /// `(id, ..)`
+    fn parse_attr_args(&mut self) -> ParseResult2 {
+        let four = ST::AttrArgs;
+        let mut subs = vec![];
+
+        subs.push((
+            ST::lparen,
+            SN::E(self.expect_eat_tok1_t(ST::lparen, four)?),
+        ));
+        subs.push((ST::id, SN::E(self.expect_eat_id_t(four)?)));
+
+        while self.peek1_t().check_name("comma") {
+            subs.push((ST::comma, SN::E(self.unchecked_advance())));
+            subs.push((ST::id, SN::E(self.expect_eat_id_t(four)?)));
         }
 
+        subs.push((
+            ST::rparen,
+            SN::E(self.expect_eat_tok1_t(ST::rparen, four)?),
+        ));
+
         Ok(TT { subs })
     }
 }
@@ -31,6 +31,22 @@ impl Parser {
                 }
             }
 
+            /* FunCall */
+
+            if self.peek1_t().check_name("lparen")
+                && expr_units.len() == ops.len() + 1
+                && matches!(expr_units.last(), Some((ST::PathExpr, _)))
+            {
+                let path = expr_units.pop().unwrap();
+                let args = (ST::CallArgs, SN::T(self.parse_call_args()?));
+
+                expr_units.push((
+                    ST::FunCallExpr,
+                    SN::T(TT::new(vec![path, args])),
+                ));
+                continue;
+            }
+
             /* ExprBlk */
 
             if
@@ -63,29 +79,9 @@ impl Parser {
                 break;
             }
 
-            if let Some((st, sn)) = expr_units.pop() {
-                if st == ST::PathExpr && ty == ST::GroupedExpr {
-                    expr_units.push((
-                        ST::FunCallExpr,
-                        SN::T(TT { subs: vec![
-                                (st, sn),
-                                (ty, tt)
-                            ]
-                        })
-                    ));
-                }
-                else {
-                    expr_units.push((st, sn));
-                    expr_units.push((ty, tt));
-                }
-            }
-            else {
-                expr_units.push((ty, tt));
-            }
+            expr_units.push((ty, tt));
 
             if expr_units.len() > ops.len() + 1 {
-                println!("expr units: {:#?}", expr_units);
-
                 let span = Span {
                     from,
                     end: self.prev_t().span().end,
@@ -102,10 +98,14 @@ impl Parser {
         if ops.is_empty() {
             Ok(TT::new(expr_units))
         } else {
+            // like `a +`
             if ops.len() + 1 != expr_units.len() {
-                println!("Unmathced ops {} - expr_units {}", ops.len(), expr_units.len());
-                println!("{ops:#?}");
-                println!("{expr_units:#?}");
+                let span = Span {
+                    from,
+                    end: self.prev_t().span().end,
+                };
+
+                return Err(R::lack("Operand", "BOpExpr", span));
             }
 
             Ok(map_infix_expr_to_tt(parse_infix_expr(ops, expr_units)))
@@ -244,6 +244,35 @@ impl Parser {
         Ok(TT::new(subs))
     }
 
+    /// `(a, b)`, empty for `()`
+    pub(crate) fn parse_call_args(&mut self) -> ParseResult2 {
+        let four = ST::CallArgs;
+        let mut subs = vec![];
+
+        subs.push((
+            ST::lparen,
+            SN::E(self.expect_eat_tok1_t(ST::lparen, four)?)
+        ));
+
+        if !self.peek1_t().check_name("rparen") {
+            loop {
+                subs.push((ST::Expr, SN::T(self.parse_expr()?)));
+
+                if !self.peek1_t().check_name("comma") {
+                    break;
+                }
+                subs.push((ST::comma, SN::E(self.unchecked_advance())));
+            }
+        }
+
+        subs.push((
+            ST::rparen,
+            SN::E(self.expect_eat_tok1_t(ST::rparen, four)?)
+        ));
+
+        Ok(TT::new(subs))
+    }
+
     pub(crate) fn parse_return_expr(&mut self) -> ParseResult2 {
         let four = ST::ReturnExpr;
         let mut subs = vec![
@@ -262,6 +291,13 @@ impl Parser {
         let four = ST::CmdExpr;
         let mut subs = vec![];
 
+        if self.peek1_t().check_name("tag") {
+            subs.push((
+                ST::tag,
+                SN::E(self.unchecked_advance())
+            ))
+        }
+
         let cmd_tok = self.expect_eat_tok1_t(ST::cmd, four)?;
 
         subs.push((ST::cmd, SN::E(cmd_tok)));
@@ -308,6 +344,13 @@ impl Parser {
             if let Some((ty, tt)) = self.try_parse_lit_expr() {
                 (ty, tt)
             }
+            // tagged CmdExpr
+            else if tok1.check_name("tag") && self.peek2_t().check_name("cmd") {
+                (
+                    ST::CmdExpr,
+                    SN::T(self.parse_cmd_expr()?)
+                )
+            }
             // PathExpr | SideEffectExpr
             else if tok1.check_names_in(&["id", "tag"]) {
                 let tok2 = self.peek2_t();
@@ -326,13 +369,21 @@ impl Parser {
 
                 (ty, tt)
             } else if tok1.check_name("continue") {
-                // ty = ST::ContinueExpr;
-                // tt = SN::T(self.parse_return_expr()?);
-                todo!()
+                (
+                    ST::ContinueExpr,
+                    SN::T(TT::new(vec![(
+                        ST::r#continue,
+                        SN::E(self.unchecked_advance()),
+                    )]))
+                )
             } else if tok1.check_name("break") {
-                // ty = ST::BreakExpr;
-                // tt = SN::T(self.parse_return_expr()?);
-                todo!()
+                (
+                    ST::BreakExpr,
+                    SN::T(TT::new(vec![(
+                        ST::r#break,
+                        SN::E(self.unchecked_advance()),
+                    )]))
+                )
             } else if tok1.check_name("ret") {
                 (
                     ST::ReturnExpr,
@@ -3,7 +3,7 @@ use std::fmt::Debug;
 use m6lexerkit::{Span, SrcFileInfo, Token};
 use m6parserkit::{ gen_syntax_enum, SyntaxNode, TokenTree };
 
-use crate::ref_source;
+use crate::diagnostics::Diagnostic;
 
 mod attr;
 mod expr;
@@ -16,6 +16,7 @@ mod ty;
 gen_syntax_enum! [ pub SyntaxType |
     Item,
     Attrs,
+    AttrArgs,
     CupBoard,
     Function,
     BlockExpr,
@@ -52,6 +53,7 @@ gen_syntax_enum! [ pub SyntaxType |
     LoopExpr,
     InfiLoopExpr,
     FunCallExpr,
+    CallArgs,
 
     r#fn,
     r#let,
@@ -117,7 +119,9 @@ gen_syntax_enum! [ pub SyntaxType |
     rshf_assign,
     attr,
     tag,
-    eof
+    eof,
+    /// Skipped tokens on parse error recovery
+    Error
 ];
 pub use SyntaxType as ST;
 
@@ -132,11 +136,13 @@ pub struct Parser {
 
     ent_if_cond: bool,
     eof: Token,
+    /// Errors recovered from
+    errors: Vec<ParseErrorReason>,
 }
 
 
 pub struct ParseError {
-    reason: ParseErrorReason,
+    reasons: Vec<ParseErrorReason>,
     src: SrcFileInfo,
 }
 
@@ -161,6 +167,11 @@ pub enum ParseErrorReason {
         four: String,
         span: Span,
     },
+    /// Parsed tree doesn't conform to `spec::GRAMMER`
+    Grammar {
+        msg: String,
+        span: Span,
+    },
 }
 
 
@@ -181,26 +192,31 @@ impl std::fmt::Display for ParseError {
 impl Debug for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f)?;
-        writeln!(f)?;
 
-        writeln!(f, "{:?}", self.reason)?;
-
-        let span = self.reason.span();
-
-        ref_source!(span, "^", f, self.src);
+        for diag in self.diagnostics() {
+            writeln!(f, "{}", diag.render_human(&self.src, false))?;
+        }
 
         Ok(())
     }
 }
 
 
-impl ParseErrorReason {
-    pub(super) fn emit_error(self, src: SrcFileInfo) -> ParseError {
-        match self {
-            _ => ParseError { reason: self, src },
-        }
+impl ParseError {
+    pub(crate) fn src(&self) -> &SrcFileInfo {
+        &self.src
     }
 
+    pub(crate) fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.reasons
+            .iter()
+            .map(|reason| reason.to_diagnostic())
+            .collect()
+    }
+}
+
+
+impl ParseErrorReason {
     pub(super) fn span(&self) -> Span {
         match self {
             Self::Expect {
@@ -214,9 +230,44 @@ impl ParseErrorReason {
                 four: _,
                 span,
             } => *span,
+            Self::Grammar { span, .. } => *span,
         }
     }
 
+    pub(crate) fn to_diagnostic(&self) -> Diagnostic {
+        let (code, msg, label) = match self {
+            Self::Expect {
+                expect,
+                four,
+                found,
+            } => (
+                "E0101",
+                format!(
+                    "expected {expect} in {four}, found {}",
+                    describe_tok(found)
+                ),
+                format!("expected {expect}"),
+            ),
+            Self::Unrecognized { four, found } => (
+                "E0102",
+                format!("unexpected {} in {four}", describe_tok(found)),
+                "unrecognized token".to_owned(),
+            ),
+            Self::Lack { lack, four, .. } => (
+                "E0103",
+                format!("missing {lack} for {four}"),
+                format!("missing {lack}"),
+            ),
+            Self::Grammar { msg, .. } => (
+                "E0104",
+                format!("malformed syntax: {msg}"),
+                String::new(),
+            ),
+        };
+
+        Diagnostic::error(code, msg).with_primary(self.span(), label)
+    }
+
     pub(crate) fn lack(lack: &str, four: &str, span: Span) -> Self {
         Self::Lack {
             lack: lack.to_string(),
@@ -226,6 +277,17 @@ impl ParseErrorReason {
     }
 }
 
+/// `` `value` `` of the token, or end of file
+fn describe_tok(tok: &Token) -> String {
+    let value = tok.value_string();
+
+    if value.is_empty() {
+        "end of file".to_owned()
+    } else {
+        format!("`{}`", value.escape_debug())
+    }
+}
+
 impl Debug for ParseErrorReason {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -242,21 +304,69 @@ impl Debug for ParseErrorReason {
             Self::Lack { lack, four, .. } => {
                 writeln!(f, "Lack {lack} for {four} ")
             }
+            Self::Grammar { msg, .. } => {
+                writeln!(f, "Grammar verification failed: {msg}")
+            }
         }
     }
 }
 
 
 pub(crate) fn parse(tokens: Vec<Token>, srcfile: &SrcFileInfo) -> ParseResult {
+    let span = match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => Span {
+            from: first.span.from,
+            end: last.span.end,
+        },
+        _ => Span::default(),
+    };
     let mut parser = Parser::new(tokens);
 
-    let tt = parser.parse(srcfile)?;
+    let tt = parser.parse_();
+
+    // Error nodes are out of grammar
+    if !parser.errors.is_empty() {
+        return Err(ParseError {
+            reasons: parser.errors,
+            src: srcfile.clone(),
+        });
+    }
+
+    match crate::spec::GRAMMER.verify(&tt) {
+        Ok(_) => Ok(tt),
+        Err(err) => Err(ParseError {
+            reasons: vec![ParseErrorReason::Grammar {
+                msg: format!("{err:?}"),
+                span: ungrammatical_tok(tt).unwrap_or(span),
+            }],
+            src: srcfile.clone(),
+        }),
+    }
+}
+
 
-    // println!("tt: {tt:#?}");
+/// First token of the first item which doesn't conform to the grammar on
+/// its own, since the verification error isn't located
+fn ungrammatical_tok(tt: TT) -> Option<Span> {
+    tt.subs.into_iter().find_map(|sub| {
+        let item = TT { subs: vec![sub] };
 
-    crate::spec::GRAMMER.verify(&tt).unwrap();
+        if crate::spec::GRAMMER.verify(&item).is_ok() {
+            None
+        } else {
+            first_tok_span(&item)
+        }
+    })
+}
+
+
+fn first_tok_span(tt: &TT) -> Option<Span> {
+    let (_, sn) = tt.subs.first()?;
 
-    Ok(tt)
+    match sn {
+        SyntaxNode::T(sub) => first_tok_span(sub),
+        _ => Some(sn.as_tok().span),
+    }
 }
 
 
@@ -267,6 +377,7 @@ impl Parser {
             tokens,
             ent_if_cond: false,
             eof: Token::eof(),
+            errors: vec![],
         }
     }
 
@@ -321,22 +432,64 @@ impl Parser {
         self.cursor >= self.tokens.len()
     }
 
-    fn parse_(&mut self) -> ParseResult2 {
+    /// Errors are recorded into `self.errors` and replaced by error nodes
+    fn parse_(&mut self) -> TT {
         let mut subs = vec![];
 
         while !self.is_end() {
-            subs.push((SyntaxType::Item, SyntaxNode::T(self.parse_item()?)))
+            match self.parse_item() {
+                Ok(tt) => subs.push((SyntaxType::Item, SyntaxNode::T(tt))),
+                Err(reason) => {
+                    let skipped = self.recover(reason, false);
+                    subs.push((SyntaxType::Error, SyntaxNode::T(skipped)));
+                }
+            }
         }
 
-        Ok(TT { subs })
+        TT { subs }
     }
 
-    fn parse(&mut self, srcfile: &SrcFileInfo) -> ParseResult {
-        self.parse_()
-            .or_else(|reason|
-                Err(reason.emit_error(srcfile.clone()))
-            )
-            .and_then(|tt| Ok(tt))
+    /// Record `reason` and skip to the synchronization point:
+    ///
+    /// in block: after `;`, before `}` or `fn`
+    ///
+    /// in module: before `fn` or attributes
+    ///
+    /// Nested braces are skipped as a whole.
+    fn recover(&mut self, reason: ParseErrorReason, in_block: bool) -> TT {
+        self.errors.push(reason);
+
+        let mut subs = vec![];
+        let mut depth = 0usize;
+
+        while !self.is_end() {
+            let tok = self.peek1_t();
+
+            if tok.check_name("fn")
+                || !in_block && depth == 0 && tok.check_name("attr")
+            {
+                break;
+            }
+
+            let is_semi = tok.check_name("semi");
+
+            if tok.check_name("lbrace") {
+                depth += 1;
+            } else if tok.check_name("rbrace") {
+                if depth == 0 && in_block {
+                    break;
+                }
+                depth = depth.saturating_sub(1);
+            }
+
+            subs.push((SyntaxType::Error, SN::E(self.unchecked_advance())));
+
+            if is_semi && depth == 0 && in_block {
+                break;
+            }
+        }
+
+        TT { subs }
     }
 
     fn expect_eat_id_t(
@@ -361,13 +514,6 @@ impl Parser {
         self.expect_eat_tok1_t(SyntaxType::colon, four)
     }
 
-    fn expect_eat_semi_t(
-        &mut self,
-        four: SyntaxType,
-    ) -> Result<Token, ParseErrorReason> {
-        self.expect_eat_tok1_t(SyntaxType::semi, four)
-    }
-
     fn expect_eat_tok1_t(
         &mut self,
         expect: SyntaxType,
@@ -394,9 +540,21 @@ mod tests {
 
     use m6lexerkit::SrcFileInfo;
 
-    use super::parse;
+    use super::{parse, SN, ST, TT};
     use crate::lexer::tokenize;
 
+    /// Sub trees of `ty` in `tt`, outer ones first
+    fn find_all<'a>(tt: &'a TT, ty: ST, found: &mut Vec<&'a TT>) {
+        for (st, sn) in tt.subs.iter() {
+            if let SN::T(sub) = sn {
+                if *st == ty {
+                    found.push(sub);
+                }
+                find_all(sub, ty, found);
+            }
+        }
+    }
+
     #[test]
     fn test_parser() -> Result<(), Box<dyn std::error::Error>> {
         let path = PathBuf::from("./examples/exp1.bath");
@@ -409,4 +567,52 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_parse_error_recovery() {
+        let src = SrcFileInfo::new(&"./examples/err_parse.bath").unwrap();
+
+        let tokens = tokenize(&src).unwrap();
+        let err = parse(tokens, &src).unwrap_err();
+
+        let diags = err.diagnostics();
+        let codes = diags.iter().map(|diag| diag.code).collect::<Vec<_>>();
+
+        assert_eq!(codes, ["E0103", "E0101", "E0103"]);
+
+        // messages are for user, not the debug text
+        assert_eq!(diags[0].message, "missing Operand for BOpExpr");
+        assert!(diags[1].message.ends_with(", found `ret`"));
+    }
+
+    #[test]
+    fn test_parse_call_args() -> Result<(), Box<dyn std::error::Error>> {
+        let src = SrcFileInfo::new(&"./examples/call_args.bath")?;
+        let tokens = tokenize(&src)?;
+        let tt = parse(tokens, &src)?;
+
+        // `add(zero(), (2))` and `zero()`
+        let mut args = vec![];
+        find_all(&tt, ST::CallArgs, &mut args);
+
+        let arg_cnts = args
+            .iter()
+            .map(|args| {
+                args.subs.iter().filter(|(st, _)| *st == ST::Expr).count()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(arg_cnts, [2, 0]);
+
+        // `(a + b)` and `(2)`, the expression is between the parens
+        let mut grouped = vec![];
+        find_all(&tt, ST::GroupedExpr, &mut grouped);
+
+        assert_eq!(grouped.len(), 2);
+        for tt in grouped {
+            let tys = tt.subs.iter().map(|(st, _)| *st).collect::<Vec<_>>();
+            assert_eq!(tys, [ST::lparen, ST::Expr, ST::rparen]);
+        }
+
+        Ok(())
+    }
 }
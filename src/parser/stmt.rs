@@ -27,7 +27,15 @@ impl Parser {
             subs.push((ST::Expr, SN::T(self.parse_expr()?)));
         }
 
-        subs.push((ST::semi, SN::E(self.expect_eat_semi_t(four)?)));
+        // leave it for recovery
+        if !self.peek1_t().check_name("semi") {
+            return Err(R::Expect {
+                expect: ST::semi,
+                four,
+                found: *self.peek1_t(),
+            });
+        }
+        subs.push((ST::semi, SN::E(self.unchecked_advance())));
 
         Ok(TT::new(subs))
     }
@@ -35,37 +43,48 @@ impl Parser {
     pub(crate) fn parse_stmts(&mut self) -> ParseResult2 {
         let mut subs = vec![];
 
-        loop {
-            if self.peek1_t().check_name("let") {
-                subs.push((ST::Stmt, SN::T(self.parse_stmt()?)));
-            } else {
-                let expr_sn = SN::T(self.parse_expr()?);
-                if self.peek1_t().check_name("semi") {
-                    let semi_sn = SN::E(self.unchecked_advance());
-                    let stmt_tt = TT::new(vec![
-                        (ST::Expr, expr_sn),
-                        (ST::semi, semi_sn),
-                    ]);
-                    subs.push((ST::Stmt, SN::T(stmt_tt)));
-                } else {
-                    if !self.peek1_t().check_name("rbrace") {
-                        return Err(R::Expect {
-                            expect: ST::semi,
-                            four: ST::Stmt,
-                            found: self.unchecked_advance(),
-                        });
-                    }
-
+        while !self.is_end()
+            && !self.peek1_t().check_name("rbrace")
+            && !self.peek1_t().check_name("fn")
+        {
+            match self.parse_stmt_or_tail_expr() {
+                Ok((ST::Expr, expr_sn)) => {
                     subs.push((ST::Expr, expr_sn));
                     break;
                 }
-            }
-
-            if self.peek1_t().check_name("rbrace") {
-                break;
+                Ok(sub) => subs.push(sub),
+                Err(reason) => {
+                    let skipped = self.recover(reason, true);
+                    subs.push((ST::Error, SN::T(skipped)));
+                }
             }
         }
 
         Ok(TT::new(subs))
     }
+
+    /// Stmt or the tail Expr (followed by `}`)
+    fn parse_stmt_or_tail_expr(&mut self) -> Result<(ST, SN), R> {
+        if self.peek1_t().check_name("let") {
+            return Ok((ST::Stmt, SN::T(self.parse_stmt()?)));
+        }
+
+        let expr_sn = SN::T(self.parse_expr()?);
+
+        if self.peek1_t().check_name("semi") {
+            let semi_sn = SN::E(self.unchecked_advance());
+            let stmt_tt =
+                TT::new(vec![(ST::Expr, expr_sn), (ST::semi, semi_sn)]);
+
+            Ok((ST::Stmt, SN::T(stmt_tt)))
+        } else if self.peek1_t().check_name("rbrace") {
+            Ok((ST::Expr, expr_sn))
+        } else {
+            Err(R::Expect {
+                expect: ST::semi,
+                four: ST::Stmt,
+                found: *self.peek1_t(),
+            })
+        }
+    }
 }
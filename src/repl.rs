@@ -0,0 +1,558 @@
+//! Interactive REPL
+//!
+//! Every input is compiled into a new module and added into one JIT session.
+//! Statements are wrapped into a snippet function, whose top level `let`
+//! bindings are turned into module level variables, so that they stay
+//! visible to the later inputs.
+
+use std::{
+    error::Error,
+    ffi::{c_void, CStr},
+    fs,
+    io::{self, BufRead, Write},
+    os::raw::{c_char, c_int},
+    path::PathBuf,
+};
+
+use inkwellkit::{execution_engine::ExecutionEngine, VMMod};
+use m6entry::KVEntry as Entry;
+use m6lexerkit::{str2sym, sym2str, SrcFileInfo, Symbol};
+
+use crate::{
+    ast_lowering::{
+        AMod, APriType, AType, AVal, ExtSymSet, Pass1Export, Pass2Export,
+        SemanticAnalyzerPass1, SemanticAnalyzerPass2,
+    },
+    codegen::{create_jit_engine, jit_config, CodeGen},
+    config::OptLv,
+    diagnostics::{report, ErrorFormat},
+    driver::RunCompiler,
+    dump::dump_mir,
+    lexer::tokenize,
+    name_mangling::signature,
+    parser::parse,
+};
+
+
+extern "C" {
+    fn fflush(stream: *mut c_void) -> c_int;
+}
+
+
+/// `CmdRes` of runtime
+#[repr(C)]
+struct CmdRes {
+    out: *const c_char,
+    err: *const c_char,
+    status: i32,
+}
+
+
+pub struct Repl<'ctx> {
+    /// Directory to save input snippets
+    dir: TempDir,
+    cnt: usize,
+    optlv: OptLv,
+    /// Session state: root scope bindings and module level variables
+    amod: AMod,
+    /// Core and all previous inputs
+    ess: ExtSymSet,
+    ee: ExecutionEngine<'ctx>,
+    vmmods: Vec<VMMod<'ctx>>,
+}
+
+
+/// Directory removed on drop, so that it's cleaned even if the REPL exits
+/// by an error
+struct TempDir(PathBuf);
+
+
+enum Input {
+    Stmts(String),
+    Items(String),
+    Type(String),
+    Mir(String),
+    Help,
+    Quit,
+    Empty,
+}
+
+
+/// Analyzed input
+struct Snippet {
+    src: SrcFileInfo,
+    amod: AMod,
+    ess: ExtSymSet,
+    /// Snippet function if input is wrapped
+    entry: Option<Symbol>,
+}
+
+
+const HELP: &str = "\
+<stmts/expr>     evaluate and print the value with its type
+fn ...           define function
+:type <expr>     print type of expression
+:mir <stmts>     print MIR of statements
+:quit            exit";
+
+
+
+impl<'ctx> Repl<'ctx> {
+    pub fn new(optlv: OptLv) -> Result<Self, Box<dyn Error>> {
+        let (core, (core_amod, core_ess)) = RunCompiler::boot_jit()?;
+
+        let (core_vmmod, _) = CodeGen::gen_vmmod(
+            None,
+            core_amod,
+            core_ess,
+            jit_config(optlv),
+        )?;
+
+        let vmmod = VMMod::new("repl");
+        let ee = create_jit_engine(&vmmod, optlv)?;
+
+        ee.add_module(&core_vmmod.module)
+            .map_err(|_| "Add core module into JIT failed")?;
+
+        let dir =
+            std::env::temp_dir().join(format!("bas-repl-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir: TempDir(dir),
+            cnt: 0,
+            optlv,
+            amod: AMod::init(str2sym("repl")),
+            ess: ExtSymSet { mods: vec![core] },
+            ee,
+            vmmods: vec![vmmod, core_vmmod],
+        })
+    }
+
+    pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+
+        loop {
+            let text = match read_input(&mut lines)? {
+                Some(text) => text,
+                None => break,
+            };
+
+            let res = match classify(text) {
+                Input::Stmts(text) => self.eval_stmts(&text),
+                Input::Items(text) => self.eval_items(&text),
+                Input::Type(text) => self.print_type(&text),
+                Input::Mir(text) => self.print_mir(&text),
+                Input::Help => {
+                    println!("{HELP}");
+                    Ok(())
+                }
+                Input::Quit => break,
+                Input::Empty => Ok(()),
+            };
+
+            if let Err(err) = res {
+                report(&*err, ErrorFormat::Human);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn eval_stmts(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let snippet = self.analyze(text, true)?;
+        let entry = snippet.entry.unwrap();
+        let ret = snippet.amod.afns.get(&entry).unwrap().ret.clone();
+
+        self.load(snippet)?;
+        self.call_and_print(entry, ret)
+    }
+
+    fn eval_items(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let snippet = self.analyze(text, false)?;
+
+        for afn in snippet.amod.afns.values() {
+            println!("{}", signature(afn.idt.value, &afn.params, &afn.ret));
+        }
+
+        self.load(snippet)
+    }
+
+    fn print_type(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let snippet = self.analyze(text, true)?;
+        let entry = snippet.entry.unwrap();
+
+        println!(
+            "{}",
+            snippet.amod.afns.get(&entry).unwrap().ret.ident_name()
+        );
+
+        Ok(())
+    }
+
+    fn print_mir(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let snippet = self.analyze(text, true)?;
+
+        print!("{}", dump_mir(&snippet.amod));
+
+        Ok(())
+    }
+
+    /// Analyze input against session state without changing it
+    fn analyze(
+        &mut self,
+        text: &str,
+        wrap: bool,
+    ) -> Result<Snippet, Box<dyn Error>> {
+        self.cnt += 1;
+
+        let entry = str2sym(&format!("__repl_{}", self.cnt));
+        let code = if wrap {
+            format!("@no_mangle\nfn {}() {{\n{}\n}}\n", sym2str(entry), text)
+        } else {
+            text.to_owned()
+        };
+
+        let path = self.dir.0.join(format!("repl_{}.bath", self.cnt));
+        fs::write(&path, code)?;
+
+        let src = SrcFileInfo::new(&path)?;
+        let tokens = tokenize(&src)?;
+        let tt = parse(tokens, &src)?;
+
+        let Pass1Export {
+            src,
+            tt2,
+            mut amod,
+            ess,
+        } = SemanticAnalyzerPass1::run(src, tt, self.ess.clone())?;
+
+        if wrap {
+            amod.afns.get_mut(&entry).unwrap().ret = AType::PH;
+        }
+
+        amod.scopes[0].explicit_bindings =
+            self.amod.scopes[0].explicit_bindings.clone();
+        amod.eglobals = self.amod.globals.clone();
+
+        let Pass2Export { src, mut amod, ess, .. } =
+            SemanticAnalyzerPass2::run(src, tt2, amod, ess)?;
+
+        if wrap {
+            globalize(&mut amod, entry);
+        }
+
+        Ok(Snippet {
+            src,
+            amod,
+            ess,
+            entry: wrap.then(|| entry),
+        })
+    }
+
+    /// Add snippet into JIT and merge it into session state
+    fn load(&mut self, snippet: Snippet) -> Result<(), Box<dyn Error>> {
+        let Snippet {
+            src,
+            amod,
+            ess,
+            entry,
+        } = snippet;
+
+        let mut bindings = vec![];
+
+        if let Some(entry) = entry {
+            let scope_idx = amod.fn_scope_idx(entry).unwrap();
+
+            for Entry(sym, (tagid, avar)) in
+                amod.scopes[scope_idx].explicit_bindings.iter()
+            {
+                if amod.globals.contains_key(&(*sym, *tagid)) {
+                    bindings.push(Entry(*sym, (*tagid, avar.clone())));
+                }
+            }
+        }
+
+        let globals = amod.globals.clone();
+        let exp = amod.export();
+
+        let (vmmod, _) = CodeGen::gen_vmmod(
+            Some(src),
+            amod,
+            ess,
+            jit_config(self.optlv),
+        )?;
+
+        self.ee
+            .add_module(&vmmod.module)
+            .map_err(|_| "Add snippet module into JIT failed")?;
+
+        self.vmmods.push(vmmod);
+        self.ess.mods.push(exp);
+        self.amod.globals.extend(globals);
+        self.amod.scopes[0].explicit_bindings.extend(bindings);
+
+        Ok(())
+    }
+
+    fn call_and_print(
+        &self,
+        entry: Symbol,
+        ret: AType,
+    ) -> Result<(), Box<dyn Error>> {
+        let name = sym2str(entry);
+
+        macro_rules! call {
+            ($ty:ty) => {
+                unsafe {
+                    let f = self
+                        .ee
+                        .get_function::<unsafe extern "C" fn() -> $ty>(&name)
+                        .map_err(|err| format!("{err:?}"))?;
+
+                    f.call()
+                }
+            };
+        }
+
+        let value = match ret {
+            AType::Pri(APriType::Int(1)) => {
+                format!("{}", call!(i8) != 0)
+            }
+            AType::Pri(APriType::Int(-1)) => format!("{}", call!(i8)),
+            AType::Pri(APriType::Int(4 | -4)) => format!("{}", call!(i32)),
+            AType::Pri(APriType::Int(8 | -8)) => format!("{}", call!(i64)),
+            AType::Pri(APriType::Float(_)) => format!("{}", call!(f64)),
+            AType::Pri(APriType::Ptr) => {
+                let s = call!(*const c_char);
+
+                if s.is_null() {
+                    "null".to_owned()
+                } else {
+                    unsafe {
+                        format!("{:?}", CStr::from_ptr(s).to_string_lossy())
+                    }
+                }
+            }
+            AType::Pri(APriType::OpaqueStruct(name))
+                if sym2str(name) == "CmdRes" =>
+            {
+                let res = unsafe { &*call!(*const CmdRes) };
+
+                // captured output is shown as it is
+                unsafe {
+                    print!("{}", CStr::from_ptr(res.out).to_string_lossy());
+                    eprint!("{}", CStr::from_ptr(res.err).to_string_lossy());
+                }
+
+                format!("status {}", res.status)
+            }
+            AType::Pri(..) | AType::Arr(..) => {
+                format!("{:?}", call!(*const c_void))
+            }
+            _ => {
+                call!(());
+                flush_c_stdout();
+
+                return Ok(());
+            }
+        };
+
+        flush_c_stdout();
+        println!("{value}: {}", ret.ident_name());
+
+        Ok(())
+    }
+}
+
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+
+pub fn run_repl(optlv: OptLv) -> Result<(), Box<dyn Error>> {
+    let mut repl = Repl::new(optlv)?;
+
+    println!("Bas REPL, :help for help");
+
+    repl.run()
+}
+
+
+/// Turn top level bindings of snippet function into module level variables
+fn globalize(amod: &mut AMod, entry: Symbol) {
+    let scope_idx = amod.fn_scope_idx(entry).unwrap();
+    let fn_alloc = amod.allocs.get_mut(&entry).unwrap();
+
+    for Entry(_, (_, avar)) in amod.scopes[scope_idx].explicit_bindings.iter()
+    {
+        if let AVal::Var(sym, tagid) = avar.val {
+            if let Some(ty) = fn_alloc.shift_remove(&(sym, tagid)) {
+                amod.globals.insert((sym, tagid), ty);
+            }
+        }
+    }
+
+    // diverged body
+    let afn = amod.afns.get_mut(&entry).unwrap();
+    if afn.ret == AType::PH {
+        afn.ret = AType::Void;
+    }
+}
+
+
+/// Read until brackets are balanced, return None if EOF
+fn read_input<B: BufRead>(
+    lines: &mut io::Lines<B>,
+) -> io::Result<Option<String>> {
+    let mut text = String::new();
+    let mut depth = 0isize;
+    // open quote of string continued to next line
+    let mut quote = None;
+
+    loop {
+        print!("{}", if text.is_empty() { "bas> " } else { "...  " });
+        io::stdout().flush()?;
+
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => {
+                return Ok(if text.is_empty() { None } else { Some(text) })
+            }
+        };
+
+        depth += bracket_depth(&line, &mut quote);
+
+        text.push_str(&line);
+        text.push('\n');
+
+        if depth <= 0 {
+            break Ok(Some(text));
+        }
+    }
+}
+
+
+/// Depth change of brackets in `line`, those in strings, commands and
+/// comments are skipped. `quote` is the closing char of the open one.
+fn bracket_depth(line: &str, quote: &mut Option<char>) -> isize {
+    let mut depth = 0;
+    let mut prev = ' ';
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if let Some(q) = *quote {
+            if c == '\\' {
+                chars.next();
+            } else if c == q {
+                *quote = None;
+            }
+            continue;
+        }
+
+        match c {
+            '"' | '\'' | '`' => *quote = Some(c),
+            '!' if chars.peek() == Some(&'(') => {
+                chars.next();
+                *quote = Some(')');
+            }
+            // comment, unless it ends a tag like `err#`
+            '#' if !(prev.is_alphanumeric() || prev == '_') => break,
+            '{' | '(' | '[' => depth += 1,
+            '}' | ')' | ']' => depth -= 1,
+            _ => (),
+        }
+
+        prev = c;
+    }
+
+    depth
+}
+
+
+fn classify(text: String) -> Input {
+    let trimmed = text.trim();
+
+    if trimmed.is_empty() {
+        return Input::Empty;
+    }
+
+    if let Some(cmd) = trimmed.strip_prefix(':') {
+        let (name, arg) = cmd.split_once(char::is_whitespace).unwrap_or((cmd, ""));
+        let arg = arg.trim().to_owned();
+
+        return match name {
+            "type" | "t" => Input::Type(arg),
+            "mir" => Input::Mir(arg),
+            "quit" | "q" => Input::Quit,
+            _ => Input::Help,
+        };
+    }
+
+    if trimmed.starts_with("fn ") || trimmed.starts_with('@') {
+        return Input::Items(text);
+    }
+
+    // complete the missing semicolon for let statement
+    if trimmed.starts_with("let ") && !trimmed.ends_with(';') {
+        return Input::Stmts(format!("{trimmed};"));
+    }
+
+    Input::Stmts(text)
+}
+
+
+fn flush_c_stdout() {
+    unsafe {
+        fflush(std::ptr::null_mut());
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, Cursor};
+
+    use super::{classify, read_input, Input};
+
+    #[test]
+    fn test_repl_classify() {
+        assert!(matches!(classify("fn f() {}".to_owned()), Input::Items(_)));
+        assert!(matches!(classify(":t 1 + 2".to_owned()), Input::Type(s) if s == "1 + 2"));
+        assert!(matches!(classify("let a = 1".to_owned()), Input::Stmts(s) if s == "let a = 1;"));
+        assert!(matches!(classify("  \n".to_owned()), Input::Empty));
+    }
+
+    #[test]
+    fn test_repl_read_input() {
+        let src = "\
+fn f() {
+    # comment with (
+    ret \"}\";
+}
+let s = \"a
+)\"
+err#!(echo don't [)
+{";
+        let mut lines = Cursor::new(src).lines();
+
+        let inputs = std::iter::from_fn(|| read_input(&mut lines).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            inputs,
+            [
+                "fn f() {\n    # comment with (\n    ret \"}\";\n}\n",
+                "let s = \"a\n)\"\n",
+                "err#!(echo don't [)\n",
+                // unbalanced at EOF
+                "{\n",
+            ]
+        );
+    }
+}
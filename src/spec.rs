@@ -35,7 +35,10 @@ Type:
   | <lbracket> <id> <rbracket>
 
 Attrs:
-  | <attr>*
+  | (<attr> [AttrArgs]?)*
+
+AttrArgs:
+  | <lparen> <id> (<comma> <id>)* <rparen>
 
 Stmts:
   | [Stmt]* [Expr]?
@@ -77,6 +80,8 @@ Expr:
   | [SideEffectExpr]
   | [PathExpr]
   | [ReturnExpr]
+  | [BreakExpr]
+  | [ContinueExpr]
   | [CmdExpr]
   | [FunCallExpr]
 
@@ -117,11 +122,20 @@ PathExprSeg:
 ReturnExpr:
   | <ret> [Expr]?
 
+BreakExpr:
+  | <break>
+
+ContinueExpr:
+  | <continue>
+
 CmdExpr:
-  | <cmd>
+  | <tag>? <cmd>
 
 FunCallExpr:
-  | [PathExpr] [GroupedExpr]
+  | [PathExpr] [CallArgs]
+
+CallArgs:
+  | <lparen> ([Expr] (<comma> [Expr])*)? <rparen>
 
 "#;
 